@@ -0,0 +1,376 @@
+use ::std::collections::HashMap;
+use ::std::time::{Duration, Instant};
+
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{Touch, TouchPhase};
+
+use crate::pointer::PointerEvent;
+
+/// Thresholds used by [`GestureRecognizer`].  Distances are in physical pixels.
+#[derive(Debug, Clone)]
+pub struct GestureConfig {
+    /// How far a pointer may wander and still count as a tap or long-press.
+    pub tap_slop: f64,
+    /// Longest press that still counts as a tap.
+    pub tap_timeout: Duration,
+    /// Longest gap between two taps that makes a double tap.
+    pub double_tap_timeout: Duration,
+    /// Largest distance between two taps that makes a double tap.
+    pub double_tap_slop: f64,
+    pub long_press_timeout: Duration,
+    /// Shortest travel distance for a swipe.
+    pub swipe_min_distance: f64,
+    /// A swipe released faster than this (pixels per second) is reported as a fling.
+    pub fling_min_velocity: f64,
+    /// Samples older than this are ignored when computing release velocity.
+    pub velocity_window: Duration,
+    /// Relative change in finger distance before a pinch is reported.
+    pub pinch_min_scale: f64,
+    /// Change in finger angle (radians) before a rotation is reported.
+    pub rotate_min_angle: f64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            tap_slop: 24.0,
+            tap_timeout: Duration::from_millis(300),
+            double_tap_timeout: Duration::from_millis(300),
+            double_tap_slop: 48.0,
+            long_press_timeout: Duration::from_millis(500),
+            swipe_min_distance: 64.0,
+            fling_min_velocity: 1000.0,
+            velocity_window: Duration::from_millis(100),
+            pinch_min_scale: 0.05,
+            rotate_min_angle: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gesture {
+    Tap { position: PhysicalPosition<f64> },
+    DoubleTap { position: PhysicalPosition<f64> },
+    LongPress { position: PhysicalPosition<f64> },
+    /// A single finger drag released below the fling velocity.
+    Swipe {
+        direction: SwipeDirection,
+        start: PhysicalPosition<f64>,
+        end: PhysicalPosition<f64>,
+        /// pixels per second
+        velocity: (f64, f64),
+    },
+    /// A single finger drag released at or above the fling velocity.
+    Fling {
+        direction: SwipeDirection,
+        start: PhysicalPosition<f64>,
+        end: PhysicalPosition<f64>,
+        /// pixels per second
+        velocity: (f64, f64),
+    },
+    /// Two finger pinch. `scale` is relative to the finger distance when the
+    /// second finger went down, `delta` is relative to the previous event.
+    Pinch { center: PhysicalPosition<f64>, scale: f64, delta: f64 },
+    /// Two finger rotation in radians, positive is clockwise on screen.
+    Rotate { center: PhysicalPosition<f64>, angle: f64, delta: f64 },
+}
+
+struct PointerTrack {
+    start: PhysicalPosition<f64>,
+    start_at: Instant,
+    current: PhysicalPosition<f64>,
+    samples: Vec<(Instant, PhysicalPosition<f64>)>,
+    moved_beyond_slop: bool,
+    long_pressed: bool,
+}
+
+struct TwoFingerState {
+    ids: [u64; 2],
+    initial_distance: f64,
+    initial_angle: f64,
+    last_scale: f64,
+    last_angle: f64,
+    pinching: bool,
+    rotating: bool,
+}
+
+/// Turns raw pointer down/move/up events into [`Gesture`]s.
+///
+/// Time is always passed in explicitly so a recorded or synthetic sequence
+/// produces the same gestures every run.  Long-press needs [`GestureRecognizer::poll`]
+/// to be called while a finger rests on the screen, see [`GestureRecognizer::next_deadline`].
+pub struct GestureRecognizer {
+    pub config: GestureConfig,
+    pointers: HashMap<u64, PointerTrack>,
+    two_finger: Option<TwoFingerState>,
+    /// Set once a second finger touches down; single finger gestures are
+    /// suppressed until every finger is lifted.
+    multi_touch: bool,
+    last_tap: Option<(Instant, PhysicalPosition<f64>)>,
+}
+
+fn distance(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+fn midpoint(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> PhysicalPosition<f64> {
+    PhysicalPosition::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
+}
+
+fn angle(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    (b.y - a.y).atan2(b.x - a.x)
+}
+
+/// Smallest signed difference between two angles.
+fn angle_delta(from: f64, to: f64) -> f64 {
+    let mut delta = to - from;
+    while delta > ::std::f64::consts::PI {
+        delta -= ::std::f64::consts::TAU;
+    }
+    while delta < -::std::f64::consts::PI {
+        delta += ::std::f64::consts::TAU;
+    }
+    delta
+}
+
+fn direction_of(dx: f64, dy: f64) -> SwipeDirection {
+    if dx.abs() >= dy.abs() {
+        if dx < 0.0 { SwipeDirection::Left } else { SwipeDirection::Right }
+    } else if dy < 0.0 {
+        SwipeDirection::Up
+    } else {
+        SwipeDirection::Down
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            pointers: HashMap::new(),
+            two_finger: None,
+            multi_touch: false,
+            last_tap: None,
+        }
+    }
+
+    /// Number of pointers currently down.
+    pub fn active_pointers(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub fn touch(&mut self, touch: &Touch, now: Instant) -> Vec<Gesture> {
+        self.pointer(touch.id, touch.phase, touch.location, now)
+    }
+
+    /// Feed a unified pointer event.  Mouse input takes part through its primary button.
+    pub fn pointer_event(&mut self, event: &PointerEvent, now: Instant) -> Vec<Gesture> {
+        match event.contact_phase() {
+            Some(phase) => self.pointer(event.id, phase, event.position, now),
+            None => self.poll(now),
+        }
+    }
+
+    pub fn pointer(&mut self, id: u64, phase: TouchPhase, location: PhysicalPosition<f64>, now: Instant) -> Vec<Gesture> {
+        let mut gestures = self.poll(now);
+        match phase {
+            TouchPhase::Started => self.pointer_down(id, location, now),
+            TouchPhase::Moved => self.pointer_moved(id, location, now, &mut gestures),
+            TouchPhase::Ended => self.pointer_up(id, location, now, &mut gestures),
+            TouchPhase::Cancelled => self.pointer_cancelled(id),
+        }
+        gestures
+    }
+
+    /// When a long-press could fire, if a finger is resting on the screen.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.multi_touch {
+            return None;
+        }
+        self.pointers.values()
+            .filter(|p| !p.moved_beyond_slop && !p.long_pressed)
+            .map(|p| p.start_at + self.config.long_press_timeout)
+            .min()
+    }
+
+    /// Emit time based gestures (long-press) that are due at `now`.
+    pub fn poll(&mut self, now: Instant) -> Vec<Gesture> {
+        let mut gestures = vec![];
+        if self.multi_touch {
+            return gestures;
+        }
+        for track in self.pointers.values_mut() {
+            if !track.moved_beyond_slop
+                && !track.long_pressed
+                && now.saturating_duration_since(track.start_at) >= self.config.long_press_timeout
+            {
+                track.long_pressed = true;
+                gestures.push(Gesture::LongPress { position: track.current });
+            }
+        }
+        gestures
+    }
+
+    fn pointer_down(&mut self, id: u64, location: PhysicalPosition<f64>, now: Instant) {
+        self.pointers.insert(id, PointerTrack {
+            start: location,
+            start_at: now,
+            current: location,
+            samples: vec![(now, location)],
+            moved_beyond_slop: false,
+            long_pressed: false,
+        });
+        if self.pointers.len() >= 2 {
+            self.multi_touch = true;
+        }
+        if self.pointers.len() == 2 {
+            self.begin_two_finger();
+        }
+    }
+
+    fn begin_two_finger(&mut self) {
+        let mut ids: Vec<u64> = self.pointers.keys().copied().collect();
+        ids.sort_unstable();
+        let a = self.pointers[&ids[0]].current;
+        let b = self.pointers[&ids[1]].current;
+        self.two_finger = Some(TwoFingerState {
+            ids: [ids[0], ids[1]],
+            initial_distance: distance(a, b).max(1.0),
+            initial_angle: angle(a, b),
+            last_scale: 1.0,
+            last_angle: 0.0,
+            pinching: false,
+            rotating: false,
+        });
+    }
+
+    fn pointer_moved(&mut self, id: u64, location: PhysicalPosition<f64>, now: Instant, gestures: &mut Vec<Gesture>) {
+        let tap_slop = self.config.tap_slop;
+        let window = self.config.velocity_window;
+        let track = match self.pointers.get_mut(&id) {
+            Some(track) => track,
+            None => return,
+        };
+        track.current = location;
+        track.samples.push((now, location));
+        // keep the newest sample older than the window as well, so a slow
+        // drag still has two points to measure between
+        let first_recent = track.samples.iter()
+            .position(|(at, _)| now.saturating_duration_since(*at) <= window)
+            .unwrap_or(track.samples.len() - 1);
+        track.samples.drain(..first_recent.saturating_sub(1));
+        if distance(track.start, location) > tap_slop {
+            track.moved_beyond_slop = true;
+        }
+
+        let two_finger = match self.two_finger.as_mut() {
+            Some(state) if state.ids.contains(&id) => state,
+            _ => return,
+        };
+        let a = self.pointers[&two_finger.ids[0]].current;
+        let b = self.pointers[&two_finger.ids[1]].current;
+        let center = midpoint(a, b);
+
+        let scale = distance(a, b) / two_finger.initial_distance;
+        if two_finger.pinching || (scale - 1.0).abs() >= self.config.pinch_min_scale {
+            two_finger.pinching = true;
+            gestures.push(Gesture::Pinch { center, scale, delta: scale / two_finger.last_scale });
+            two_finger.last_scale = scale;
+        }
+
+        let rotation = angle_delta(two_finger.initial_angle, angle(a, b));
+        if two_finger.rotating || rotation.abs() >= self.config.rotate_min_angle {
+            two_finger.rotating = true;
+            gestures.push(Gesture::Rotate { center, angle: rotation, delta: rotation - two_finger.last_angle });
+            two_finger.last_angle = rotation;
+        }
+    }
+
+    fn pointer_up(&mut self, id: u64, location: PhysicalPosition<f64>, now: Instant, gestures: &mut Vec<Gesture>) {
+        // make sure the release position takes part in the velocity estimate
+        self.pointer_moved(id, location, now, gestures);
+        let track = match self.pointers.remove(&id) {
+            Some(track) => track,
+            None => return,
+        };
+        if self.two_finger.as_ref().is_some_and(|state| state.ids.contains(&id)) {
+            self.two_finger = None;
+        }
+        if self.multi_touch {
+            if self.pointers.is_empty() {
+                self.multi_touch = false;
+            }
+            return;
+        }
+        if track.long_pressed {
+            return;
+        }
+
+        let held = now.saturating_duration_since(track.start_at);
+        if !track.moved_beyond_slop && held <= self.config.tap_timeout {
+            gestures.push(self.tap(location, now));
+            return;
+        }
+
+        let dx = location.x - track.start.x;
+        let dy = location.y - track.start.y;
+        if dx.hypot(dy) < self.config.swipe_min_distance {
+            return;
+        }
+        let velocity = Self::velocity(&track.samples);
+        let direction = direction_of(dx, dy);
+        if velocity.0.hypot(velocity.1) >= self.config.fling_min_velocity {
+            gestures.push(Gesture::Fling { direction, start: track.start, end: location, velocity });
+        } else {
+            gestures.push(Gesture::Swipe { direction, start: track.start, end: location, velocity });
+        }
+    }
+
+    fn pointer_cancelled(&mut self, id: u64) {
+        self.pointers.remove(&id);
+        if self.two_finger.as_ref().is_some_and(|state| state.ids.contains(&id)) {
+            self.two_finger = None;
+        }
+        if self.pointers.is_empty() {
+            self.multi_touch = false;
+        }
+    }
+
+    fn tap(&mut self, position: PhysicalPosition<f64>, now: Instant) -> Gesture {
+        if let Some((at, last)) = self.last_tap.take() {
+            if now.saturating_duration_since(at) <= self.config.double_tap_timeout
+                && distance(last, position) <= self.config.double_tap_slop
+            {
+                return Gesture::DoubleTap { position };
+            }
+        }
+        self.last_tap = Some((now, position));
+        Gesture::Tap { position }
+    }
+
+    fn velocity(samples: &[(Instant, PhysicalPosition<f64>)]) -> (f64, f64) {
+        let (first, last) = match (samples.first(), samples.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return (0.0, 0.0),
+        };
+        let dt = last.0.saturating_duration_since(first.0).as_secs_f64();
+        if dt <= 0.0 {
+            return (0.0, 0.0);
+        }
+        ((last.1.x - first.1.x) / dt, (last.1.y - first.1.y) / dt)
+    }
+}
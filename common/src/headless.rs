@@ -0,0 +1,157 @@
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+use ::std::sync::{Arc, Mutex};
+use ::std::time::{Duration, Instant};
+
+use ::winit::dpi::{PhysicalPosition, PhysicalSize};
+use ::winit::event::{DeviceId, ElementState, MouseButton, Touch, TouchPhase, WindowEvent};
+use ::winit::keyboard::PhysicalKey;
+
+use crate::window::AppWindow;
+
+/// App state that can be driven without a winit event loop.
+///
+/// `ApplicationHandler` callbacks need an `ActiveEventLoop`, which only a real
+/// event loop can provide.  The examples forward those callbacks to these
+/// methods so tests and input replay can call them directly.
+pub trait HeadlessApp {
+    fn resume_with_window(&mut self, window: Arc<dyn AppWindow>);
+    fn suspend(&mut self);
+    fn handle_window_event(&mut self, event: WindowEvent, now: Instant);
+    /// Keyboard events cannot be constructed outside of winit, so keys are fed separately.
+    fn key_input(&mut self, physical_key: PhysicalKey, state: ElementState, now: Instant);
+    fn exit_requested(&self) -> bool;
+
+    /// Fire time based input that is due at `now`.
+    fn advance_to(&mut self, _now: Instant) {}
+
+    /// Next time [`HeadlessApp::advance_to`] has something to do.
+    fn next_deadline(&self) -> Option<Instant> {
+        None
+    }
+}
+
+/// A window that only remembers its size and how often a redraw was requested.
+pub struct HeadlessWindow {
+    size: Mutex<PhysicalSize<u32>>,
+    redraw_requests: AtomicUsize,
+}
+
+impl HeadlessWindow {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            size: Mutex::new(PhysicalSize::new(width, height)),
+            redraw_requests: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_inner_size(&self, size: PhysicalSize<u32>) {
+        *self.size.lock().unwrap() = size;
+    }
+
+    pub fn redraw_requests(&self) -> usize {
+        self.redraw_requests.load(Ordering::SeqCst)
+    }
+}
+
+impl AppWindow for HeadlessWindow {
+    fn inner_size(&self) -> PhysicalSize<u32> {
+        *self.size.lock().unwrap()
+    }
+
+    fn request_redraw(&self) {
+        self.redraw_requests.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Drives a [`HeadlessApp`] with synthetic events on a manual clock.
+pub struct Harness<A: HeadlessApp> {
+    pub app: A,
+    pub window: Arc<HeadlessWindow>,
+    now: Instant,
+}
+
+impl <A: HeadlessApp>Harness<A> {
+    pub fn new(app: A, width: u32, height: u32) -> Self {
+        Self {
+            app,
+            window: Arc::new(HeadlessWindow::new(width, height)),
+            now: Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    pub fn resume(&mut self) -> &mut Self {
+        self.app.resume_with_window(self.window.clone());
+        self
+    }
+
+    pub fn suspend(&mut self) -> &mut Self {
+        self.app.suspend();
+        self
+    }
+
+    /// Move the clock forward, firing anything that became due on the way.
+    pub fn advance(&mut self, by: Duration) -> &mut Self {
+        let until = self.now + by;
+        while let Some(deadline) = self.app.next_deadline().filter(|deadline| *deadline <= until) {
+            self.now = self.now.max(deadline);
+            self.app.advance_to(self.now);
+        }
+        self.now = until;
+        self.app.advance_to(self.now);
+        self
+    }
+
+    pub fn event(&mut self, event: WindowEvent) -> &mut Self {
+        self.app.handle_window_event(event, self.now);
+        self
+    }
+
+    pub fn key(&mut self, physical_key: PhysicalKey, state: ElementState) -> &mut Self {
+        self.app.key_input(physical_key, state, self.now);
+        self
+    }
+
+    pub fn touch(&mut self, id: u64, phase: TouchPhase, x: f64, y: f64) -> &mut Self {
+        self.event(WindowEvent::Touch(Touch {
+            device_id: DeviceId::dummy(),
+            phase,
+            location: PhysicalPosition::new(x, y),
+            force: None,
+            id,
+        }))
+    }
+
+    /// A short single finger tap.
+    pub fn tap(&mut self, x: f64, y: f64) -> &mut Self {
+        self.touch(0, TouchPhase::Started, x, y)
+            .advance(Duration::from_millis(50))
+            .touch(0, TouchPhase::Ended, x, y)
+    }
+
+    pub fn mouse_move(&mut self, x: f64, y: f64) -> &mut Self {
+        self.event(WindowEvent::CursorMoved { device_id: DeviceId::dummy(), position: PhysicalPosition::new(x, y) })
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, state: ElementState) -> &mut Self {
+        self.event(WindowEvent::MouseInput { device_id: DeviceId::dummy(), state, button })
+    }
+
+    /// Change the window size and deliver the matching `Resized` event.
+    pub fn resize(&mut self, width: u32, height: u32) -> &mut Self {
+        let size = PhysicalSize::new(width, height);
+        self.window.set_inner_size(size);
+        self.event(WindowEvent::Resized(size))
+    }
+
+    pub fn redraw(&mut self) -> &mut Self {
+        self.event(WindowEvent::RedrawRequested)
+    }
+
+    pub fn close(&mut self) -> &mut Self {
+        self.event(WindowEvent::CloseRequested)
+    }
+}
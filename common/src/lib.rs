@@ -0,0 +1,9 @@
+//! Pieces shared by the example crates that do not depend on a specific
+//! rendering backend.
+pub mod gesture;
+pub mod headless;
+pub mod navigation;
+pub mod pointer;
+pub mod record;
+pub mod timing;
+pub mod window;
@@ -0,0 +1,140 @@
+use ::std::time::{Duration, Instant};
+
+use ::log::info;
+
+/// Android's `AKEYCODE_BACK`, the system back key, as winit reports it in
+/// `NativeKeyCode::Android`.  Spelled out rather than taken from
+/// android-activity so recordings made on a device replay the same on desktop.
+pub const ANDROID_KEYCODE_BACK: u32 = 4;
+
+/// android-activity's `Button::Back` (`AMOTION_EVENT_BUTTON_BACK`), handled
+/// as a predictive back gesture.
+pub const ANDROID_BUTTON_BACK: u32 = 1 << 3;
+
+/// What happened after a back request was applied to a [`NavigationStack`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackOutcome<S> {
+    /// The top screen was removed and is handed back to the caller.
+    Popped(S),
+    /// The root screen is showing and exit confirmation is enabled.
+    /// Show a "press back again to exit" hint.
+    ConfirmExit,
+    /// The root screen is showing and the app should exit.
+    Exit,
+}
+
+/// Predictive back gesture stages.
+///
+/// NativeActivity does not forward `OnBackAnimationCallback` progress, so on
+/// most devices only `Started` and `Committed` are seen (from the back button
+/// press and release).  `Progressed` is accepted for platforms that do report it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackGesture {
+    Started,
+    /// Progress of the swipe, from 0.0 to 1.0
+    Progressed(f32),
+    Cancelled,
+    Committed,
+}
+
+/// A stack of screens with a root that is never popped.
+///
+/// Back pops the top screen.  On the root screen back either exits
+/// immediately or, when exit confirmation is enabled, asks for a second
+/// back press within the confirmation window.
+pub struct NavigationStack<S> {
+    screens: Vec<S>,
+    exit_confirmation: Option<Duration>,
+    last_back_at_root: Option<Instant>,
+    back_progress: Option<f32>,
+}
+
+impl <S: ::std::fmt::Debug>NavigationStack<S> {
+    pub fn new(root: S) -> Self {
+        Self {
+            screens: vec![root],
+            exit_confirmation: None,
+            last_back_at_root: None,
+            back_progress: None,
+        }
+    }
+
+    /// Require a second back press within `window` before exiting from the root screen.
+    pub fn with_exit_confirmation(mut self, window: Duration) -> Self {
+        self.exit_confirmation = Some(window);
+        self
+    }
+
+    pub fn push(&mut self, screen: S) {
+        info!("navigation push {:?}", screen);
+        self.screens.push(screen);
+        self.last_back_at_root = None;
+    }
+
+    pub fn current(&self) -> &S {
+        self.screens.last().expect("navigation stack always has a root screen")
+    }
+
+    pub fn depth(&self) -> usize {
+        self.screens.len()
+    }
+
+    pub fn is_at_root(&self) -> bool {
+        self.screens.len() == 1
+    }
+
+    /// Progress of an in-flight predictive back gesture, if any.
+    pub fn back_progress(&self) -> Option<f32> {
+        self.back_progress
+    }
+
+    pub fn back(&mut self) -> BackOutcome<S> {
+        self.back_at(Instant::now())
+    }
+
+    /// Same as [`NavigationStack::back`] but with an explicit clock, so the
+    /// confirmation window can be driven deterministically.
+    pub fn back_at(&mut self, now: Instant) -> BackOutcome<S> {
+        self.back_progress = None;
+        if !self.is_at_root() {
+            let screen = self.screens.pop().expect("stack deeper than root");
+            info!("navigation pop {:?}", screen);
+            return BackOutcome::Popped(screen);
+        }
+        let window = match self.exit_confirmation {
+            Some(window) => window,
+            None => return BackOutcome::Exit,
+        };
+        match self.last_back_at_root {
+            Some(at) if now.saturating_duration_since(at) <= window => BackOutcome::Exit,
+            _ => {
+                self.last_back_at_root = Some(now);
+                BackOutcome::ConfirmExit
+            }
+        }
+    }
+
+    /// Feed a predictive back gesture stage.  Only `Committed` performs
+    /// navigation, the other stages just track progress.
+    pub fn back_gesture(&mut self, gesture: BackGesture) -> Option<BackOutcome<S>> {
+        self.back_gesture_at(gesture, Instant::now())
+    }
+
+    pub fn back_gesture_at(&mut self, gesture: BackGesture, now: Instant) -> Option<BackOutcome<S>> {
+        match gesture {
+            BackGesture::Started => {
+                self.back_progress = Some(0.0);
+                None
+            },
+            BackGesture::Progressed(progress) => {
+                self.back_progress = Some(progress.clamp(0.0, 1.0));
+                None
+            },
+            BackGesture::Cancelled => {
+                self.back_progress = None;
+                None
+            },
+            BackGesture::Committed => Some(self.back_at(now)),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use ::std::collections::HashMap;
+
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{ElementState, Force, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
+
+/// Pointer id used for the mouse.  Touch and pen pointers use the platform finger id.
+pub const MOUSE_POINTER_ID: u64 = u64::MAX;
+
+/// Pixels per line for wheels that report [`MouseScrollDelta::LineDelta`].
+pub const LINE_HEIGHT: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerKind {
+    Touch,
+    Mouse,
+    /// winit only reports a stylus through the altitude angle of a calibrated
+    /// force, which only iOS fills in.  On Android winit drops the tool type
+    /// of the motion event, so a stylus shows up as [`PointerKind::Touch`],
+    /// as it does on every other platform.
+    Pen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    /// Left mouse button, or the contact of a finger or pen.
+    Primary,
+    Secondary,
+    Middle,
+    Back,
+    Forward,
+    Other(u16),
+}
+
+impl PointerButton {
+    fn mask(&self) -> u32 {
+        match self {
+            PointerButton::Primary => 1 << 0,
+            PointerButton::Secondary => 1 << 1,
+            PointerButton::Middle => 1 << 2,
+            PointerButton::Back => 1 << 3,
+            PointerButton::Forward => 1 << 4,
+            PointerButton::Other(n) => 1 << (5 + (*n as u32).min(26)),
+        }
+    }
+}
+
+impl From<MouseButton> for PointerButton {
+    fn from(button: MouseButton) -> Self {
+        match button {
+            MouseButton::Left => PointerButton::Primary,
+            MouseButton::Right => PointerButton::Secondary,
+            MouseButton::Middle => PointerButton::Middle,
+            MouseButton::Back => PointerButton::Back,
+            MouseButton::Forward => PointerButton::Forward,
+            MouseButton::Other(n) => PointerButton::Other(n),
+        }
+    }
+}
+
+/// Set of buttons held down on a pointer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PointerButtons(u32);
+
+impl PointerButtons {
+    pub fn contains(&self, button: PointerButton) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn set(&mut self, button: PointerButton, pressed: bool) {
+        if pressed {
+            self.0 |= button.mask();
+        } else {
+            self.0 &= !button.mask();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerAction {
+    Down(PointerButton),
+    Up(PointerButton),
+    Moved,
+    Cancelled,
+    /// The mouse left the window.
+    Left,
+    /// Scroll amount in physical pixels.
+    Wheel { delta_x: f64, delta_y: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointerEvent {
+    pub id: u64,
+    pub kind: PointerKind,
+    pub action: PointerAction,
+    /// Physical pixels, relative to the window.
+    pub position: PhysicalPosition<f64>,
+    /// 0.0 to 1.0.  Devices without pressure report 1.0 while in contact.
+    pub pressure: f64,
+    /// Buttons held after this event was applied.
+    pub buttons: PointerButtons,
+}
+
+impl PointerEvent {
+    /// Touch phase of this event for the primary contact, used to drive
+    /// touch oriented consumers like the gesture recognizer with mouse input too.
+    pub fn contact_phase(&self) -> Option<TouchPhase> {
+        match self.action {
+            PointerAction::Down(PointerButton::Primary) => Some(TouchPhase::Started),
+            PointerAction::Up(PointerButton::Primary) => Some(TouchPhase::Ended),
+            PointerAction::Moved if self.buttons.contains(PointerButton::Primary) => Some(TouchPhase::Moved),
+            PointerAction::Cancelled => Some(TouchPhase::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+struct PointerState {
+    kind: PointerKind,
+    position: PhysicalPosition<f64>,
+    buttons: PointerButtons,
+}
+
+/// Merges touch, mouse, pen and wheel window events into one stream of [`PointerEvent`]s.
+#[derive(Default)]
+pub struct PointerTracker {
+    pointers: HashMap<u64, PointerState>,
+}
+
+fn touch_pressure(force: Option<Force>) -> f64 {
+    force.map(|f| f.normalized()).unwrap_or(1.0)
+}
+
+/// Pen only on iOS, see [`PointerKind::Pen`].
+fn touch_kind(force: Option<Force>) -> PointerKind {
+    match force {
+        Some(Force::Calibrated { altitude_angle: Some(_), .. }) => PointerKind::Pen,
+        _ => PointerKind::Touch,
+    }
+}
+
+impl PointerTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Last known position of a pointer.
+    pub fn position(&self, id: u64) -> Option<PhysicalPosition<f64>> {
+        self.pointers.get(&id).map(|p| p.position)
+    }
+
+    /// Ids of pointers with at least one button (or contact) held.
+    pub fn pressed(&self) -> impl Iterator<Item = u64> + '_ {
+        self.pointers.iter()
+            .filter(|(_, p)| !p.buttons.is_empty())
+            .map(|(id, _)| *id)
+    }
+
+    /// Translate a window event, returns `None` for events that are not pointer input.
+    pub fn window_event(&mut self, event: &WindowEvent) -> Option<PointerEvent> {
+        match event {
+            WindowEvent::Touch(touch) => {
+                let kind = touch_kind(touch.force);
+                let state = self.pointers.entry(touch.id).or_insert(PointerState {
+                    kind,
+                    position: touch.location,
+                    buttons: PointerButtons::default(),
+                });
+                state.position = touch.location;
+                let action = match touch.phase {
+                    TouchPhase::Started => {
+                        state.buttons.set(PointerButton::Primary, true);
+                        PointerAction::Down(PointerButton::Primary)
+                    },
+                    TouchPhase::Moved => PointerAction::Moved,
+                    TouchPhase::Ended => {
+                        state.buttons.set(PointerButton::Primary, false);
+                        PointerAction::Up(PointerButton::Primary)
+                    },
+                    TouchPhase::Cancelled => {
+                        state.buttons = PointerButtons::default();
+                        PointerAction::Cancelled
+                    },
+                };
+                let pointer = PointerEvent {
+                    id: touch.id,
+                    kind,
+                    action,
+                    position: touch.location,
+                    pressure: if action == PointerAction::Up(PointerButton::Primary) { 0.0 } else { touch_pressure(touch.force) },
+                    buttons: state.buttons,
+                };
+                if matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled) {
+                    self.pointers.remove(&touch.id);
+                }
+                Some(pointer)
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let mouse = self.mouse();
+                mouse.position = *position;
+                Some(self.mouse_event(PointerAction::Moved))
+            },
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = PointerButton::from(*button);
+                let pressed = *state == ElementState::Pressed;
+                self.mouse().buttons.set(button, pressed);
+                let action = if pressed { PointerAction::Down(button) } else { PointerAction::Up(button) };
+                Some(self.mouse_event(action))
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (delta_x, delta_y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (*x as f64 * LINE_HEIGHT, *y as f64 * LINE_HEIGHT),
+                    MouseScrollDelta::PixelDelta(position) => (position.x, position.y),
+                };
+                Some(self.mouse_event(PointerAction::Wheel { delta_x, delta_y }))
+            },
+            WindowEvent::CursorLeft { .. } => {
+                let event = self.mouse_event(PointerAction::Left);
+                self.pointers.remove(&MOUSE_POINTER_ID);
+                Some(event)
+            },
+            _ => None,
+        }
+    }
+
+    fn mouse(&mut self) -> &mut PointerState {
+        self.pointers.entry(MOUSE_POINTER_ID).or_insert(PointerState {
+            kind: PointerKind::Mouse,
+            position: PhysicalPosition::new(0.0, 0.0),
+            buttons: PointerButtons::default(),
+        })
+    }
+
+    fn mouse_event(&mut self, action: PointerAction) -> PointerEvent {
+        let mouse = self.mouse();
+        PointerEvent {
+            id: MOUSE_POINTER_ID,
+            kind: mouse.kind,
+            action,
+            position: mouse.position,
+            pressure: if mouse.buttons.is_empty() { 0.0 } else { 1.0 },
+            buttons: mouse.buttons,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use ::std::fs::File;
+use ::std::io::{self, BufRead, BufReader, BufWriter, Write};
+use ::std::path::Path;
+use ::std::time::{Duration, Instant};
+
+use ::serde::{Deserialize, Serialize};
+use ::winit::dpi::{PhysicalPosition, PhysicalSize};
+use ::winit::event::{DeviceId, ElementState, Force, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent};
+use ::winit::keyboard::PhysicalKey;
+
+use crate::headless::HeadlessApp;
+
+/// Serializable copy of the parts of a [`WindowEvent`] the examples react to.
+///
+/// `WindowEvent` itself cannot be serialized (and keyboard events cannot be
+/// constructed outside of winit), so recordings store this instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Resized(PhysicalSize<u32>),
+    CloseRequested,
+    Focused(bool),
+    Occluded(bool),
+    ScaleFactorChanged(f64),
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        location: PhysicalPosition<f64>,
+        force: Option<RecordedForce>,
+    },
+    CursorMoved(PhysicalPosition<f64>),
+    CursorEntered,
+    CursorLeft,
+    MouseInput { state: ElementState, button: MouseButton },
+    MouseWheel { delta: MouseScrollDelta, phase: TouchPhase },
+    Keyboard { physical_key: PhysicalKey, state: ElementState, repeat: bool },
+    RedrawRequested,
+    /// Anything else, kept as its debug output for reference.  Not replayed.
+    Unsupported(String),
+}
+
+impl RecordedEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Self {
+        match event {
+            WindowEvent::Resized(size) => RecordedEvent::Resized(*size),
+            WindowEvent::CloseRequested => RecordedEvent::CloseRequested,
+            WindowEvent::Focused(focused) => RecordedEvent::Focused(*focused),
+            WindowEvent::Occluded(occluded) => RecordedEvent::Occluded(*occluded),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => RecordedEvent::ScaleFactorChanged(*scale_factor),
+            WindowEvent::Touch(touch) => RecordedEvent::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                location: touch.location,
+                force: touch.force.map(RecordedForce::from),
+            },
+            WindowEvent::CursorMoved { position, .. } => RecordedEvent::CursorMoved(*position),
+            WindowEvent::CursorEntered { .. } => RecordedEvent::CursorEntered,
+            WindowEvent::CursorLeft { .. } => RecordedEvent::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => RecordedEvent::MouseInput { state: *state, button: *button },
+            WindowEvent::MouseWheel { delta, phase, .. } => RecordedEvent::MouseWheel { delta: *delta, phase: *phase },
+            WindowEvent::KeyboardInput { event, .. } => RecordedEvent::Keyboard {
+                physical_key: event.physical_key,
+                state: event.state,
+                repeat: event.repeat,
+            },
+            WindowEvent::RedrawRequested => RecordedEvent::RedrawRequested,
+            other => RecordedEvent::Unsupported(format!("{:?}", other)),
+        }
+    }
+
+    /// Rebuild the window event.  `None` for keyboard input, scale factor
+    /// changes and unsupported events, which winit does not let us construct.
+    pub fn to_window_event(&self) -> Option<WindowEvent> {
+        let device_id = DeviceId::dummy();
+        let event = match self {
+            RecordedEvent::Resized(size) => WindowEvent::Resized(*size),
+            RecordedEvent::CloseRequested => WindowEvent::CloseRequested,
+            RecordedEvent::Focused(focused) => WindowEvent::Focused(*focused),
+            RecordedEvent::Occluded(occluded) => WindowEvent::Occluded(*occluded),
+            RecordedEvent::Touch { id, phase, location, force } => WindowEvent::Touch(Touch {
+                device_id,
+                phase: *phase,
+                location: *location,
+                force: force.map(Force::from),
+                id: *id,
+            }),
+            RecordedEvent::CursorMoved(position) => WindowEvent::CursorMoved { device_id, position: *position },
+            RecordedEvent::CursorEntered => WindowEvent::CursorEntered { device_id },
+            RecordedEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+            RecordedEvent::MouseInput { state, button } => WindowEvent::MouseInput { device_id, state: *state, button: *button },
+            RecordedEvent::MouseWheel { delta, phase } => WindowEvent::MouseWheel { device_id, delta: *delta, phase: *phase },
+            RecordedEvent::RedrawRequested => WindowEvent::RedrawRequested,
+            RecordedEvent::ScaleFactorChanged(_)
+            | RecordedEvent::Keyboard { .. }
+            | RecordedEvent::Unsupported(_) => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Serializable copy of [`Force`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordedForce {
+    Calibrated { force: f64, max_possible_force: f64, altitude_angle: Option<f64> },
+    Normalized(f64),
+}
+
+impl From<Force> for RecordedForce {
+    fn from(force: Force) -> Self {
+        match force {
+            Force::Calibrated { force, max_possible_force, altitude_angle } =>
+                RecordedForce::Calibrated { force, max_possible_force, altitude_angle },
+            Force::Normalized(force) => RecordedForce::Normalized(force),
+        }
+    }
+}
+
+impl From<RecordedForce> for Force {
+    fn from(force: RecordedForce) -> Self {
+        match force {
+            RecordedForce::Calibrated { force, max_possible_force, altitude_angle } =>
+                Force::Calibrated { force, max_possible_force, altitude_angle },
+            RecordedForce::Normalized(force) => Force::Normalized(force),
+        }
+    }
+}
+
+/// One line of a recording: an event and when it arrived, relative to the start of the recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub at: Duration,
+    pub event: RecordedEvent,
+}
+
+/// Writes window events to a JSON lines file.
+///
+/// Every line is flushed as it is written, Android may kill the process
+/// without running destructors.
+pub struct Recorder {
+    start: Instant,
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            start: Instant::now(),
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, event: &WindowEvent) -> io::Result<()> {
+        self.record_at(event, Instant::now())
+    }
+
+    pub fn record_at(&mut self, event: &WindowEvent, now: Instant) -> io::Result<()> {
+        self.record_event(RecordedEvent::from_window_event(event), now)
+    }
+
+    /// Write an already converted event, e.g. keyboard input, whose
+    /// `WindowEvent` cannot be built outside of winit.
+    pub fn record_event(&mut self, event: RecordedEvent, now: Instant) -> io::Result<()> {
+        let line = TimedEvent {
+            at: now.saturating_duration_since(self.start),
+            event,
+        };
+        ::serde_json::to_writer(&mut self.out, &line)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+}
+
+/// Read a recording made by [`Recorder`].
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<TimedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(::serde_json::from_str(&line)?);
+    }
+    Ok(events)
+}
+
+/// Feed a recording through `app` without a window, using the recorded
+/// timestamps so time based input (long-press, double back) comes out the same.
+pub fn replay<A: HeadlessApp>(app: &mut A, recording: &[TimedEvent]) {
+    let start = Instant::now();
+    for timed in recording {
+        let now = start + timed.at;
+        // time based input that fired between two recorded events
+        while let Some(deadline) = app.next_deadline().filter(|deadline| *deadline <= now) {
+            app.advance_to(deadline);
+        }
+        match (&timed.event, timed.event.to_window_event()) {
+            (_, Some(event)) => app.handle_window_event(event, now),
+            (RecordedEvent::Keyboard { physical_key, state, .. }, None) => {
+                app.advance_to(now);
+                app.key_input(*physical_key, *state, now);
+            },
+            (event, None) => ::log::info!("replay skipped {:?}", event),
+        }
+        if app.exit_requested() {
+            ::log::info!("replay stopped, app requested exit");
+            break;
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use ::std::collections::VecDeque;
+use ::std::fmt;
+use ::std::time::{Duration, Instant};
+
+/// The last `capacity` samples of a duration, also counted in fixed width
+/// buckets.  The last bucket collects everything longer than the others cover.
+#[derive(Debug, Clone)]
+pub struct RollingHistogram {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+    bucket_width: Duration,
+    buckets: Vec<u32>,
+    sum: Duration,
+}
+
+impl RollingHistogram {
+    pub fn new(capacity: usize, bucket_width: Duration, bucket_count: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            bucket_width,
+            buckets: vec![0; bucket_count.max(1)],
+            sum: Duration::ZERO,
+        }
+    }
+
+    /// Add a sample, forgetting the oldest one when full.
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() == self.capacity {
+            if let Some(oldest) = self.samples.pop_front() {
+                let bucket = self.bucket(oldest);
+                self.buckets[bucket] -= 1;
+                self.sum -= oldest;
+            }
+        }
+        let bucket = self.bucket(sample);
+        self.buckets[bucket] += 1;
+        self.sum += sample;
+        self.samples.push_back(sample);
+    }
+
+    fn bucket(&self, sample: Duration) -> usize {
+        let index = sample.as_nanos() / self.bucket_width.as_nanos().max(1);
+        (index as usize).min(self.buckets.len() - 1)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.buckets.iter_mut().for_each(|count| *count = 0);
+        self.sum = Duration::ZERO;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Oldest first.
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = Duration> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn bucket_width(&self) -> Duration {
+        self.bucket_width
+    }
+
+    /// Sample count per bucket, bucket `i` covers `i * bucket_width` up to `(i + 1) * bucket_width`.
+    pub fn buckets(&self) -> &[u32] {
+        &self.buckets
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (!self.is_empty()).then(|| self.sum / self.samples.len() as u32)
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    /// The `quantile` (0.0 to 1.0) read from the buckets, so it is rounded up
+    /// to the end of a bucket.  Never more than [`RollingHistogram::max`].
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let max = self.max()?;
+        let rank = ((quantile.clamp(0.0, 1.0) * self.samples.len() as f64).ceil() as u32).max(1);
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                if index == self.buckets.len() - 1 {
+                    return Some(max);
+                }
+                return Some((self.bucket_width * (index as u32 + 1)).min(max));
+            }
+        }
+        Some(max)
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        Some(Summary {
+            mean: self.mean()?,
+            p50: self.percentile(0.5)?,
+            p95: self.percentile(0.95)?,
+            max: self.max()?,
+        })
+    }
+}
+
+/// What a [`RollingHistogram`] holds, in short.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(f, "mean {:.2}ms p50 {:.2}ms p95 {:.2}ms max {:.2}ms", ms(self.mean), ms(self.p50), ms(self.p95), ms(self.max))
+    }
+}
+
+/// Per frame timings.
+///
+/// * `cpu` - time spent handling a redraw, from `RedrawRequested` until the frame is submitted
+/// * `present_interval` - time between two presented frames
+/// * `gpu` - time the GPU spent on a frame, when the device can measure it
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    pub cpu: RollingHistogram,
+    pub present_interval: RollingHistogram,
+    pub gpu: RollingHistogram,
+    /// Frames presented since the stats were created.
+    pub frames: u64,
+    last_present: Option<Instant>,
+    log_interval: Option<Duration>,
+    last_log: Option<Instant>,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameStats {
+    /// Four seconds at 60 frames per second, in 1ms buckets up to 50ms.
+    pub fn new() -> Self {
+        Self::with_capacity(240)
+    }
+
+    /// Keep the last `frames` samples of each timing.
+    pub fn with_capacity(frames: usize) -> Self {
+        let histogram = || RollingHistogram::new(frames, Duration::from_millis(1), 50);
+        Self {
+            cpu: histogram(),
+            present_interval: histogram(),
+            gpu: histogram(),
+            frames: 0,
+            last_present: None,
+            log_interval: None,
+            last_log: None,
+        }
+    }
+
+    /// Have [`FrameStats::log_due`] return a summary every `interval`.
+    pub fn with_log_interval(mut self, interval: Duration) -> Self {
+        self.log_interval = Some(interval);
+        self
+    }
+
+    pub fn record_cpu(&mut self, duration: Duration) {
+        self.cpu.record(duration);
+    }
+
+    pub fn record_gpu(&mut self, duration: Duration) {
+        self.gpu.record(duration);
+    }
+
+    /// A frame was presented at `now`.
+    pub fn record_present(&mut self, now: Instant) {
+        self.frames += 1;
+        if let Some(last) = self.last_present {
+            self.present_interval.record(now.saturating_duration_since(last));
+        }
+        self.last_present = Some(now);
+    }
+
+    /// Nothing is presented for a while, e.g. the app is suspended.  The
+    /// pause does not count as a present interval.
+    pub fn pause(&mut self) {
+        self.last_present = None;
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        FrameSummary {
+            frames: self.frames,
+            cpu: self.cpu.summary(),
+            present_interval: self.present_interval.summary(),
+            gpu: self.gpu.summary(),
+        }
+    }
+
+    /// A summary once per log interval, `None` in between and without an interval.
+    pub fn log_due(&mut self, now: Instant) -> Option<FrameSummary> {
+        let interval = self.log_interval?;
+        match self.last_log {
+            Some(last) if now.saturating_duration_since(last) < interval => None,
+            Some(_) => {
+                self.last_log = Some(now);
+                Some(self.summary())
+            },
+            None => {
+                self.last_log = Some(now);
+                None
+            },
+        }
+    }
+}
+
+/// [`FrameStats::summary`], printed as one log line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSummary {
+    pub frames: u64,
+    pub cpu: Option<Summary>,
+    pub present_interval: Option<Summary>,
+    pub gpu: Option<Summary>,
+}
+
+impl fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} frames", self.frames)?;
+        for (name, summary) in [("cpu", &self.cpu), ("present", &self.present_interval), ("gpu", &self.gpu)] {
+            if let Some(summary) = summary {
+                write!(f, ", {} {}", name, summary)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+use ::winit::dpi::PhysicalSize;
+
+/// The parts of a window the examples use, so app state can run against a
+/// [`crate::headless::HeadlessWindow`] in tests.
+pub trait AppWindow {
+    fn inner_size(&self) -> PhysicalSize<u32>;
+    fn request_redraw(&self);
+}
+
+impl AppWindow for ::winit::window::Window {
+    fn inner_size(&self) -> PhysicalSize<u32> {
+        ::winit::window::Window::inner_size(self)
+    }
+
+    fn request_redraw(&self) {
+        ::winit::window::Window::request_redraw(self)
+    }
+}
@@ -0,0 +1,146 @@
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_common::gesture::{Gesture, GestureRecognizer, SwipeDirection};
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{DeviceId, Touch, TouchPhase};
+
+fn touch(id: u64, phase: TouchPhase, x: f64, y: f64) -> Touch {
+    Touch {
+        device_id: DeviceId::dummy(),
+        phase,
+        location: PhysicalPosition::new(x, y),
+        force: None,
+        id,
+    }
+}
+
+/// Feed `(ms offset, touch)` pairs and collect every gesture produced.
+fn run(recognizer: &mut GestureRecognizer, start: Instant, events: &[(u64, Touch)]) -> Vec<Gesture> {
+    events.iter()
+        .flat_map(|(ms, t)| recognizer.touch(t, start + Duration::from_millis(*ms)))
+        .collect()
+}
+
+#[test]
+fn tap_then_double_tap() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (50, touch(0, TouchPhase::Ended, 102.0, 101.0)),
+        (150, touch(0, TouchPhase::Started, 104.0, 100.0)),
+        (200, touch(0, TouchPhase::Ended, 104.0, 100.0)),
+    ]);
+    assert!(matches!(gestures[..], [Gesture::Tap { .. }, Gesture::DoubleTap { .. }]), "{:?}", gestures);
+}
+
+#[test]
+fn slow_taps_are_not_a_double_tap() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (50, touch(0, TouchPhase::Ended, 100.0, 100.0)),
+        (900, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (950, touch(0, TouchPhase::Ended, 100.0, 100.0)),
+    ]);
+    assert!(matches!(gestures[..], [Gesture::Tap { .. }, Gesture::Tap { .. }]), "{:?}", gestures);
+}
+
+#[test]
+fn long_press_fires_from_poll_and_suppresses_tap() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    assert!(recognizer.touch(&touch(0, TouchPhase::Started, 10.0, 10.0), start).is_empty());
+    let deadline = recognizer.next_deadline().expect("long press deadline");
+    assert_eq!(deadline, start + recognizer.config.long_press_timeout);
+    assert!(recognizer.poll(deadline - Duration::from_millis(1)).is_empty());
+    assert!(matches!(recognizer.poll(deadline)[..], [Gesture::LongPress { .. }]));
+    let released = recognizer.touch(&touch(0, TouchPhase::Ended, 10.0, 10.0), deadline + Duration::from_millis(100));
+    assert!(released.is_empty(), "{:?}", released);
+}
+
+#[test]
+fn slow_drag_is_a_swipe() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 500.0)),
+        (200, touch(0, TouchPhase::Moved, 100.0, 450.0)),
+        (400, touch(0, TouchPhase::Moved, 100.0, 400.0)),
+        (600, touch(0, TouchPhase::Ended, 100.0, 350.0)),
+    ]);
+    match gestures[..] {
+        [Gesture::Swipe { direction, velocity, .. }] => {
+            assert_eq!(direction, SwipeDirection::Up);
+            assert!(velocity.1 < 0.0);
+        },
+        _ => panic!("expected a swipe, got {:?}", gestures),
+    }
+}
+
+#[test]
+fn fast_drag_is_a_fling_with_velocity() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (20, touch(0, TouchPhase::Moved, 150.0, 100.0)),
+        (40, touch(0, TouchPhase::Moved, 200.0, 100.0)),
+        (60, touch(0, TouchPhase::Ended, 250.0, 100.0)),
+    ]);
+    match gestures[..] {
+        [Gesture::Fling { direction, velocity, .. }] => {
+            assert_eq!(direction, SwipeDirection::Right);
+            assert!((velocity.0 - 2500.0).abs() < 1.0, "{:?}", velocity);
+        },
+        _ => panic!("expected a fling, got {:?}", gestures),
+    }
+}
+
+#[test]
+fn two_finger_pinch_and_rotate() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (10, touch(1, TouchPhase::Started, 200.0, 100.0)),
+        // spread apart: distance 100 -> 200
+        (30, touch(1, TouchPhase::Moved, 300.0, 100.0)),
+    ]);
+    match gestures[..] {
+        [Gesture::Pinch { scale, center, .. }] => {
+            assert!((scale - 2.0).abs() < 1e-9);
+            assert_eq!(center, PhysicalPosition::new(200.0, 100.0));
+        },
+        _ => panic!("expected a pinch, got {:?}", gestures),
+    }
+
+    // rotate the second finger a quarter turn around the first
+    let gestures = recognizer.touch(&touch(1, TouchPhase::Moved, 100.0, 300.0), start + Duration::from_millis(50));
+    let angle = gestures.iter().find_map(|g| match g {
+        Gesture::Rotate { angle, .. } => Some(*angle),
+        _ => None,
+    }).expect("rotate gesture");
+    assert!((angle - ::std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+
+    // lifting fingers after a pinch does not produce taps or swipes
+    let gestures = run(&mut recognizer, start, &[
+        (60, touch(1, TouchPhase::Ended, 100.0, 300.0)),
+        (70, touch(0, TouchPhase::Ended, 100.0, 100.0)),
+    ]);
+    assert!(!gestures.iter().any(|g| matches!(g, Gesture::Tap { .. } | Gesture::Swipe { .. } | Gesture::Fling { .. })), "{:?}", gestures);
+    assert_eq!(recognizer.active_pointers(), 0);
+}
+
+#[test]
+fn cancelled_touch_produces_nothing() {
+    let mut recognizer = GestureRecognizer::default();
+    let start = Instant::now();
+    let gestures = run(&mut recognizer, start, &[
+        (0, touch(0, TouchPhase::Started, 100.0, 100.0)),
+        (20, touch(0, TouchPhase::Cancelled, 100.0, 100.0)),
+    ]);
+    assert!(gestures.is_empty());
+    assert_eq!(recognizer.next_deadline(), None);
+}
@@ -0,0 +1,81 @@
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_common::navigation::{BackGesture, BackOutcome, NavigationStack};
+
+const WINDOW: Duration = Duration::from_secs(2);
+
+#[test]
+fn back_pops_down_to_the_root() {
+    let mut stack = NavigationStack::new("home");
+    stack.push("settings");
+    stack.push("about");
+    assert_eq!((stack.depth(), *stack.current()), (3, "about"));
+
+    let now = Instant::now();
+    assert_eq!(stack.back_at(now), BackOutcome::Popped("about"));
+    assert_eq!(stack.back_at(now), BackOutcome::Popped("settings"));
+    assert!(stack.is_at_root());
+    assert_eq!(stack.back_at(now), BackOutcome::Exit);
+    assert_eq!(*stack.current(), "home");
+}
+
+#[test]
+fn back_at_root_asks_for_confirmation() {
+    let mut stack = NavigationStack::new("home").with_exit_confirmation(WINDOW);
+    let start = Instant::now();
+    assert_eq!(stack.back_at(start), BackOutcome::ConfirmExit);
+    assert_eq!(stack.back_at(start + WINDOW), BackOutcome::Exit);
+}
+
+#[test]
+fn back_after_the_window_asks_again() {
+    let mut stack = NavigationStack::new("home").with_exit_confirmation(WINDOW);
+    let start = Instant::now();
+    assert_eq!(stack.back_at(start), BackOutcome::ConfirmExit);
+    let later = start + WINDOW + Duration::from_millis(1);
+    assert_eq!(stack.back_at(later), BackOutcome::ConfirmExit);
+    // the window starts again from the second press
+    assert_eq!(stack.back_at(later + Duration::from_millis(500)), BackOutcome::Exit);
+}
+
+#[test]
+fn pushing_a_screen_forgets_the_pending_confirmation() {
+    let mut stack = NavigationStack::new("home").with_exit_confirmation(WINDOW);
+    let start = Instant::now();
+    assert_eq!(stack.back_at(start), BackOutcome::ConfirmExit);
+    stack.push("settings");
+    assert_eq!(stack.back_at(start), BackOutcome::Popped("settings"));
+    assert_eq!(stack.back_at(start), BackOutcome::ConfirmExit);
+}
+
+#[test]
+fn gesture_progress_is_clamped_and_cancel_clears_it() {
+    let mut stack = NavigationStack::new("home");
+    stack.push("settings");
+    let now = Instant::now();
+    assert_eq!(stack.back_gesture_at(BackGesture::Started, now), None);
+    assert_eq!(stack.back_progress(), Some(0.0));
+    assert_eq!(stack.back_gesture_at(BackGesture::Progressed(0.4), now), None);
+    assert_eq!(stack.back_progress(), Some(0.4));
+    stack.back_gesture_at(BackGesture::Progressed(1.5), now);
+    assert_eq!(stack.back_progress(), Some(1.0));
+    stack.back_gesture_at(BackGesture::Progressed(-0.5), now);
+    assert_eq!(stack.back_progress(), Some(0.0));
+
+    assert_eq!(stack.back_gesture_at(BackGesture::Cancelled, now), None);
+    assert_eq!(stack.back_progress(), None);
+    assert_eq!(stack.depth(), 2);
+}
+
+#[test]
+fn committed_gesture_navigates_back() {
+    let mut stack = NavigationStack::new("home").with_exit_confirmation(WINDOW);
+    stack.push("settings");
+    let now = Instant::now();
+    stack.back_gesture_at(BackGesture::Started, now);
+    stack.back_gesture_at(BackGesture::Progressed(0.8), now);
+    assert_eq!(stack.back_gesture_at(BackGesture::Committed, now), Some(BackOutcome::Popped("settings")));
+    assert_eq!(stack.back_progress(), None);
+    assert_eq!(stack.back_gesture_at(BackGesture::Committed, now), Some(BackOutcome::ConfirmExit));
+    assert_eq!(stack.back_gesture_at(BackGesture::Committed, now + WINDOW), Some(BackOutcome::Exit));
+}
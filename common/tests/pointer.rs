@@ -0,0 +1,112 @@
+use ::android_rust_example_apk_common::pointer::{
+    PointerAction, PointerButton, PointerKind, PointerTracker, LINE_HEIGHT, MOUSE_POINTER_ID,
+};
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{DeviceId, ElementState, Force, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent};
+
+fn touch(id: u64, phase: TouchPhase, x: f64, y: f64, force: Option<Force>) -> WindowEvent {
+    WindowEvent::Touch(Touch {
+        device_id: DeviceId::dummy(),
+        phase,
+        location: PhysicalPosition::new(x, y),
+        force,
+        id,
+    })
+}
+
+fn cursor_moved(x: f64, y: f64) -> WindowEvent {
+    WindowEvent::CursorMoved { device_id: DeviceId::dummy(), position: PhysicalPosition::new(x, y) }
+}
+
+fn mouse_input(button: MouseButton, state: ElementState) -> WindowEvent {
+    WindowEvent::MouseInput { device_id: DeviceId::dummy(), state, button }
+}
+
+fn wheel(delta: MouseScrollDelta) -> WindowEvent {
+    WindowEvent::MouseWheel { device_id: DeviceId::dummy(), delta, phase: TouchPhase::Moved }
+}
+
+#[test]
+fn touch_and_mouse_are_separate_pointers() {
+    let mut tracker = PointerTracker::new();
+    let finger = tracker.window_event(&touch(3, TouchPhase::Started, 10.0, 20.0, None)).unwrap();
+    assert_eq!((finger.id, finger.kind, finger.action), (3, PointerKind::Touch, PointerAction::Down(PointerButton::Primary)));
+    assert_eq!(finger.contact_phase(), Some(TouchPhase::Started));
+
+    let mouse = tracker.window_event(&cursor_moved(30.0, 40.0)).unwrap();
+    assert_eq!((mouse.id, mouse.kind, mouse.action), (MOUSE_POINTER_ID, PointerKind::Mouse, PointerAction::Moved));
+    assert_eq!(tracker.position(3), Some(PhysicalPosition::new(10.0, 20.0)));
+    assert_eq!(tracker.position(MOUSE_POINTER_ID), Some(PhysicalPosition::new(30.0, 40.0)));
+    assert_eq!(tracker.pressed().collect::<Vec<_>>(), vec![3]);
+
+    let lifted = tracker.window_event(&touch(3, TouchPhase::Ended, 12.0, 22.0, None)).unwrap();
+    assert_eq!((lifted.action, lifted.pressure), (PointerAction::Up(PointerButton::Primary), 0.0));
+    assert_eq!(tracker.position(3), None);
+    assert_eq!(tracker.pressed().count(), 0);
+}
+
+#[test]
+fn mouse_buttons_are_held_until_released() {
+    let mut tracker = PointerTracker::new();
+    tracker.window_event(&cursor_moved(5.0, 5.0));
+    let down = tracker.window_event(&mouse_input(MouseButton::Left, ElementState::Pressed)).unwrap();
+    assert_eq!((down.action, down.pressure), (PointerAction::Down(PointerButton::Primary), 1.0));
+    let both = tracker.window_event(&mouse_input(MouseButton::Right, ElementState::Pressed)).unwrap();
+    assert!(both.buttons.contains(PointerButton::Primary) && both.buttons.contains(PointerButton::Secondary));
+
+    let dragged = tracker.window_event(&cursor_moved(8.0, 5.0)).unwrap();
+    assert_eq!(dragged.contact_phase(), Some(TouchPhase::Moved));
+
+    let up = tracker.window_event(&mouse_input(MouseButton::Left, ElementState::Released)).unwrap();
+    assert_eq!(up.action, PointerAction::Up(PointerButton::Primary));
+    assert!(!up.buttons.contains(PointerButton::Primary) && up.buttons.contains(PointerButton::Secondary));
+    let hovered = tracker.window_event(&cursor_moved(9.0, 5.0)).unwrap();
+    assert_eq!(hovered.contact_phase(), None);
+
+    let up = tracker.window_event(&mouse_input(MouseButton::Right, ElementState::Released)).unwrap();
+    assert!(up.buttons.is_empty());
+    assert_eq!(up.pressure, 0.0);
+
+    let left = tracker.window_event(&WindowEvent::CursorLeft { device_id: DeviceId::dummy() }).unwrap();
+    assert_eq!(left.action, PointerAction::Left);
+    assert_eq!(tracker.position(MOUSE_POINTER_ID), None);
+}
+
+#[test]
+fn cancelled_touch_releases_its_contact() {
+    let mut tracker = PointerTracker::new();
+    tracker.window_event(&touch(1, TouchPhase::Started, 0.0, 0.0, None));
+    let cancelled = tracker.window_event(&touch(1, TouchPhase::Cancelled, 0.0, 0.0, None)).unwrap();
+    assert_eq!((cancelled.action, cancelled.contact_phase()), (PointerAction::Cancelled, Some(TouchPhase::Cancelled)));
+    assert!(cancelled.buttons.is_empty());
+    assert_eq!(tracker.pressed().count(), 0);
+}
+
+#[test]
+fn wheel_lines_are_scaled_to_pixels() {
+    let mut tracker = PointerTracker::new();
+    let lines = tracker.window_event(&wheel(MouseScrollDelta::LineDelta(1.0, -2.0))).unwrap();
+    assert_eq!(lines.action, PointerAction::Wheel { delta_x: LINE_HEIGHT, delta_y: -2.0 * LINE_HEIGHT });
+    let pixels = tracker.window_event(&wheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(3.0, 4.0)))).unwrap();
+    assert_eq!(pixels.action, PointerAction::Wheel { delta_x: 3.0, delta_y: 4.0 });
+}
+
+#[test]
+fn pressure_and_pen_come_from_the_force() {
+    let mut tracker = PointerTracker::new();
+    let finger = tracker.window_event(&touch(1, TouchPhase::Started, 0.0, 0.0, Some(Force::Normalized(0.5)))).unwrap();
+    assert_eq!((finger.kind, finger.pressure), (PointerKind::Touch, 0.5));
+
+    let calibrated = Force::Calibrated { force: 1.0, max_possible_force: 4.0, altitude_angle: Some(1.0) };
+    let pen = tracker.window_event(&touch(2, TouchPhase::Started, 0.0, 0.0, Some(calibrated))).unwrap();
+    assert_eq!((pen.id, pen.kind), (2, PointerKind::Pen));
+
+    let unknown = tracker.window_event(&touch(3, TouchPhase::Started, 0.0, 0.0, None)).unwrap();
+    assert_eq!(unknown.pressure, 1.0);
+}
+
+#[test]
+fn other_events_are_not_pointer_input() {
+    let mut tracker = PointerTracker::new();
+    assert_eq!(tracker.window_event(&WindowEvent::Focused(true)), None);
+}
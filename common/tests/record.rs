@@ -0,0 +1,76 @@
+use ::std::path::Path;
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_common::record::{self, RecordedEvent, RecordedForce, Recorder, TimedEvent};
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{DeviceId, ElementState, Force, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent};
+use ::winit::keyboard::{KeyCode, NativeKeyCode, PhysicalKey};
+
+fn touch(force: Option<Force>) -> WindowEvent {
+    WindowEvent::Touch(Touch {
+        device_id: DeviceId::dummy(),
+        phase: TouchPhase::Moved,
+        location: PhysicalPosition::new(1.5, 2.5),
+        force,
+        id: 7,
+    })
+}
+
+fn wheel(delta: MouseScrollDelta) -> WindowEvent {
+    WindowEvent::MouseWheel { device_id: DeviceId::dummy(), delta, phase: TouchPhase::Moved }
+}
+
+fn keyboard(physical_key: PhysicalKey, state: ElementState) -> RecordedEvent {
+    RecordedEvent::Keyboard { physical_key, state, repeat: false }
+}
+
+#[test]
+fn recordings_load_back_unchanged() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("round_trip.jsonl");
+    let mut recorder = Recorder::create(&path).expect("create recording");
+    let start = Instant::now();
+    let events = [
+        touch(Some(Force::Normalized(0.25))),
+        touch(Some(Force::Calibrated { force: 2.0, max_possible_force: 4.0, altitude_angle: Some(0.5) })),
+        touch(Some(Force::Calibrated { force: 1.0, max_possible_force: 4.0, altitude_angle: None })),
+        touch(None),
+        wheel(MouseScrollDelta::LineDelta(1.0, -3.0)),
+        wheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.5, 12.0))),
+        WindowEvent::MouseInput { device_id: DeviceId::dummy(), state: ElementState::Pressed, button: MouseButton::Other(9) },
+    ];
+    for (i, event) in events.iter().enumerate() {
+        recorder.record_at(event, start + Duration::from_millis(i as u64)).expect("record");
+    }
+    let keys = [
+        keyboard(PhysicalKey::Unidentified(NativeKeyCode::Android(4)), ElementState::Pressed),
+        keyboard(PhysicalKey::Unidentified(NativeKeyCode::Xkb(166)), ElementState::Released),
+        keyboard(PhysicalKey::Code(KeyCode::F12), ElementState::Pressed),
+    ];
+    for key in keys.iter() {
+        recorder.record_event(key.clone(), start + Duration::from_millis(100)).expect("record");
+    }
+    drop(recorder);
+
+    let expected: Vec<_> = events.iter().enumerate()
+        .map(|(i, event)| TimedEvent { at: Duration::from_millis(i as u64), event: RecordedEvent::from_window_event(event) })
+        .chain(keys.iter().map(|key| TimedEvent { at: Duration::from_millis(100), event: key.clone() }))
+        .collect();
+    // the recorder's clock starts when it is created, slightly before `start`
+    let loaded = record::load(&path).expect("load recording");
+    let offset = loaded[0].at;
+    let loaded: Vec<_> = loaded.into_iter().map(|timed| TimedEvent { at: timed.at - offset, ..timed }).collect();
+    assert_eq!(loaded, expected);
+    assert_eq!(
+        expected[1].event,
+        RecordedEvent::Touch {
+            id: 7,
+            phase: TouchPhase::Moved,
+            location: PhysicalPosition::new(1.5, 2.5),
+            force: Some(RecordedForce::Calibrated { force: 2.0, max_possible_force: 4.0, altitude_angle: Some(0.5) }),
+        },
+    );
+    // and back into the same window events
+    for (timed, event) in expected.iter().zip(events.iter()) {
+        assert_eq!(timed.event.to_window_event().as_ref(), Some(event));
+    }
+}
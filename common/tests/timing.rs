@@ -0,0 +1,70 @@
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_common::timing::{FrameStats, RollingHistogram};
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn histogram_forgets_the_oldest_samples() {
+    let mut histogram = RollingHistogram::new(3, ms(1), 10);
+    for sample in [1, 2, 3, 4] {
+        histogram.record(ms(sample));
+    }
+    assert_eq!(histogram.samples().collect::<Vec<_>>(), vec![ms(2), ms(3), ms(4)]);
+    assert_eq!(histogram.buckets()[1], 0);
+    assert_eq!(histogram.buckets()[2..5], [1, 1, 1]);
+    assert_eq!(histogram.mean(), Some(ms(3)));
+    assert_eq!(histogram.max(), Some(ms(4)));
+}
+
+#[test]
+fn long_samples_land_in_the_last_bucket() {
+    let mut histogram = RollingHistogram::new(10, ms(1), 4);
+    histogram.record(ms(100));
+    assert_eq!(histogram.buckets(), &[0, 0, 0, 1]);
+    assert_eq!(histogram.percentile(0.5), Some(ms(100)));
+}
+
+#[test]
+fn percentiles_round_up_to_bucket_ends() {
+    let mut histogram = RollingHistogram::new(100, ms(2), 50);
+    for sample in 1..=100 {
+        histogram.record(Duration::from_micros(sample * 100));
+    }
+    // samples run from 0.1ms to 10ms
+    assert_eq!(histogram.percentile(0.5), Some(ms(6)));
+    assert_eq!(histogram.percentile(0.95), Some(ms(10)));
+    assert_eq!(histogram.percentile(0.0), Some(ms(2)));
+    assert_eq!(RollingHistogram::new(4, ms(1), 4).summary(), None);
+}
+
+#[test]
+fn present_interval_skips_pauses() {
+    let start = Instant::now();
+    let mut stats = FrameStats::new();
+    stats.record_present(start);
+    stats.record_present(start + ms(16));
+    stats.pause();
+    stats.record_present(start + ms(5000));
+    stats.record_present(start + ms(5017));
+    assert_eq!(stats.frames, 4);
+    assert_eq!(stats.present_interval.samples().collect::<Vec<_>>(), vec![ms(16), ms(17)]);
+}
+
+#[test]
+fn summary_is_logged_once_per_interval() {
+    let start = Instant::now();
+    let mut stats = FrameStats::new().with_log_interval(ms(1000));
+    stats.record_cpu(ms(3));
+    assert_eq!(stats.log_due(start), None, "the first call starts the interval");
+    assert_eq!(stats.log_due(start + ms(500)), None);
+    let summary = stats.log_due(start + ms(1000)).expect("interval elapsed");
+    assert_eq!(summary.cpu.map(|cpu| cpu.max), Some(ms(3)));
+    assert_eq!(summary.gpu, None);
+    assert!(summary.to_string().contains("cpu mean 3.00ms"), "{}", summary);
+    assert_eq!(stats.log_due(start + ms(1500)), None);
+
+    assert_eq!(FrameStats::new().log_due(start), None, "no interval, no logging");
+}
@@ -0,0 +1,70 @@
+#[cfg(target_os="android")]
+use ::std::os::unix::prelude::AsRawFd;
+use ::std::os::unix::prelude::OwnedFd;
+use ::std::sync::{Arc, Mutex};
+
+/// Commands written to the looper pipe and handled on the Android main thread.
+///
+/// Each command is sent as a single byte, see `jni_looper::callback`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeCommand {
+    ShowGreeting = 1,
+    ConfirmExit = 2,
+    ShowDetails = 3,
+}
+
+impl BridgeCommand {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(BridgeCommand::ShowGreeting),
+            2 => Some(BridgeCommand::ConfirmExit),
+            3 => Some(BridgeCommand::ShowDetails),
+            _ => None,
+        }
+    }
+
+    /// Text of the toast shown for this command.
+    pub fn toast_message(&self) -> &'static str {
+        match self {
+            BridgeCommand::ShowGreeting => "EHLO World",
+            BridgeCommand::ConfirmExit => "Press back again to exit",
+            BridgeCommand::ShowDetails => "Details, press back to return",
+        }
+    }
+}
+
+/// Where [`BridgeCommand`]s go.
+pub trait Bridge {
+    fn send(&mut self, command: BridgeCommand);
+}
+
+/// Writes commands to the looper pipe set up by `jni_looper::setup_looper`.
+pub struct PipeBridge(pub [OwnedFd;2]);
+
+impl Bridge for PipeBridge {
+    fn send(&mut self, command: BridgeCommand) {
+        #[cfg(target_os="android")]
+        let _ = unsafe { libc::write(
+            self.0[1].as_raw_fd(),
+            &(command as u8) as *const u8 as *const ::std::os::raw::c_void,
+            1
+        )};
+    }
+}
+
+/// Keeps every command sent, for tests.  Clones share the same log.
+#[derive(Clone, Default)]
+pub struct CommandLog(Arc<Mutex<Vec<BridgeCommand>>>);
+
+impl CommandLog {
+    pub fn commands(&self) -> Vec<BridgeCommand> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Bridge for CommandLog {
+    fn send(&mut self, command: BridgeCommand) {
+        self.0.lock().unwrap().push(command);
+    }
+}
@@ -7,6 +7,8 @@ use ::jni::objects::{JObject, JValue, JValueGen};
 use ::log::{debug, info};
 use ::ndk::looper::FdEvent;
 
+use crate::bridge::BridgeCommand;
+
 #[no_mangle]
 pub fn setup_looper(app: &AndroidApp) -> Result<[OwnedFd;2], ()> {
     info!("android_main setup up looper");
@@ -43,18 +45,21 @@ pub fn callback(fd: BorrowedFd<'_>, _event: FdEvent, app: &AndroidApp ) -> bool
     };
     // schedule_alarm();
     // send_notification(app);
-    display_toast();
+    match BridgeCommand::from_byte(cmd_i) {
+        Some(command) => display_toast(command.toast_message()),
+        None => debug!("unknown bridge command {}", cmd_i),
+    }
     return read_result > 0;
 }
 #[no_mangle]
-fn display_toast()
+fn display_toast(text: &str)
 {
     let ctx = ndk_context::android_context();
     let vm = unsafe { jni::JavaVM::from_raw(ctx.vm().cast()) }.expect("cannot get vm from ctx");
     let mut env = vm.attach_current_thread().expect("cannot attach to current thread");
 
     let toast_clazz = env.find_class("android/widget/Toast").expect("cannot load context class");
-    let message = env.new_string("EHLO World").unwrap();
+    let message = env.new_string(text).unwrap();
     let ctx_as_jobect = unsafe { JObject::from_raw(ctx.context() as jni::sys::jobject) };
     let ctx_as_param = JValueGen::Object(&ctx_as_jobect);
     let args = &[ctx_as_param, JValue::Object(&message), JValue::Int(0)];
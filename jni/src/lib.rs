@@ -1,46 +1,92 @@
 #![allow(dead_code, unused_variables, unused_assignments)]
-use ::std::os::unix::prelude::{AsRawFd, OwnedFd};
+use ::std::os::unix::prelude::OwnedFd;
 use ::std::sync::Arc;
+use ::std::time::{Duration, Instant};
 
+use ::android_rust_example_apk_common::gesture::{Gesture, GestureRecognizer};
+use ::android_rust_example_apk_common::headless::HeadlessApp;
+use ::android_rust_example_apk_common::navigation::{BackGesture, BackOutcome, NavigationStack, ANDROID_BUTTON_BACK, ANDROID_KEYCODE_BACK};
+use ::android_rust_example_apk_common::pointer::{PointerEvent, PointerTracker};
+use ::android_rust_example_apk_common::record::Recorder;
+use ::android_rust_example_apk_common::timing::FrameStats;
+use ::android_rust_example_apk_common::window::AppWindow;
 use ::log::info;
-use ::winit::event::TouchPhase;
 #[cfg(target_os="android")]
 use ::winit::platform::android::ActiveEventLoopExtAndroid;
 use ::winit::{application::ApplicationHandler, keyboard::PhysicalKey};
 use ::winit::event_loop::EventLoop;
-#[allow(unused_imports)]
 use ::winit::keyboard::NativeKeyCode;
-#[allow(unused_imports)]
 use ::winit::event::ElementState;
+pub mod bridge;
 #[cfg(target_os="android")]
 mod notification;
 #[cfg(target_os="android")]
 mod jni_looper;
 
+use bridge::{Bridge, BridgeCommand, PipeBridge};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screen {
+    Home,
+    /// Opened with a double tap.
+    Details,
+}
+
 pub struct ApplicationState {
     pub gfx: Option<GfxState>,
     #[cfg(target_os="android")]
     pub native_window: Option<ndk::native_window::NativeWindow>,
-    pub pipe: Option<[OwnedFd;2]>,
+    pub bridge: Option<Box<dyn Bridge>>,
+    pub navigation: NavigationStack<Screen>,
+    pub gestures: GestureRecognizer,
+    pub pointers: PointerTracker,
+    pub recorder: Option<Recorder>,
+    /// Redraw timings, summarized in the log every few seconds.
+    pub stats: FrameStats,
+    /// Set when the app wants the event loop to stop.
+    pub exit_requested: bool,
 }
 pub struct GfxState {
-    pub window: Arc<winit::window::Window>,
+    pub window: Arc<dyn AppWindow>,
     pub cursor_position: ::winit::dpi::PhysicalPosition<f64>,
     pub did_resize: bool,
     pub size: (u32, u32),
 }
+impl Default for ApplicationState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ApplicationState  {
     pub fn new () -> Self {
         Self {
             gfx: None,
             #[cfg(target_os="android")]
             native_window: None,
-            pipe: None,
+            bridge: None,
+            navigation: NavigationStack::new(Screen::Home)
+                .with_exit_confirmation(Duration::from_secs(2)),
+            gestures: GestureRecognizer::default(),
+            pointers: PointerTracker::new(),
+            recorder: None,
+            stats: FrameStats::new().with_log_interval(Duration::from_secs(5)),
+            exit_requested: false,
         }
     }
 
+    /// Write every window event to `recorder`, see [`record::replay`](::android_rust_example_apk_common::record::replay).
+    pub fn with_recorder(&mut self, recorder: Recorder) -> &mut Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     pub fn with_looper(&mut self, looper: [OwnedFd;2]) -> &mut Self {
-        self.pipe = Some(looper);
+        self.with_bridge(Box::new(PipeBridge(looper)))
+    }
+
+    pub fn with_bridge(&mut self, bridge: Box<dyn Bridge>) -> &mut Self {
+        self.bridge = Some(bridge);
         self
     }
 
@@ -51,10 +97,69 @@ impl ApplicationState  {
         self
     }
 
+    pub fn push_screen(&mut self, screen: Screen) {
+        self.navigation.push(screen);
+    }
+
+    /// Ask the Android main thread to run `command`.  No-op without a bridge.
+    pub fn send_command(&mut self, command: BridgeCommand) {
+        if let Some(bridge) = self.bridge.as_mut() {
+            bridge.send(command);
+        }
+    }
+
+    fn apply_back_outcome(&mut self, outcome: BackOutcome<Screen>) {
+        match outcome {
+            BackOutcome::Popped(screen) => info!("left screen {:?}, now on {:?}", screen, self.navigation.current()),
+            BackOutcome::ConfirmExit => self.send_command(BridgeCommand::ConfirmExit),
+            BackOutcome::Exit => self.exit_requested = true,
+        }
+    }
+
+    pub fn navigate_back(&mut self, now: Instant) {
+        let outcome = self.navigation.back_at(now);
+        self.apply_back_outcome(outcome);
+    }
+
+    pub fn back_gesture(&mut self, gesture: BackGesture, now: Instant) {
+        if let Some(outcome) = self.navigation.back_gesture_at(gesture, now) {
+            self.apply_back_outcome(outcome);
+        }
+    }
+
+    fn on_pointer(&mut self, pointer: PointerEvent, now: Instant) {
+        if let Some(gfx) = self.gfx.as_mut() {
+            gfx.cursor_position = pointer.position;
+        }
+        for gesture in self.gestures.pointer_event(&pointer, now) {
+            self.on_gesture(gesture);
+        }
+    }
+
+    fn on_gesture(&mut self, gesture: Gesture) {
+        info!("gesture {:?}", gesture);
+        match gesture {
+            Gesture::Tap { .. } => self.send_command(BridgeCommand::ShowGreeting),
+            Gesture::DoubleTap { .. } if *self.navigation.current() == Screen::Home => {
+                self.push_screen(Screen::Details);
+                self.send_command(BridgeCommand::ShowDetails);
+            },
+            _ => (),
+        }
+    }
 }
 
 impl ApplicationHandler for ApplicationState {
 
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        self.advance_to(Instant::now());
+        // wake up in time to report a long-press on a resting finger
+        match self.gestures.next_deadline() {
+            Some(deadline) => event_loop.set_control_flow(::winit::event_loop::ControlFlow::WaitUntil(deadline)),
+            None => event_loop.set_control_flow(::winit::event_loop::ControlFlow::Wait),
+        }
+    }
+
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         info!("Window resumed");
         // let ndk_context = ndk_context::android_context();
@@ -62,17 +167,16 @@ impl ApplicationHandler for ApplicationState {
             event_loop.create_window(winit::window::WindowAttributes::default())
                 .expect("cannot create window")
         );
-        let physical_size = window.inner_size();
-        self.gfx = Some(GfxState{
-            window: window.clone(),
-            cursor_position: ::winit::dpi::PhysicalPosition::<f64> {x: 0.0, y: 0.0},
-            did_resize: false,
-            size: (physical_size.width, physical_size.height),
-        });
+        self.resume_with_window(window);
         #[cfg(target_os="android")]
         self.with_native_window(event_loop.android_app().native_window());
     }
 
+    fn suspended(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        info!("Window suspended");
+        self.suspend();
+    }
+
     fn window_event(
         &mut self,
         event_loop: &winit::event_loop::ActiveEventLoop,
@@ -82,71 +186,45 @@ impl ApplicationHandler for ApplicationState {
         if self.gfx.is_none() {
             return;
         }
-        let window = match self.gfx.as_ref() {
-            Some(gfx) => gfx.window.clone(),
-            None => return,
-        };
-        let GfxState {
-            window,
-            cursor_position,
-            did_resize,
-            size,
-        } = self.gfx.as_mut().expect("cannot destruct gfxstate, even though it is not none");
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(&event) {
+                info!("stopped recording input: {}", e);
+                self.recorder = None;
+            }
+        }
+        self.handle_window_event(event, Instant::now());
+        if self.exit_requested {
+            event_loop.exit();
+        }
+    }
+}
+
+impl ApplicationState {
+    /// Apply one window event.  Does not need a running event loop, which
+    /// lets recordings be replayed without a window.
+    pub fn handle_window_event(&mut self, event: winit::event::WindowEvent, now: Instant) {
+        self.advance_to(now);
+        if let Some(pointer) = self.pointers.window_event(&event) {
+            self.on_pointer(pointer, now);
+        }
 
         match event {
             ::winit::event::WindowEvent::CloseRequested => {
                 self.gfx = None;
-                event_loop.exit();
-                ()
-            },
-            ::winit::event::WindowEvent::Touch(touch) => {
-                *cursor_position = touch.location;
-                info!("window event touch");
-                #[cfg(target_os="android")]
-                if touch.phase == TouchPhase::Ended {
-
-                    if let Some(pipe) = self.pipe.as_mut() {
-                        info!("pipe was some under touch");
-                        let _ = unsafe { libc::write(
-                            pipe[1].as_raw_fd(),
-                            &1_u8 as *const u8 as *const ::std::os::raw::c_void,
-                            1
-                        )};
-                    }
-                }
+                self.exit_requested = true;
             },
+            // pointer input is handled by `on_pointer` before this match
+            ::winit::event::WindowEvent::Touch(touch) => (),
             ::winit::event::WindowEvent::KeyboardInput { device_id, event, is_synthetic } => {
-                #[cfg(target_os="android")]
-                if let PhysicalKey::Unidentified(ncode) = event.physical_key {
-                    match ncode {
-                        NativeKeyCode::Android(val) if val == ::android_activity::input::Button::Back.into() => {
-                            match event.state {
-                                ElementState::Pressed => info!("back motion motioned"),
-                                ElementState::Released => info!("back motion released"),
-                            }
-                        },
-                        NativeKeyCode::Android(val) if val == ::android_activity::input::Keycode::Back.into() => {
-                            match event.state {
-                                ElementState::Pressed => info!("back button pressed"),
-                                ElementState::Released => event_loop.exit(),
-                            }
-                        },
-                        _ => ()
-                    }
-                }
-                if let PhysicalKey::Code(key_code) = event.physical_key {
-                    if event.state.is_pressed() {
-                        info!("key pressed {}", key_code as u32);
-                    } else {
-                        info!("key released {}", key_code as u32);
-                    }
-                }
+                self.key_input(event.physical_key, event.state, now);
             },
             ::winit::event::WindowEvent::ActivationTokenDone { serial, token } => (),
             ::winit::event::WindowEvent::Resized(physical_size) => {
                 info!("resized {} x {}", physical_size.width, physical_size.height);
-                *did_resize = true;
-                window.request_redraw();
+                if let Some(gfx) = self.gfx.as_mut() {
+                    gfx.did_resize = true;
+                    gfx.window.request_redraw();
+                }
             },
             ::winit::event::WindowEvent::Moved(physical_position) => (),
             ::winit::event::WindowEvent::Destroyed => (),
@@ -171,10 +249,13 @@ impl ApplicationHandler for ApplicationState {
             ::winit::event::WindowEvent::ThemeChanged(theme) => (),
             ::winit::event::WindowEvent::Occluded(_) => (),
             ::winit::event::WindowEvent::RedrawRequested => {
-                    if *did_resize {
-                        let wsize = window.inner_size();
-                        *size = (wsize.width, wsize.height);
-                        *did_resize = false;
+                    let started = Instant::now();
+                    if let Some(gfx) = self.gfx.as_mut() {
+                        if gfx.did_resize {
+                            let wsize = gfx.window.inner_size();
+                            gfx.size = (wsize.width, wsize.height);
+                            gfx.did_resize = false;
+                        }
                     }
                     info!("redraw requested");
 
@@ -182,10 +263,98 @@ impl ApplicationHandler for ApplicationState {
                     if self.native_window.is_some() {
                         info!("redraw requested: native_window is_some");
                         android::dummy_render(self.native_window.as_ref().unwrap());
+                        // only a frame that reached the window counts as presented
+                        self.stats.record_present(now);
                     }
+
+                    self.stats.record_cpu(started.elapsed());
+                    if let Some(summary) = self.stats.log_due(now) {
+                        info!("frame times: {}", summary);
+                    }
+            }
+        }
+    }
+
+    pub fn key_input(&mut self, physical_key: PhysicalKey, state: ElementState, now: Instant) {
+        // not behind cfg(target_os = "android"), so device recordings replay on desktop
+        match physical_key {
+            PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_BUTTON_BACK)) => {
+                match state {
+                    ElementState::Pressed => self.back_gesture(BackGesture::Started, now),
+                    ElementState::Released => self.back_gesture(BackGesture::Committed, now),
+                }
+            },
+            PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_KEYCODE_BACK)) => {
+                match state {
+                    ElementState::Pressed => info!("back button pressed"),
+                    ElementState::Released => self.navigate_back(now),
+                }
+            },
+            _ => ()
+        }
+        if let PhysicalKey::Code(key_code) = physical_key {
+            if state.is_pressed() {
+                info!("key pressed {}", key_code as u32);
+            } else {
+                info!("key released {}", key_code as u32);
             }
         }
     }
+
+    /// Fire time based input (long-press) that is due at `now`.
+    pub fn advance_to(&mut self, now: Instant) {
+        for gesture in self.gestures.poll(now) {
+            self.on_gesture(gesture);
+        }
+    }
+
+    pub fn resume_with_window(&mut self, window: Arc<dyn AppWindow>) {
+        let physical_size = window.inner_size();
+        self.gfx = Some(GfxState{
+            window,
+            cursor_position: ::winit::dpi::PhysicalPosition::<f64> {x: 0.0, y: 0.0},
+            did_resize: false,
+            size: (physical_size.width, physical_size.height),
+        });
+    }
+
+    /// The native window is gone, drop everything that refers to it.
+    pub fn suspend(&mut self) {
+        self.gfx = None;
+        self.stats.pause();
+        #[cfg(target_os="android")]
+        self.with_native_window(None);
+    }
+}
+
+impl HeadlessApp for ApplicationState {
+    fn resume_with_window(&mut self, window: Arc<dyn AppWindow>) {
+        ApplicationState::resume_with_window(self, window);
+    }
+
+    fn suspend(&mut self) {
+        ApplicationState::suspend(self);
+    }
+
+    fn handle_window_event(&mut self, event: winit::event::WindowEvent, now: Instant) {
+        ApplicationState::handle_window_event(self, event, now);
+    }
+
+    fn key_input(&mut self, physical_key: PhysicalKey, state: ElementState, now: Instant) {
+        ApplicationState::key_input(self, physical_key, state, now);
+    }
+
+    fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    fn advance_to(&mut self, now: Instant) {
+        ApplicationState::advance_to(self, now);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.gestures.next_deadline()
+    }
 }
 
 pub fn _main(event_loop: EventLoop<()>, app: &mut ApplicationState) -> Result<(), winit::error::EventLoopError>
@@ -199,6 +368,7 @@ mod android {
     use crate::ApplicationState;
     use super::_main;
     use super::jni_looper::setup_looper;
+    use ::android_rust_example_apk_common::record::Recorder;
     use android_activity::AndroidApp;
     use ::log::info;
     use winit::platform::android::EventLoopBuilderExtAndroid;
@@ -217,6 +387,15 @@ mod android {
         app.with_looper(looper);
         app.with_native_window(aapp.native_window());
 
+        // an empty `files/record_input` turns on an input recording to pull with
+        // `adb shell run-as <package> cat files/input.jsonl`
+        if let Some(dir) = aapp.internal_data_path().filter(|dir| dir.join("record_input").exists()) {
+            match Recorder::create(dir.join("input.jsonl")) {
+                Ok(recorder) => { app.with_recorder(recorder); },
+                Err(e) => info!("cannot record input: {}", e),
+            }
+        }
+
         log::info!("android_main started");
         let event_loop = ::winit::event_loop::EventLoop::builder()
             .with_android_app(aapp).build().unwrap();
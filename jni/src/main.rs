@@ -1,16 +1,39 @@
-use android_rust_example_apk_jni::_main;
-use android_rust_example_apk_jni::ApplicationState;
+use ::std::path::PathBuf;
+
+use ::android_rust_example_apk_common::record::{self, Recorder};
+use ::android_rust_example_apk_jni::{_main, ApplicationState};
+use ::clap::Parser;
+
+/// Desktop runner for the JNI example.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Write input to a file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Feed recorded input back without a window
+    #[arg(long)]
+    replay: Option<PathBuf>,
+}
 
 fn main() {
-    env_logger::builder()
+    let args = Args::parse();
+    ::env_logger::builder()
         .filter_level(::log::LevelFilter::Info) // Default Log Level
         .parse_default_env()
         .init();
 
+    let mut app = ApplicationState::new();
+
+    if let Some(path) = args.replay.as_ref() {
+        let recording = record::load(path).expect("cannot read recording");
+        record::replay(&mut app, &recording);
+        return;
+    }
+    if let Some(path) = args.record.as_ref() {
+        app.with_recorder(Recorder::create(path).expect("cannot create recording"));
+    }
+
     let event_loop = ::winit::event_loop::EventLoop::builder().build()
         .expect("unable to create event loop");
-
-    let mut app = ApplicationState::new();
     let _ = _main(event_loop, &mut app);
 }
-
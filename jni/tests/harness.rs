@@ -0,0 +1,131 @@
+use ::std::time::Duration;
+
+use ::android_rust_example_apk_common::headless::Harness;
+use ::android_rust_example_apk_common::navigation::ANDROID_KEYCODE_BACK;
+use ::android_rust_example_apk_common::record::{self, RecordedEvent, TimedEvent};
+use ::android_rust_example_apk_jni::bridge::{BridgeCommand, CommandLog};
+use ::android_rust_example_apk_jni::{ApplicationState, Screen};
+use ::winit::event::{ElementState, MouseButton};
+use ::winit::keyboard::{NativeKeyCode, PhysicalKey};
+
+fn harness() -> (Harness<ApplicationState>, CommandLog) {
+    let log = CommandLog::default();
+    let mut app = ApplicationState::new();
+    app.with_bridge(Box::new(log.clone()));
+    let mut harness = Harness::new(app, 800, 600);
+    harness.resume();
+    (harness, log)
+}
+
+#[test]
+fn tap_shows_greeting() {
+    let (mut harness, log) = harness();
+    harness.tap(10.0, 20.0);
+    assert_eq!(log.commands(), vec![BridgeCommand::ShowGreeting]);
+    let gfx = harness.app.gfx.as_ref().expect("resumed");
+    assert_eq!((gfx.cursor_position.x, gfx.cursor_position.y), (10.0, 20.0));
+}
+
+#[test]
+fn mouse_click_counts_as_tap() {
+    let (mut harness, log) = harness();
+    harness.mouse_move(5.0, 5.0)
+        .mouse_button(MouseButton::Left, ElementState::Pressed)
+        .advance(Duration::from_millis(40))
+        .mouse_button(MouseButton::Left, ElementState::Released);
+    assert_eq!(log.commands(), vec![BridgeCommand::ShowGreeting]);
+}
+
+#[test]
+fn long_press_is_not_a_tap() {
+    let (mut harness, log) = harness();
+    harness.touch(0, ::winit::event::TouchPhase::Started, 10.0, 10.0)
+        .advance(Duration::from_secs(1))
+        .touch(0, ::winit::event::TouchPhase::Ended, 10.0, 10.0);
+    assert!(log.commands().is_empty());
+}
+
+#[test]
+fn double_tap_opens_details_until_back() {
+    let (mut harness, log) = harness();
+    harness.tap(10.0, 10.0).advance(Duration::from_millis(100)).tap(10.0, 10.0);
+    assert_eq!(log.commands(), vec![BridgeCommand::ShowGreeting, BridgeCommand::ShowDetails]);
+    assert_eq!(harness.app.navigation.current(), &Screen::Details);
+
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert_eq!(harness.app.navigation.current(), &Screen::Home);
+    assert!(!harness.app.exit_requested);
+}
+
+#[test]
+fn back_pops_then_confirms_then_exits() {
+    let (mut harness, log) = harness();
+    harness.app.push_screen(Screen::Details);
+
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert_eq!(harness.app.navigation.current(), &Screen::Home);
+    assert!(log.commands().is_empty());
+
+    harness.app.navigate_back(now);
+    assert_eq!(log.commands(), vec![BridgeCommand::ConfirmExit]);
+    assert!(!harness.app.exit_requested);
+
+    harness.advance(Duration::from_millis(500));
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert!(harness.app.exit_requested);
+}
+
+#[test]
+fn back_after_confirmation_window_asks_again() {
+    let (mut harness, log) = harness();
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    harness.advance(Duration::from_secs(3));
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert_eq!(log.commands(), vec![BridgeCommand::ConfirmExit, BridgeCommand::ConfirmExit]);
+    assert!(!harness.app.exit_requested);
+}
+
+#[test]
+fn replayed_back_key_confirms_then_exits() {
+    let (mut harness, log) = harness();
+    let back = |ms, state| TimedEvent {
+        at: Duration::from_millis(ms),
+        event: RecordedEvent::Keyboard { physical_key: PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_KEYCODE_BACK)), state, repeat: false },
+    };
+    let recording = [
+        back(0, ElementState::Pressed),
+        back(50, ElementState::Released),
+        back(300, ElementState::Pressed),
+        back(350, ElementState::Released),
+    ];
+    record::replay(&mut harness.app, &recording);
+    assert_eq!(log.commands(), vec![BridgeCommand::ConfirmExit]);
+    assert!(harness.app.exit_requested);
+}
+
+#[test]
+fn resize_is_applied_on_redraw() {
+    let (mut harness, _) = harness();
+    harness.resize(1024, 768);
+    assert_eq!(harness.window.redraw_requests(), 1);
+    assert!(harness.app.gfx.as_ref().unwrap().did_resize);
+    harness.redraw();
+    let gfx = harness.app.gfx.as_ref().unwrap();
+    assert!(!gfx.did_resize);
+    assert_eq!(gfx.size, (1024, 768));
+}
+
+#[test]
+fn suspend_drops_window_and_close_requests_exit() {
+    let (mut harness, _) = harness();
+    harness.suspend();
+    assert!(harness.app.gfx.is_none());
+    // input without a window still reaches app state
+    harness.tap(1.0, 1.0).close();
+    assert!(harness.app.exit_requested);
+}
@@ -0,0 +1,51 @@
+use ::std::io;
+use ::std::path::PathBuf;
+
+/// Where textures and other data files are read from.  Names are relative
+/// and use `/`, e.g. `textures/bricks.png`, on every platform.
+pub enum Assets {
+    /// Files below a directory, used on desktop.
+    Directory(PathBuf),
+    /// The `assets` folder packaged into the APK.
+    #[cfg(target_os = "android")]
+    Apk(::ndk::asset::AssetManager),
+}
+
+impl Default for Assets {
+    /// The first folder out of `assets` next to the executable, then `assets`
+    /// and the APK's `android/app/src/main/assets` below the working
+    /// directory, so both an installed build and `cargo run` in the crate find
+    /// their files.  `assets` in the working directory when none exists.
+    fn default() -> Self {
+        let next_to_exe = ::std::env::current_exe().ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("assets")));
+        let dir = next_to_exe.into_iter()
+            .chain([PathBuf::from("assets"), PathBuf::from("android/app/src/main/assets")])
+            .find(|dir| dir.is_dir())
+            .unwrap_or_else(|| PathBuf::from("assets"));
+        Assets::Directory(dir)
+    }
+}
+
+impl Assets {
+    /// The APK's asset folder in this crate's source tree, for tests that
+    /// should read the files the Android build packages wherever they run.
+    pub fn source_tree() -> Self {
+        Assets::Directory(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/android/app/src/main/assets")))
+    }
+
+    /// The whole file, `io::ErrorKind::NotFound` when there is no asset called `name`.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Assets::Directory(dir) => ::std::fs::read(dir.join(name)),
+            #[cfg(target_os = "android")]
+            Assets::Apk(manager) => {
+                let path = ::std::ffi::CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let mut asset = manager.open(&path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no asset {}", name)))?;
+                let mut data = Vec::with_capacity(asset.length());
+                io::Read::read_to_end(&mut asset, &mut data)?;
+                Ok(data)
+            },
+        }
+    }
+}
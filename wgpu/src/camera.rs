@@ -0,0 +1,359 @@
+use ::android_rust_example_apk_common::pointer::{PointerAction, PointerButton, PointerEvent};
+use ::winit::dpi::PhysicalPosition;
+
+use crate::renderer::{multiply, Matrix};
+
+/// A 2D camera for screen-like world coordinates: x to the right, y down,
+/// and at `zoom` 1 one world unit per physical pixel.  Physical pixels are
+/// what winit reports `cursor_position` and touches in, so UIs laid out in
+/// world units line up with input without any conversion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrthoCamera {
+    /// World position shown at the top-left corner of the viewport.
+    pub position: [f32; 2],
+    /// Physical pixels per world unit.
+    pub zoom: f32,
+    /// Size of the target in physical pixels.
+    pub viewport: (u32, u32),
+}
+
+impl Default for OrthoCamera {
+    fn default() -> Self {
+        Self::new((1, 1))
+    }
+}
+
+impl OrthoCamera {
+    pub fn new(viewport: (u32, u32)) -> Self {
+        Self { position: [0.0, 0.0], zoom: 1.0, viewport }
+    }
+
+    /// World to clip space.  Depth is always 0, 2D content is ordered by
+    /// drawing it back to front instead.
+    pub fn view_proj(&self) -> Matrix {
+        let sx = 2.0 * self.zoom / self.viewport.0.max(1) as f32;
+        let sy = -2.0 * self.zoom / self.viewport.1.max(1) as f32;
+        [
+            [sx, 0.0, 0.0, 0.0],
+            [0.0, sy, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [-1.0 - sx * self.position[0], 1.0 - sy * self.position[1], 0.0, 1.0],
+        ]
+    }
+
+    /// The world position under a point of the window, e.g. `cursor_position`.
+    pub fn screen_to_world(&self, screen: PhysicalPosition<f64>) -> [f32; 2] {
+        [
+            self.position[0] + screen.x as f32 / self.zoom,
+            self.position[1] + screen.y as f32 / self.zoom,
+        ]
+    }
+
+    pub fn world_to_screen(&self, world: [f32; 2]) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(
+            f64::from((world[0] - self.position[0]) * self.zoom),
+            f64::from((world[1] - self.position[1]) * self.zoom),
+        )
+    }
+}
+
+/// How an [`OrbitCamera`] projects the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Vertical field of view in radians.
+    Perspective { fov_y: f32 },
+    /// World units visible from the top to the bottom of the viewport.
+    Orthographic { height: f32 },
+}
+
+/// A 3D camera looking at `target` from `distance` away, at angles that an
+/// [`OrbitController`] changes.  Right handed with y up; at `yaw` and `pitch`
+/// 0 it looks down -z.  Depth maps to 0..1 as wgpu expects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitCamera {
+    pub target: [f32; 3],
+    pub distance: f32,
+    /// Rotation around the y axis in radians, positive moves the eye to the right.
+    pub yaw: f32,
+    /// Elevation in radians, positive looks down onto the target.
+    pub pitch: f32,
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    /// Size of the target in physical pixels.
+    pub viewport: (u32, u32),
+}
+
+/// Pitch stays this far from straight up or down, where the view direction
+/// would be parallel to the up vector.
+const PITCH_MARGIN: f32 = 0.01;
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self::new((1, 1))
+    }
+}
+
+impl OrbitCamera {
+    /// A 45 degree perspective camera that shows -1..1 vertically around the
+    /// origin, so meshes laid out in clip space look about as they do without a camera.
+    pub fn new(viewport: (u32, u32)) -> Self {
+        let fov_y = ::std::f32::consts::FRAC_PI_4;
+        Self {
+            target: [0.0, 0.0, 0.0],
+            distance: 1.0 / (fov_y / 2.0).tan(),
+            yaw: 0.0,
+            pitch: 0.0,
+            projection: Projection::Perspective { fov_y },
+            near: 0.1,
+            far: 100.0,
+            viewport,
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.viewport.0.max(1) as f32 / self.viewport.1.max(1) as f32
+    }
+
+    pub fn eye(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        add(self.target, scaled([cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw], self.distance))
+    }
+
+    /// Unit vectors pointing right and up on screen, in world space.
+    pub fn right_up(&self) -> ([f32; 3], [f32; 3]) {
+        let forward = normalize(sub(self.target, self.eye()));
+        let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+        (right, cross(right, forward))
+    }
+
+    pub fn view(&self) -> Matrix {
+        look_at(self.eye(), self.target, [0.0, 1.0, 0.0])
+    }
+
+    pub fn projection_matrix(&self) -> Matrix {
+        match self.projection {
+            Projection::Perspective { fov_y } => perspective(fov_y, self.aspect(), self.near, self.far),
+            Projection::Orthographic { height } => orthographic(height * self.aspect(), height, self.near, self.far),
+        }
+    }
+
+    /// World to clip space, for `Renderer::view_proj`.
+    pub fn view_proj(&self) -> Matrix {
+        multiply(&self.projection_matrix(), &self.view())
+    }
+
+    /// Look at the center of the box from `min` to `max` from far enough
+    /// away to see all of it, keeping the angles.  Fits whichever of the
+    /// width and height of `viewport` is narrower, so set that first.
+    pub fn frame(&mut self, min: [f32; 3], max: [f32; 3]) {
+        self.target = ::std::array::from_fn(|i| (min[i] + max[i]) / 2.0);
+        let radius = (0..3).map(|i| (max[i] - min[i]).powi(2)).sum::<f32>().sqrt().max(f32::EPSILON) / 2.0;
+        let aspect = self.aspect();
+        match &mut self.projection {
+            Projection::Perspective { fov_y } => {
+                let fov_x = 2.0 * ((*fov_y / 2.0).tan() * aspect).atan();
+                self.distance = radius / (fov_y.min(fov_x) / 2.0).sin();
+            },
+            Projection::Orthographic { height } => {
+                // on a portrait viewport the width is what has to fit
+                *height = 2.0 * radius * (1.0 / aspect).max(1.0);
+                self.distance = 2.0 * radius;
+            },
+        }
+        self.near = self.near.min(self.distance / 10.0);
+        self.far = self.far.max(2.0 * (self.distance + radius));
+    }
+
+    /// World units per physical pixel in the plane of the target.
+    pub fn world_per_pixel(&self) -> f32 {
+        let height = match self.projection {
+            Projection::Perspective { fov_y } => 2.0 * self.distance * (fov_y / 2.0).tan(),
+            Projection::Orthographic { height } => height,
+        };
+        height / self.viewport.1.max(1) as f32
+    }
+
+    /// Turn around the target.  Pitch is kept short of the poles.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let limit = ::std::f32::consts::FRAC_PI_2 - PITCH_MARGIN;
+        self.yaw = (self.yaw + yaw).rem_euclid(::std::f32::consts::TAU);
+        self.pitch = (self.pitch + pitch).clamp(-limit, limit);
+    }
+
+    /// Move the target so the scene follows a pointer that moved by
+    /// `dx`, `dy` physical pixels (y down).
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let (right, up) = self.right_up();
+        let step = self.world_per_pixel();
+        self.target = add(self.target, add(scaled(right, -dx * step), scaled(up, dy * step)));
+    }
+
+    /// Magnify by `factor`, above 1 zooms in.  A perspective camera moves
+    /// closer, staying between twice `near` and half of `far`; an
+    /// orthographic one shows less.
+    pub fn zoom(&mut self, factor: f32) {
+        if !(factor.is_finite() && factor > 0.0) {
+            return;
+        }
+        match &mut self.projection {
+            Projection::Perspective { .. } => self.distance = (self.distance / factor).clamp(2.0 * self.near, self.far / 2.0),
+            Projection::Orthographic { height } => *height /= factor,
+        }
+    }
+
+    /// [`OrbitCamera::zoom`] keeping what is at `screen` (physical pixels) in
+    /// the plane of the target where it is, like the point between pinching fingers.
+    pub fn zoom_at(&mut self, factor: f32, screen: PhysicalPosition<f64>) {
+        let before = self.world_per_pixel();
+        self.zoom(factor);
+        let factor = before / self.world_per_pixel();
+        let dx = screen.x as f32 - self.viewport.0 as f32 / 2.0;
+        let dy = screen.y as f32 - self.viewport.1 as f32 / 2.0;
+        self.pan(dx * (1.0 - factor), dy * (1.0 - factor));
+    }
+}
+
+/// Zoom factor per pixel of wheel scrolling.
+const WHEEL_ZOOM: f32 = 1.002;
+
+/// Turns pointer input into [`OrbitCamera`] movement: one finger or the left
+/// mouse button orbits, the right or middle button pans, two fingers pinch to
+/// zoom and pan with their midpoint, and the wheel zooms at the cursor.
+#[derive(Debug, Clone, Default)]
+pub struct OrbitController {
+    /// Pointers in contact, or the mouse with a button held, and where they were last.
+    pressed: Vec<(u64, PhysicalPosition<f64>)>,
+}
+
+impl OrbitController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move `camera` for `pointer`, true when it moved.  A drag across the
+    /// height of the viewport orbits by half a turn.
+    pub fn pointer_event(&mut self, camera: &mut OrbitCamera, pointer: &PointerEvent) -> bool {
+        match pointer.action {
+            PointerAction::Down(_) => {
+                self.release(pointer.id);
+                self.pressed.push((pointer.id, pointer.position));
+                false
+            },
+            PointerAction::Moved => self.moved(camera, pointer),
+            PointerAction::Up(_) if !pointer.buttons.is_empty() => false,
+            PointerAction::Up(_) | PointerAction::Cancelled | PointerAction::Left => {
+                self.release(pointer.id);
+                false
+            },
+            PointerAction::Wheel { delta_y, .. } => {
+                camera.zoom_at(WHEEL_ZOOM.powf(delta_y as f32), pointer.position);
+                delta_y != 0.0
+            },
+        }
+    }
+
+    /// Forget every pointer, e.g. when the window loses focus mid drag.
+    pub fn reset(&mut self) {
+        self.pressed.clear();
+    }
+
+    fn release(&mut self, id: u64) {
+        self.pressed.retain(|(pressed, _)| *pressed != id);
+    }
+
+    fn moved(&mut self, camera: &mut OrbitCamera, pointer: &PointerEvent) -> bool {
+        let Some(index) = self.pressed.iter().position(|(id, _)| *id == pointer.id) else {
+            return false;
+        };
+        if let [(_, first), (_, second), ..] = self.pressed[..] {
+            if index < 2 {
+                let (before_center, before_distance) = midpoint_distance(first, second);
+                self.pressed[index].1 = pointer.position;
+                let (_, first) = self.pressed[0];
+                let (_, second) = self.pressed[1];
+                let (center, distance) = midpoint_distance(first, second);
+                camera.pan((center.x - before_center.x) as f32, (center.y - before_center.y) as f32);
+                if before_distance > 0.0 && distance > 0.0 {
+                    camera.zoom_at((distance / before_distance) as f32, center);
+                }
+                return true;
+            }
+            return false;
+        }
+        let last = ::std::mem::replace(&mut self.pressed[index].1, pointer.position);
+        let (dx, dy) = ((pointer.position.x - last.x) as f32, (pointer.position.y - last.y) as f32);
+        if pointer.buttons.contains(PointerButton::Secondary) || pointer.buttons.contains(PointerButton::Middle) {
+            camera.pan(dx, dy);
+        } else {
+            let speed = ::std::f32::consts::PI / camera.viewport.1.max(1) as f32;
+            camera.orbit(-dx * speed, dy * speed);
+        }
+        dx != 0.0 || dy != 0.0
+    }
+}
+
+fn midpoint_distance(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> (PhysicalPosition<f64>, f64) {
+    let center = PhysicalPosition::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+    (center, (b.x - a.x).hypot(b.y - a.y))
+}
+
+/// Right handed view matrix for an eye at `eye` looking at `target`.
+pub fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Matrix {
+    let forward = normalize(sub(target, eye));
+    let right = normalize(cross(forward, up));
+    let up = cross(right, forward);
+    [
+        [right[0], up[0], -forward[0], 0.0],
+        [right[1], up[1], -forward[1], 0.0],
+        [right[2], up[2], -forward[2], 0.0],
+        [-dot(right, eye), -dot(up, eye), dot(forward, eye), 1.0],
+    ]
+}
+
+/// Right handed perspective projection with depth from 0 at `near` to 1 at `far`.
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Matrix {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+/// Right handed orthographic projection of a `width` by `height` box
+/// centered on the view axis, depth from 0 at `near` to 1 at `far`.
+pub fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Matrix {
+    [
+        [2.0 / width, 0.0, 0.0, 0.0],
+        [0.0, 2.0 / height, 0.0, 0.0],
+        [0.0, 0.0, 1.0 / (near - far), 0.0],
+        [0.0, 0.0, near / (near - far), 1.0],
+    ]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scaled(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    scaled(a, 1.0 / dot(a, a).sqrt())
+}
@@ -0,0 +1,124 @@
+use ::std::collections::HashMap;
+
+use ::log::info;
+
+use crate::gpu::GpuContext;
+
+/// Invocations per workgroup of the kernels in this crate, the
+/// `@workgroup_size` of their shaders.  Well within the 256 of
+/// `wgpu::Limits::downlevel_defaults()`.
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// True when compute kernels can run on `gpu`: the adapter has
+/// `DownlevelFlags::COMPUTE_SHADERS`, which many GLES devices lack, and the
+/// device was opened with room for storage buffers and [`WORKGROUP_SIZE`]
+/// invocations, see [`crate::config::RendererConfig::compute_limits`].
+pub fn supported(gpu: &GpuContext) -> bool {
+    let limits = gpu.device.limits();
+    gpu.adapter.get_downlevel_capabilities().flags.contains(::wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        && limits.max_storage_buffers_per_shader_stage > 0
+        && limits.max_compute_workgroup_size_x >= WORKGROUP_SIZE
+        && limits.max_compute_invocations_per_workgroup >= WORKGROUP_SIZE
+        && limits.max_compute_workgroups_per_dimension > 0
+}
+
+/// Workgroups to dispatch for `count` invocations, `workgroup_size` per
+/// workgroup.  More than `max_per_dimension` workgroups spill over into y, so
+/// kernels work out their index from `@builtin(num_workgroups)` and return
+/// early past `count`.
+pub fn workgroups(count: u32, workgroup_size: u32, max_per_dimension: u32) -> [u32; 3] {
+    let groups = count.div_ceil(workgroup_size.max(1));
+    let rows = groups.div_ceil(max_per_dimension.max(1));
+    if rows <= 1 {
+        return [groups, 1, 1];
+    }
+    [groups.div_ceil(rows), rows, 1]
+}
+
+/// Layout entry for a storage buffer read, or also written, by a kernel.
+pub fn storage_buffer(binding: u32, read_only: bool) -> ::wgpu::BindGroupLayoutEntry {
+    ::wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: ::wgpu::ShaderStages::COMPUTE,
+        ty: ::wgpu::BindingType::Buffer {
+            ty: ::wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Layout entry for a uniform buffer of a kernel.
+pub fn uniform_buffer(binding: u32) -> ::wgpu::BindGroupLayoutEntry {
+    ::wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: ::wgpu::ShaderStages::COMPUTE,
+        ty: ::wgpu::BindingType::Buffer {
+            ty: ::wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// One compute entry point with a single bind group at group 0.
+///
+/// The shader's `override WORKGROUP_SIZE: u32` is set to [`WORKGROUP_SIZE`],
+/// further overrides come in `constants`.
+///
+/// Like everything else made from a device it has to be created again when
+/// [`GpuContext::generation`] changes, and only where [`supported`] holds.
+pub struct ComputeKernel {
+    pub layout: ::wgpu::BindGroupLayout,
+    pub pipeline: ::wgpu::ComputePipeline,
+    label: String,
+}
+
+impl ComputeKernel {
+    pub fn new(gpu: &GpuContext, label: &str, shader: ::wgpu::ShaderModuleDescriptor<'_>, entry_point: &str, constants: &[(&str, f64)], entries: &[::wgpu::BindGroupLayoutEntry]) -> Self {
+        info!("creating compute kernel {} for device generation {}", label, gpu.generation);
+        let device = &gpu.device;
+        let module = device.create_shader_module(shader);
+        let layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor { label: Some(label), entries });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let constants: HashMap<String, f64> = constants.iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .chain(::std::iter::once(("WORKGROUP_SIZE".to_string(), f64::from(WORKGROUP_SIZE))))
+            .collect();
+        let pipeline = device.create_compute_pipeline(&::wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: Some(entry_point),
+            compilation_options: ::wgpu::PipelineCompilationOptions { constants: &constants, ..Default::default() },
+            cache: None,
+        });
+        Self { layout, pipeline, label: label.to_string() }
+    }
+
+    /// A bind group with `resources` at bindings 0, 1, 2 and so on.
+    pub fn bind_group(&self, gpu: &GpuContext, resources: &[::wgpu::BindingResource<'_>]) -> ::wgpu::BindGroup {
+        let entries: Vec<_> = resources.iter().enumerate()
+            .map(|(binding, resource)| ::wgpu::BindGroupEntry { binding: binding as u32, resource: resource.clone() })
+            .collect();
+        gpu.device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some(&self.label),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+
+    /// Record enough workgroups of [`WORKGROUP_SIZE`] for `count` invocations into `pass`.
+    pub fn dispatch(&self, gpu: &GpuContext, pass: &mut ::wgpu::ComputePass<'_>, bind_group: &::wgpu::BindGroup, count: u32) {
+        let [x, y, z] = workgroups(count, WORKGROUP_SIZE, gpu.device.limits().max_compute_workgroups_per_dimension);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.dispatch_workgroups(x, y, z);
+    }
+}
@@ -0,0 +1,512 @@
+use ::std::fmt;
+use ::std::path::{Path, PathBuf};
+
+use ::log::{info, warn};
+use ::serde::de::IntoDeserializer;
+use ::serde::{Deserialize, Serialize};
+
+use crate::pacing::RunMode;
+
+/// Environment variable naming a TOML file to load the [`RendererConfig`] from.
+pub const CONFIG_PATH_VAR: &str = "RENDERER_CONFIG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+    BrowserWebgpu,
+}
+
+impl From<Backend> for ::wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => ::wgpu::Backends::VULKAN,
+            Backend::Metal => ::wgpu::Backends::METAL,
+            Backend::Dx12 => ::wgpu::Backends::DX12,
+            Backend::Gl => ::wgpu::Backends::GL,
+            Backend::BrowserWebgpu => ::wgpu::Backends::BROWSER_WEBGPU,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    None,
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for ::wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::None => ::wgpu::PowerPreference::None,
+            PowerPreference::LowPower => ::wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => ::wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for ::wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::AutoVsync => ::wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => ::wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => ::wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => ::wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Immediate => ::wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => ::wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaMode {
+    Auto,
+    Opaque,
+    PreMultiplied,
+    PostMultiplied,
+    Inherit,
+}
+
+impl From<AlphaMode> for ::wgpu::CompositeAlphaMode {
+    fn from(mode: AlphaMode) -> Self {
+        match mode {
+            AlphaMode::Auto => ::wgpu::CompositeAlphaMode::Auto,
+            AlphaMode::Opaque => ::wgpu::CompositeAlphaMode::Opaque,
+            AlphaMode::PreMultiplied => ::wgpu::CompositeAlphaMode::PreMultiplied,
+            AlphaMode::PostMultiplied => ::wgpu::CompositeAlphaMode::PostMultiplied,
+            AlphaMode::Inherit => ::wgpu::CompositeAlphaMode::Inherit,
+        }
+    }
+}
+
+/// Starting point for the device limits.  Whatever is picked is raised to
+/// the adapter's maximum texture size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitsPreset {
+    /// Runs on pretty much anything, including GLES 3.0 Android devices.
+    DownlevelWebgl2,
+    Downlevel,
+    Default,
+}
+
+impl From<LimitsPreset> for ::wgpu::Limits {
+    fn from(preset: LimitsPreset) -> Self {
+        match preset {
+            LimitsPreset::DownlevelWebgl2 => ::wgpu::Limits::downlevel_webgl2_defaults(),
+            LimitsPreset::Downlevel => ::wgpu::Limits::downlevel_defaults(),
+            LimitsPreset::Default => ::wgpu::Limits::default(),
+        }
+    }
+}
+
+/// Which of the surface formats to render into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SrgbPolicy {
+    /// First sRGB format, so shaders can output linear colors.
+    Prefer,
+    /// First non-sRGB format.
+    Avoid,
+    /// Whatever the surface lists first.
+    First,
+}
+
+/// Renderer settings, loaded from a TOML file and/or `RENDERER_*`
+/// environment variables, see [`RendererConfig::load`].
+///
+/// Nothing here is trusted: [`RendererConfig::surface_settings`],
+/// [`RendererConfig::features`] and [`RendererConfig::limits`] check each
+/// setting against what the adapter supports and log when they fall back.
+///
+/// Also carries the window and run options, so the desktop command line
+/// and Android's `renderer.toml` set up the app the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendererConfig {
+    /// Backends to pick an adapter from, empty means all of them.
+    pub backends: Vec<Backend>,
+    /// Use the first adapter whose name contains this, ignoring case.
+    pub adapter: Option<String>,
+    pub power_preference: PowerPreference,
+    pub present_mode: PresentMode,
+    pub alpha_mode: AlphaMode,
+    pub frame_latency: u32,
+    /// `wgpu::Features` flag names, e.g. `"DEPTH_CLIP_CONTROL"`.
+    pub required_features: Vec<String>,
+    pub limits: LimitsPreset,
+    pub srgb: SrgbPolicy,
+    /// MSAA samples per pixel, 1 turns it off.  Lowered to what the adapter supports for the target format.
+    pub msaa_samples: u32,
+    pub window: WindowConfig,
+    pub run_mode: RunMode,
+    /// Frames per second in `fixed_rate` mode.
+    pub frame_rate: u32,
+    /// Simulation updates per second, independent of the frame rate.
+    pub update_rate: u32,
+    /// Show the frame time graph from the start, F3 or a double tap toggles it.
+    pub overlay: bool,
+    /// Request `TIMESTAMP_QUERY` when the adapter has it, to measure GPU time per frame.
+    pub gpu_timing: bool,
+    /// Request the ASTC, ETC2 and BC texture compression features the adapter has, for KTX2 textures.
+    pub compressed_textures: bool,
+    /// Raise the limits compute shaders need when the adapter runs them, see [`RendererConfig::compute_limits`].
+    pub compute_shaders: bool,
+    /// Exit after presenting this many frames, redrawing continuously until then.
+    pub frames: Option<u32>,
+    /// Render offscreen without opening a window, see [`crate::offscreen`].
+    pub headless: bool,
+    /// Write the last frame to this PNG before exiting, with `headless` or `frames`.
+    pub screenshot: Option<PathBuf>,
+    /// Record window events to `files/input.jsonl` on Android, the desktop binary takes `--record` instead.
+    pub record_input: bool,
+}
+
+/// Window options, ignored on Android where the window always fills the screen.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Inner size in physical pixels, winit's default when unset.
+    pub size: Option<(u32, u32)>,
+    /// Borderless fullscreen on the current monitor.
+    pub fullscreen: bool,
+}
+
+impl WindowConfig {
+    pub fn attributes(&self) -> ::winit::window::WindowAttributes {
+        let mut attributes = ::winit::window::WindowAttributes::default();
+        if let Some((width, height)) = self.size {
+            attributes = attributes.with_inner_size(::winit::dpi::PhysicalSize::new(width, height));
+        }
+        if self.fullscreen {
+            attributes = attributes.with_fullscreen(Some(::winit::window::Fullscreen::Borderless(None)));
+        }
+        attributes
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec![],
+            adapter: None,
+            power_preference: PowerPreference::None,
+            present_mode: PresentMode::AutoVsync,
+            alpha_mode: AlphaMode::Auto,
+            frame_latency: 2,
+            required_features: vec![],
+            limits: LimitsPreset::DownlevelWebgl2,
+            srgb: SrgbPolicy::Prefer,
+            msaa_samples: 1,
+            window: WindowConfig::default(),
+            run_mode: RunMode::OnDemand,
+            frame_rate: 60,
+            update_rate: 60,
+            overlay: false,
+            gpu_timing: true,
+            compressed_textures: true,
+            compute_shaders: true,
+            frames: None,
+            headless: false,
+            screenshot: None,
+            record_input: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(::std::io::Error),
+    Toml(::toml::de::Error),
+    /// An environment variable holds a value the setting does not accept.
+    Env { name: String, value: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read renderer config: {}", e),
+            ConfigError::Toml(e) => write!(f, "invalid renderer config: {}", e),
+            ConfigError::Env { name, value, reason } => write!(f, "invalid {}={:?}: {}", name, value, reason),
+        }
+    }
+}
+
+impl ::std::error::Error for ConfigError {}
+
+/// Surface configuration picked from the config and the surface capabilities.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceSettings {
+    pub format: ::wgpu::TextureFormat,
+    pub present_mode: ::wgpu::PresentMode,
+    pub alpha_mode: ::wgpu::CompositeAlphaMode,
+    pub frame_latency: u32,
+    /// Includes `COPY_SRC` where the surface supports it, for screenshots.
+    pub usage: ::wgpu::TextureUsages,
+}
+
+impl SurfaceSettings {
+    pub fn configuration(&self, size: (u32, u32)) -> ::wgpu::SurfaceConfiguration {
+        ::wgpu::SurfaceConfiguration {
+            format                       : self.format,
+            usage                        : self.usage,
+            width                        : size.0,
+            height                       : size.1,
+            present_mode                 : self.present_mode,
+            alpha_mode                   : self.alpha_mode,
+            view_formats                 : vec![],
+            desired_maximum_frame_latency: self.frame_latency,
+        }
+    }
+}
+
+impl RendererConfig {
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        ::toml::from_str(text).map_err(ConfigError::Toml)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let text = ::std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml(&text)
+    }
+
+    /// The file named by `RENDERER_CONFIG` (or the defaults when unset),
+    /// then any `RENDERER_*` variables on top.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match ::std::env::var_os(CONFIG_PATH_VAR) {
+            Some(path) => {
+                info!("loading renderer config from {:?}", path);
+                Self::from_file(path)?
+            },
+            None => Self::default(),
+        };
+        config.apply_env(|name| ::std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Override settings from environment variables, looked up with `var` so
+    /// tests do not have to touch the process environment.
+    ///
+    /// `RENDERER_BACKENDS` and `RENDERER_FEATURES` are comma separated lists,
+    /// the others take the same values as the TOML file.
+    pub fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<(), ConfigError> {
+        if let Some(value) = var("RENDERER_BACKENDS") {
+            self.backends = split_list(&value)
+                .map(|name| parse_value("RENDERER_BACKENDS", name))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(value) = var("RENDERER_ADAPTER") {
+            self.adapter = Some(value);
+        }
+        if let Some(value) = var("RENDERER_POWER_PREFERENCE") {
+            self.power_preference = parse_value("RENDERER_POWER_PREFERENCE", &value)?;
+        }
+        if let Some(value) = var("RENDERER_PRESENT_MODE") {
+            self.present_mode = parse_value("RENDERER_PRESENT_MODE", &value)?;
+        }
+        if let Some(value) = var("RENDERER_ALPHA_MODE") {
+            self.alpha_mode = parse_value("RENDERER_ALPHA_MODE", &value)?;
+        }
+        if let Some(value) = var("RENDERER_FRAME_LATENCY") {
+            self.frame_latency = parse_from_str("RENDERER_FRAME_LATENCY", value)?;
+        }
+        if let Some(value) = var("RENDERER_FEATURES") {
+            self.required_features = split_list(&value).map(str::to_string).collect();
+        }
+        if let Some(value) = var("RENDERER_LIMITS") {
+            self.limits = parse_value("RENDERER_LIMITS", &value)?;
+        }
+        if let Some(value) = var("RENDERER_SRGB") {
+            self.srgb = parse_value("RENDERER_SRGB", &value)?;
+        }
+        if let Some(value) = var("RENDERER_MSAA") {
+            self.msaa_samples = parse_from_str("RENDERER_MSAA", value)?;
+        }
+        if let Some(value) = var("RENDERER_RUN_MODE") {
+            self.run_mode = parse_value("RENDERER_RUN_MODE", &value)?;
+        }
+        if let Some(value) = var("RENDERER_FRAME_RATE") {
+            self.frame_rate = parse_from_str("RENDERER_FRAME_RATE", value)?;
+        }
+        if let Some(value) = var("RENDERER_UPDATE_RATE") {
+            self.update_rate = parse_from_str("RENDERER_UPDATE_RATE", value)?;
+        }
+        if let Some(value) = var("RENDERER_OVERLAY") {
+            self.overlay = parse_from_str("RENDERER_OVERLAY", value)?;
+        }
+        Ok(())
+    }
+
+    pub fn backends(&self) -> ::wgpu::Backends {
+        if self.backends.is_empty() {
+            return ::wgpu::Backends::all();
+        }
+        self.backends.iter().fold(::wgpu::Backends::empty(), |all, backend| all | ::wgpu::Backends::from(*backend))
+    }
+
+    /// The requested features the adapter has.  Unknown and unsupported ones are logged and left out.
+    pub fn features(&self, supported: ::wgpu::Features) -> ::wgpu::Features {
+        let mut features = ::wgpu::Features::empty();
+        for name in &self.required_features {
+            match ::wgpu::Features::from_name(name) {
+                Some(feature) if supported.contains(feature) => features |= feature,
+                Some(_) => warn!("adapter does not support feature {}, continuing without it", name),
+                None => warn!("unknown feature {} in renderer config, ignored", name),
+            }
+        }
+        if self.gpu_timing {
+            features |= supported & ::wgpu::Features::TIMESTAMP_QUERY;
+        }
+        if self.compressed_textures {
+            features |= supported & (::wgpu::Features::TEXTURE_COMPRESSION_ASTC
+                | ::wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                | ::wgpu::Features::TEXTURE_COMPRESSION_BC);
+        }
+        features
+    }
+
+    /// The configured limits preset, or the WebGL2 defaults when the adapter
+    /// cannot provide it.
+    pub fn limits(&self, supported: &::wgpu::Limits) -> ::wgpu::Limits {
+        let limits = ::wgpu::Limits::from(self.limits);
+        let limits = if limits.check_limits(supported) {
+            limits
+        } else {
+            warn!("adapter does not reach the {:?} limits, falling back to DownlevelWebgl2", self.limits);
+            ::wgpu::Limits::downlevel_webgl2_defaults()
+        };
+        limits.using_resolution(supported.clone())
+    }
+
+    /// `limits` with the storage buffer and compute limits raised to those of
+    /// `wgpu::Limits::downlevel_defaults()`, as far as `supported` allows.
+    /// The WebGL2 limits have no storage buffers at all, so without this the
+    /// particles would always be simulated on the CPU.  Unchanged when
+    /// `compute_shaders` is off or the adapter cannot run compute shaders.
+    pub fn compute_limits(&self, limits: ::wgpu::Limits, supported: &::wgpu::Limits, flags: ::wgpu::DownlevelFlags) -> ::wgpu::Limits {
+        if !self.compute_shaders || !flags.contains(::wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+            return limits;
+        }
+        let wanted = ::wgpu::Limits::downlevel_defaults();
+        let raise = |current: u32, wanted: u32, supported: u32| current.max(wanted.min(supported));
+        ::wgpu::Limits {
+            max_storage_buffers_per_shader_stage: raise(limits.max_storage_buffers_per_shader_stage, wanted.max_storage_buffers_per_shader_stage, supported.max_storage_buffers_per_shader_stage),
+            max_storage_buffer_binding_size: raise(limits.max_storage_buffer_binding_size, wanted.max_storage_buffer_binding_size, supported.max_storage_buffer_binding_size),
+            max_compute_workgroup_storage_size: raise(limits.max_compute_workgroup_storage_size, wanted.max_compute_workgroup_storage_size, supported.max_compute_workgroup_storage_size),
+            max_compute_invocations_per_workgroup: raise(limits.max_compute_invocations_per_workgroup, wanted.max_compute_invocations_per_workgroup, supported.max_compute_invocations_per_workgroup),
+            max_compute_workgroup_size_x: raise(limits.max_compute_workgroup_size_x, wanted.max_compute_workgroup_size_x, supported.max_compute_workgroup_size_x),
+            max_compute_workgroup_size_y: raise(limits.max_compute_workgroup_size_y, wanted.max_compute_workgroup_size_y, supported.max_compute_workgroup_size_y),
+            max_compute_workgroup_size_z: raise(limits.max_compute_workgroup_size_z, wanted.max_compute_workgroup_size_z, supported.max_compute_workgroup_size_z),
+            max_compute_workgroups_per_dimension: raise(limits.max_compute_workgroups_per_dimension, wanted.max_compute_workgroups_per_dimension, supported.max_compute_workgroups_per_dimension),
+            ..limits
+        }
+    }
+
+    /// The highest sample count up to `msaa_samples` that both the color and
+    /// the depth format support, given their format feature flags.
+    pub fn sample_count(&self, color: ::wgpu::TextureFormatFeatureFlags, depth: ::wgpu::TextureFormatFeatureFlags) -> u32 {
+        let count = [16, 8, 4, 2]
+            .into_iter()
+            .filter(|&count| count <= self.msaa_samples)
+            .find(|&count| color.sample_count_supported(count) && depth.sample_count_supported(count))
+            .unwrap_or(1);
+        if count != self.msaa_samples.max(1) {
+            warn!("{}x MSAA not supported, using {}x", self.msaa_samples, count);
+        }
+        count
+    }
+
+    /// Pick the surface settings closest to the config that the surface
+    /// supports.  `None` when the surface cannot be presented to at all.
+    pub fn surface_settings(&self, capabilities: &::wgpu::SurfaceCapabilities) -> Option<SurfaceSettings> {
+        let first = *capabilities.formats.first()?;
+        let format = match self.srgb {
+            SrgbPolicy::Prefer => capabilities.formats.iter().copied().find(::wgpu::TextureFormat::is_srgb),
+            SrgbPolicy::Avoid => capabilities.formats.iter().copied().find(|format| !format.is_srgb()),
+            SrgbPolicy::First => Some(first),
+        }.unwrap_or_else(|| {
+            warn!("no surface format matches srgb policy {:?}, falling back to {:?}", self.srgb, first);
+            first
+        });
+
+        let requested = ::wgpu::PresentMode::from(self.present_mode);
+        let present_mode = match requested {
+            // picked by wgpu from what is available
+            ::wgpu::PresentMode::AutoVsync | ::wgpu::PresentMode::AutoNoVsync => requested,
+            mode if capabilities.present_modes.contains(&mode) => mode,
+            mode => {
+                warn!("present mode {:?} not supported, falling back to AutoVsync", mode);
+                ::wgpu::PresentMode::AutoVsync
+            },
+        };
+
+        let requested = ::wgpu::CompositeAlphaMode::from(self.alpha_mode);
+        let alpha_mode = match requested {
+            ::wgpu::CompositeAlphaMode::Auto => requested,
+            mode if capabilities.alpha_modes.contains(&mode) => mode,
+            mode => {
+                warn!("alpha mode {:?} not supported, falling back to Auto", mode);
+                ::wgpu::CompositeAlphaMode::Auto
+            },
+        };
+
+        let frame_latency = if self.frame_latency == 0 {
+            warn!("frame latency must be at least 1, using 1");
+            1
+        } else {
+            self.frame_latency
+        };
+
+        let usage = ::wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (capabilities.usages & ::wgpu::TextureUsages::COPY_SRC);
+
+        Some(SurfaceSettings { format, present_mode, alpha_mode, frame_latency, usage })
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+/// Parse one of the config enums from its TOML spelling, e.g. `"auto_vsync"`.
+pub fn parse_setting<T: ::serde::de::DeserializeOwned>(value: &str) -> Result<T, String> {
+    T::deserialize(value.trim().into_deserializer()).map_err(|e: ::serde::de::value::Error| e.to_string())
+}
+
+fn parse_value<T: ::serde::de::DeserializeOwned>(name: &str, value: &str) -> Result<T, ConfigError> {
+    parse_setting(value).map_err(|reason| ConfigError::Env {
+        name: name.to_string(),
+        value: value.to_string(),
+        reason,
+    })
+}
+
+/// Numbers and booleans, which `parse_setting` does not read from strings.
+fn parse_from_str<T>(name: &str, value: String) -> Result<T, ConfigError>
+where
+    T: ::std::str::FromStr,
+    T::Err: ::std::fmt::Display,
+{
+    value.trim().parse().map_err(|e: T::Err| ConfigError::Env {
+        name: name.to_string(),
+        reason: e.to_string(),
+        value,
+    })
+}
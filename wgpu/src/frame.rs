@@ -0,0 +1,108 @@
+use ::std::time::{Duration, Instant};
+
+use ::log::info;
+use ::wgpu::SurfaceError;
+
+/// Rendering problems the app is told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuEvent {
+    /// The surface went away and is being recreated.
+    SurfaceLost,
+    /// A new surface replaced a lost one.
+    SurfaceRecreated,
+    /// The device cannot be used anymore and has to be rebuilt.
+    DeviceLost,
+}
+
+/// Something frames can be acquired from.  Implemented for a window surface
+/// in [`crate::gpu`], and by fakes in tests to simulate surface errors.
+pub trait FrameSource {
+    type Frame;
+    fn acquire(&mut self) -> Result<Self::Frame, SurfaceError>;
+    /// Apply the current size and settings to the existing surface.
+    fn reconfigure(&mut self);
+    /// Create a new surface for the same window, returns false if that failed.
+    fn recreate(&mut self) -> bool;
+}
+
+/// Outcome of [`SurfaceRecovery::acquire`].
+#[derive(Debug, PartialEq)]
+pub enum Acquired<F> {
+    Frame(F),
+    /// Nothing to draw into yet, try again at the given time.
+    RetryAt(Instant),
+    /// Nothing to draw into, try again on the next redraw.
+    RetryNextFrame,
+    /// Rendering cannot continue until the app rebuilds something.
+    Stop,
+}
+
+/// How surface errors are handled when acquiring a frame:
+///
+/// * `Outdated` - reconfigure and try again once
+/// * `Lost` - recreate the surface and try again once
+/// * `Timeout` - wait, doubling the wait for each timeout in a row
+/// * `OutOfMemory` - report the device as lost
+pub struct SurfaceRecovery {
+    pub timeout_backoff: Duration,
+    pub max_backoff: Duration,
+    consecutive_timeouts: u32,
+}
+
+impl Default for SurfaceRecovery {
+    fn default() -> Self {
+        Self {
+            timeout_backoff: Duration::from_millis(16),
+            max_backoff: Duration::from_millis(500),
+            consecutive_timeouts: 0,
+        }
+    }
+}
+
+impl SurfaceRecovery {
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.consecutive_timeouts
+    }
+
+    pub fn acquire<S: FrameSource>(&mut self, source: &mut S, now: Instant, events: &mut Vec<GpuEvent>) -> Acquired<S::Frame> {
+        let mut reconfigured = false;
+        let mut recreated = false;
+        loop {
+            let error = match source.acquire() {
+                Ok(frame) => {
+                    self.consecutive_timeouts = 0;
+                    return Acquired::Frame(frame);
+                },
+                Err(error) => error,
+            };
+            info!("cannot acquire frame: {}", error);
+            match error {
+                SurfaceError::Outdated if !reconfigured => {
+                    reconfigured = true;
+                    source.reconfigure();
+                },
+                SurfaceError::Lost if !recreated => {
+                    recreated = true;
+                    events.push(GpuEvent::SurfaceLost);
+                    if !source.recreate() {
+                        return Acquired::Stop;
+                    }
+                    events.push(GpuEvent::SurfaceRecreated);
+                },
+                SurfaceError::Timeout => {
+                    self.consecutive_timeouts += 1;
+                    let backoff = self.timeout_backoff
+                        .saturating_mul(1 << (self.consecutive_timeouts - 1).min(16))
+                        .min(self.max_backoff);
+                    return Acquired::RetryAt(now + backoff);
+                },
+                SurfaceError::OutOfMemory => {
+                    events.push(GpuEvent::DeviceLost);
+                    return Acquired::Stop;
+                },
+                // failed again right after recovering, or an unknown error
+                SurfaceError::Outdated | SurfaceError::Lost | SurfaceError::Other => return Acquired::RetryNextFrame,
+            }
+        }
+    }
+}
@@ -0,0 +1,483 @@
+use ::std::collections::HashMap;
+use ::std::hash::Hash;
+use ::std::ops::{Deref, DerefMut};
+use ::std::sync::Arc;
+use ::std::sync::atomic::{AtomicBool, Ordering};
+use ::std::time::Instant;
+
+use ::log::{info, warn};
+use ::wgpu::{Instance, Surface};
+
+use crate::config::{RendererConfig, SurfaceSettings};
+use crate::frame::{Acquired, FrameSource, GpuEvent, SurfaceRecovery};
+use crate::offscreen::{read_texture, OffscreenTarget};
+use crate::renderer::Renderer;
+use crate::resources::{Handle, ResourceRegistry};
+use crate::targets::RenderTargets;
+
+/// GPU objects that do not depend on a window.
+///
+/// Android destroys the native window whenever the app is backgrounded.
+/// Keeping this around across `suspended`/`resumed` means only the surface
+/// is rebuilt, and anything created from the device stays valid.
+pub struct GpuContext {
+    pub instance: Instance,
+    pub adapter: ::wgpu::Adapter,
+    pub device: ::wgpu::Device,
+    pub queue: ::wgpu::Queue,
+    /// Bumped whenever `device` is replaced.  Anything created from an older
+    /// device has to be created again.
+    pub generation: u64,
+    pub config: RendererConfig,
+    /// Set from the device lost callback of the current device.
+    lost: Arc<AtomicBool>,
+}
+
+impl GpuContext {
+    pub fn create_instance(config: &RendererConfig) -> Instance {
+        ::wgpu::Instance::new(&::wgpu::InstanceDescriptor {
+            backends: config.backends(),
+            ..Default::default()
+        })
+    }
+
+    /// Pick an adapter that can present to `surface` and open a device on it.
+    pub fn new(instance: Instance, surface: &Surface, config: RendererConfig) -> Self {
+        let (adapter, device, queue, lost) = Self::try_request_device(&instance, Some(surface), &config)
+            .expect("cannot create adapter and device from env or config");
+        Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            generation: 0,
+            config,
+            lost,
+        }
+    }
+
+    /// A context without any surface, for rendering offscreen.  Accepts
+    /// fallback (software) adapters, returns `None` when there is no adapter at all.
+    pub fn headless(config: RendererConfig) -> Option<Self> {
+        let instance = Self::create_instance(&config);
+        let (adapter, device, queue, lost) = Self::try_request_device(&instance, None, &config)?;
+        Some(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            generation: 0,
+            config,
+            lost,
+        })
+    }
+
+    /// True once the driver reported the device as lost (reset, crash, or
+    /// removed GPU).  Nothing drawn with it will show up until [`GpuContext::rebuild`].
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    /// Request a new adapter and device after the old one was lost.  Pass
+    /// the current surface, if there is one, so the new adapter can present to it.
+    /// Resources made from the old device have to be recreated, see [`ResourceRegistry::rebuild`].
+    ///
+    /// Returns false and keeps the lost device when there is no adapter or
+    /// device to be had, e.g. while a driver reset hides every adapter for a
+    /// moment.  Try again a little later.
+    pub fn rebuild(&mut self, surface: Option<&Surface>) -> bool {
+        info!("rebuilding GPU context, generation {}", self.generation + 1);
+        self.replace_device(surface)
+    }
+
+    /// Make sure the current adapter can present to `surface`.  A new adapter
+    /// and device are only requested when it cannot, returns true if that happened.
+    pub fn ensure_compatible(&mut self, surface: &Surface) -> bool {
+        if self.adapter.is_surface_supported(surface) {
+            return false;
+        }
+        info!("adapter {} cannot present to the new surface, requesting another", self.adapter.get_info().name);
+        self.replace_device(Some(surface))
+    }
+
+    /// Keeps the current device when no new one can be had, returns false then.
+    fn replace_device(&mut self, surface: Option<&Surface>) -> bool {
+        let (adapter, device, queue, lost) = match Self::try_request_device(&self.instance, surface, &self.config) {
+            Some(created) => created,
+            None => {
+                warn!("no adapter or device available, keeping the old device for now");
+                return false;
+            },
+        };
+        self.adapter = adapter;
+        self.device = device;
+        self.queue = queue;
+        self.lost = lost;
+        self.generation += 1;
+        true
+    }
+
+    fn try_request_device(instance: &Instance, surface: Option<&Surface>, config: &RendererConfig) -> Option<(::wgpu::Adapter, ::wgpu::Device, ::wgpu::Queue, Arc<AtomicBool>)> {
+        // WGPU_ADAPTER_NAME still wins, like initialize_adapter_from_env_or_default
+        let adapter = ::wgpu::util::initialize_adapter_from_env(instance, surface).or_else(|| {
+            let name = config.adapter.as_ref()?.to_lowercase();
+            let found = instance.enumerate_adapters(config.backends())
+                .into_iter()
+                .filter(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+                .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name));
+            if found.is_none() {
+                info!("no adapter matching {:?}, using the default", name);
+            }
+            found
+        }).or_else(|| {
+            // offscreen rendering is fine with a software adapter, e.g. on CI
+            let fallbacks: &[bool] = if surface.is_some() { &[false] } else { &[false, true] };
+            fallbacks.iter().find_map(|&force_fallback_adapter| {
+                ::futures::executor::block_on(instance.request_adapter(&::wgpu::RequestAdapterOptions {
+                    power_preference: config.power_preference.into(),
+                    force_fallback_adapter,
+                    compatible_surface: surface,
+                }))
+            })
+        })?;
+        let required_limits = config.compute_limits(
+            config.limits(&adapter.limits()),
+            &adapter.limits(),
+            adapter.get_downlevel_capabilities().flags,
+        );
+        let (device, queue) = futures::executor::block_on(async {
+            adapter.request_device(
+                &wgpu::DeviceDescriptor{
+                    label: None,
+                    required_features: config.features(adapter.features()),
+                    required_limits,
+                    memory_hints: wgpu::MemoryHints::MemoryUsage,
+                },
+                None
+            ).await
+        }).map_err(|e| info!("cannot open device on {}: {}", adapter.get_info().name, e)).ok()?;
+        info!("using adapter {:?}", adapter.get_info());
+
+        // every device gets its own flag, the old device reports being
+        // destroyed when it is dropped after a rebuild
+        let lost = Arc::new(AtomicBool::new(false));
+        let flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            info!("device lost ({:?}): {}", reason, message);
+            flag.store(true, Ordering::Release);
+        });
+
+        Some((adapter, device, queue, lost))
+    }
+}
+
+/// What a renderer made from one device: objects of its own, `T`, and render
+/// pipelines by `K`, usually the target format and sample count.  Derefs to `T`.
+pub struct DeviceObjects<T, K = (::wgpu::TextureFormat, u32)> {
+    pub generation: u64,
+    objects: T,
+    pipelines: HashMap<K, ::wgpu::RenderPipeline>,
+}
+
+impl <T, K: Copy + Eq + Hash>DeviceObjects<T, K> {
+    /// The pipeline for `key`, made by `create` the first time it is asked for.
+    pub fn ensure_pipeline(&mut self, key: K, create: impl FnOnce(&T, K) -> ::wgpu::RenderPipeline) -> &::wgpu::RenderPipeline {
+        self.pipelines.entry(key).or_insert_with(|| create(&self.objects, key))
+    }
+
+    /// A pipeline made before by [`DeviceObjects::ensure_pipeline`].
+    pub fn pipeline(&self, key: &K) -> &::wgpu::RenderPipeline {
+        &self.pipelines[key]
+    }
+}
+
+impl <T, K>Deref for DeviceObjects<T, K> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.objects
+    }
+}
+
+impl <T, K>DerefMut for DeviceObjects<T, K> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.objects
+    }
+}
+
+/// The [`DeviceObjects`] of a renderer, made on first use and again
+/// whenever [`GpuContext::generation`] changes.
+pub struct PerDevice<T, K = (::wgpu::TextureFormat, u32)> {
+    current: Option<DeviceObjects<T, K>>,
+}
+
+impl <T, K>Default for PerDevice<T, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <T, K>PerDevice<T, K> {
+    pub fn new() -> Self {
+        Self { current: None }
+    }
+
+    /// True when there are objects made from the current device of `gpu`.
+    pub fn is_current(&self, gpu: &GpuContext) -> bool {
+        self.current.as_ref().is_some_and(|objects| objects.generation == gpu.generation)
+    }
+
+    /// The objects for the current device of `gpu`, made by `create` unless there are some.
+    pub fn get_or_create(&mut self, gpu: &GpuContext, create: impl FnOnce(&GpuContext) -> T) -> &mut DeviceObjects<T, K> {
+        if !self.is_current(gpu) {
+            self.current = Some(DeviceObjects { generation: gpu.generation, objects: create(gpu), pipelines: HashMap::new() });
+        }
+        self.current.as_mut().expect("device objects were just created")
+    }
+
+    /// The objects made last, possibly from an older device.
+    pub fn get(&self) -> Option<&DeviceObjects<T, K>> {
+        self.current.as_ref()
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut DeviceObjects<T, K>> {
+        self.current.as_mut()
+    }
+
+    /// Drop the objects, the next [`PerDevice::get_or_create`] makes new ones.
+    pub fn clear(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Samplers by key, such as a [`crate::texture::SamplerPreset`], each made once.
+pub struct Samplers<K> {
+    samplers: HashMap<K, ::wgpu::Sampler>,
+}
+
+impl <K>Samplers<K> where K: Copy + Eq + Hash, ::wgpu::SamplerDescriptor<'static>: From<K> {
+    /// With the samplers for `keys` made up front.
+    pub fn new(gpu: &GpuContext, keys: impl IntoIterator<Item = K>) -> Self {
+        let mut samplers = Self { samplers: HashMap::new() };
+        for key in keys {
+            samplers.insert(gpu, key);
+        }
+        samplers
+    }
+
+    /// Make the sampler for `key`, unless there is one.
+    pub fn insert(&mut self, gpu: &GpuContext, key: K) {
+        self.samplers.entry(key).or_insert_with(|| gpu.device.create_sampler(&key.into()));
+    }
+
+    /// A sampler made before by [`Samplers::new`] or [`Samplers::insert`].
+    pub fn get(&self, key: K) -> &::wgpu::Sampler {
+        &self.samplers[&key]
+    }
+}
+
+/// A 1x1 white texture standing in for missing ones, sampling it leaves
+/// colors and factors unchanged.
+pub fn white_texture(gpu: &GpuContext) -> ::wgpu::Texture {
+    ::wgpu::util::DeviceExt::create_texture_with_data(&gpu.device, &gpu.queue, &::wgpu::TextureDescriptor {
+        label: Some("white"),
+        size: ::wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: ::wgpu::TextureDimension::D2,
+        format: ::wgpu::TextureFormat::Rgba8Unorm,
+        usage: ::wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    }, ::wgpu::util::TextureDataOrder::LayerMajor, &[255, 255, 255, 255])
+}
+
+/// Recipes for the usual resources, kept so they can be uploaded again after a device loss.
+impl ResourceRegistry<GpuContext> {
+    /// A buffer filled with `contents`.
+    pub fn buffer(&mut self, gpu: &GpuContext, label: &str, usage: ::wgpu::BufferUsages, contents: Vec<u8>) -> Handle<::wgpu::Buffer> {
+        let name = label.to_string();
+        self.register(gpu, label, move |gpu| {
+            ::wgpu::util::DeviceExt::create_buffer_init(&gpu.device, &::wgpu::util::BufferInitDescriptor {
+                label: Some(&name),
+                contents: &contents,
+                usage,
+            })
+        })
+    }
+
+    /// A texture described by `descriptor` (its label is replaced by `label`)
+    /// with `data` uploaded to the first mip level, laid out as `wgpu::util::TextureDataOrder::LayerMajor`.
+    pub fn texture(&mut self, gpu: &GpuContext, label: &str, descriptor: ::wgpu::TextureDescriptor<'static>, data: Vec<u8>) -> Handle<::wgpu::Texture> {
+        let name = label.to_string();
+        self.register(gpu, label, move |gpu| {
+            ::wgpu::util::DeviceExt::create_texture_with_data(
+                &gpu.device,
+                &gpu.queue,
+                &::wgpu::TextureDescriptor { label: Some(&name), ..descriptor.clone() },
+                ::wgpu::util::TextureDataOrder::LayerMajor,
+                &data,
+            )
+        })
+    }
+}
+
+/// The presentable surface of one window.  Dropped on suspend.
+pub struct SurfaceState<'window> {
+    /// Kept to create a new surface when the current one is lost.
+    pub target: Arc<::winit::window::Window>,
+    pub surface: Surface<'window>,
+    pub settings: SurfaceSettings,
+    pub size: (u32, u32),
+    pub configured: bool,
+    pub recovery: SurfaceRecovery,
+    /// MSAA samples the adapter supports for `settings.format`, up to the configured count.
+    pub sample_count: u32,
+    /// Depth and multisampled color textures, created with the surface configuration.
+    pub targets: Option<RenderTargets>,
+}
+
+impl <'window>SurfaceState<'window> {
+    pub fn new(target: Arc<::winit::window::Window>, surface: Surface<'window>, gpu: &GpuContext) -> Self {
+        let settings = Self::resolve_settings(&surface, gpu);
+        let size = target.inner_size();
+        Self {
+            target,
+            surface,
+            sample_count: RenderTargets::sample_count(gpu, settings.format),
+            settings,
+            size: (size.width, size.height),
+            configured: false,
+            recovery: SurfaceRecovery::default(),
+            targets: None,
+        }
+    }
+
+    fn resolve_settings(surface: &Surface, gpu: &GpuContext) -> SurfaceSettings {
+        let settings = gpu.config
+            .surface_settings(&surface.get_capabilities(&gpu.adapter))
+            .expect("adapter cannot present to surface");
+        info!("surface settings {:?}", settings);
+        settings
+    }
+
+    pub fn format(&self) -> ::wgpu::TextureFormat {
+        self.settings.format
+    }
+
+    /// Configure the surface for `size` and create render targets to match.
+    pub fn configure(&mut self, gpu: &GpuContext, size: (u32, u32)) {
+        self.size = size;
+        self.surface.configure(&gpu.device, &self.settings.configuration(size));
+        self.targets = Some(RenderTargets::new(gpu, self.settings.format, size, self.sample_count));
+        self.configured = true;
+    }
+
+    /// Replace a lost surface with a new one for the same window.
+    pub fn recreate(&mut self, gpu: &GpuContext) -> bool {
+        match gpu.instance.create_surface(self.target.clone()) {
+            Ok(surface) => {
+                self.settings = Self::resolve_settings(&surface, gpu);
+                self.sample_count = RenderTargets::sample_count(gpu, self.settings.format);
+                self.surface = surface;
+                self.configure(gpu, self.size);
+                true
+            },
+            Err(e) => {
+                info!("cannot recreate surface: {}", e);
+                self.configured = false;
+                false
+            },
+        }
+    }
+
+    /// Configure the surface for a new device, the supported settings may
+    /// differ on the new adapter.
+    pub fn reconfigure_for(&mut self, gpu: &GpuContext) {
+        self.settings = Self::resolve_settings(&self.surface, gpu);
+        self.sample_count = RenderTargets::sample_count(gpu, self.settings.format);
+        self.configure(gpu, self.size);
+    }
+
+    /// Render one frame with `renderer`, recovering from surface errors as
+    /// described on [`SurfaceRecovery`].  With `capture` set the frame is
+    /// also returned as RGBA8 pixels, see [`SurfaceState::capture`].
+    pub fn draw(&mut self, gpu: &GpuContext, renderer: &mut Renderer, resources: &ResourceRegistry<GpuContext>, capture: bool, now: Instant, events: &mut Vec<GpuEvent>) -> Acquired<Option<Vec<u8>>> {
+        let mut recovery = ::std::mem::take(&mut self.recovery);
+        let acquired = recovery.acquire(&mut WindowFrames { surface: self, gpu }, now, events);
+        self.recovery = recovery;
+        let frame = match acquired {
+            Acquired::Frame(frame) => frame,
+            Acquired::RetryAt(at) => return Acquired::RetryAt(at),
+            Acquired::RetryNextFrame => return Acquired::RetryNextFrame,
+            Acquired::Stop => return Acquired::Stop,
+        };
+        let mut encoder = gpu.device.create_command_encoder(
+            &::wgpu::CommandEncoderDescriptor { label: None },
+        );
+        let view = frame.texture.create_view(&::wgpu::TextureViewDescriptor::default());
+        let targets = self.targets.as_ref().expect("surface was configured before acquiring a frame");
+        renderer.render(gpu, resources, &mut encoder, &view, targets);
+
+        gpu.queue.submit(Some(encoder.finish()));
+        renderer.frame_submitted();
+        let pixels = capture.then(|| self.capture(gpu, renderer, resources, &frame.texture));
+        frame.present();
+        Acquired::Frame(pixels)
+    }
+
+    /// Read back a frame that was just rendered to `texture`.  Copies the
+    /// surface texture itself when it allows `COPY_SRC` and holds 8 bit RGBA
+    /// or BGRA, otherwise renders the frame again, text and egui included,
+    /// into an offscreen target.  That has the surface's format when it is
+    /// 8 bit, so egui keeps its textures; other formats lose them for a frame.
+    pub fn capture(&self, gpu: &GpuContext, renderer: &mut Renderer, resources: &ResourceRegistry<GpuContext>, texture: &::wgpu::Texture) -> Vec<u8> {
+        use ::wgpu::TextureFormat::*;
+        let copyable = self.settings.usage.contains(::wgpu::TextureUsages::COPY_SRC);
+        let (format, pixels) = match self.settings.format {
+            format @ (Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb) if copyable => (format, read_texture(gpu, texture)),
+            format => {
+                info!("cannot copy from a {:?} surface, rendering the screenshot offscreen", format);
+                let offscreen = match format {
+                    Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb => OffscreenTarget::with_format(gpu, self.size, format),
+                    _ => OffscreenTarget::new(gpu, self.size),
+                };
+                renderer.repeat_frame();
+                (offscreen.targets.format, offscreen.render(gpu, renderer, resources))
+            },
+        };
+        Self::to_rgba(format, pixels)
+    }
+
+    /// Swap the channels of BGRA `pixels`.
+    fn to_rgba(format: ::wgpu::TextureFormat, mut pixels: Vec<u8>) -> Vec<u8> {
+        if matches!(format, ::wgpu::TextureFormat::Bgra8Unorm | ::wgpu::TextureFormat::Bgra8UnormSrgb) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        pixels
+    }
+}
+
+/// [`FrameSource`] over a window surface.
+struct WindowFrames<'a, 'window> {
+    surface: &'a mut SurfaceState<'window>,
+    gpu: &'a GpuContext,
+}
+
+impl <'a, 'window>FrameSource for WindowFrames<'a, 'window> {
+    type Frame = ::wgpu::SurfaceTexture;
+
+    fn acquire(&mut self) -> Result<Self::Frame, ::wgpu::SurfaceError> {
+        self.surface.surface.get_current_texture()
+    }
+
+    fn reconfigure(&mut self) {
+        let size = self.surface.size;
+        self.surface.configure(self.gpu, size);
+    }
+
+    fn recreate(&mut self) -> bool {
+        self.surface.recreate(self.gpu)
+    }
+}
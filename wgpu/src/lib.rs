@@ -1,128 +1,710 @@
 #![allow(dead_code, unused_variables, unused_assignments)]
+use ::std::path::PathBuf;
 use ::std::sync::Arc;
+use ::std::time::{Duration, Instant};
 
+use ::android_rust_example_apk_common::gesture::{Gesture, GestureRecognizer};
+use ::android_rust_example_apk_common::headless::HeadlessApp;
+use ::android_rust_example_apk_common::navigation::{BackGesture, BackOutcome, NavigationStack, ANDROID_BUTTON_BACK, ANDROID_KEYCODE_BACK};
+use ::android_rust_example_apk_common::pointer::{PointerAction, PointerEvent, PointerTracker};
+use ::android_rust_example_apk_common::record::Recorder;
+use ::android_rust_example_apk_common::timing::FrameStats;
+use ::android_rust_example_apk_common::window::AppWindow;
 use ::log::info;
-use ::wgpu::{Color, Instance, Surface};
-use ::winit::{application::ApplicationHandler, keyboard::PhysicalKey};
+use ::winit::{application::ApplicationHandler, keyboard::{KeyCode, PhysicalKey}};
+use ::winit::event::TouchPhase;
 use ::winit::event_loop::EventLoop;
-#[allow(unused_imports)]
 use ::winit::keyboard::NativeKeyCode;
-#[allow(unused_imports)]
 use ::winit::event::ElementState;
 
-struct ApplicationState<'window> {
-    pub gfx: Option<GfxState<'window>>
+pub mod assets;
+pub mod camera;
+pub mod compute;
+pub mod config;
+pub mod frame;
+pub mod gpu;
+pub mod offscreen;
+pub mod model;
+pub mod overlay;
+pub mod pacing;
+pub mod particles;
+pub mod pbr;
+pub mod renderer;
+pub mod resources;
+pub mod sprites;
+pub mod targets;
+pub mod text;
+pub mod texture;
+pub mod timing;
+pub mod ui;
+
+use assets::Assets;
+use camera::{OrbitCamera, OrbitController};
+use config::RendererConfig;
+use frame::{Acquired, GpuEvent};
+use gpu::{GpuContext, SurfaceState};
+use pacing::{FixedTimestep, FramePacer, RunMode};
+use renderer::{multiply, rotation_z, translation, DemoScene, Matrix, Renderer};
+use resources::ResourceRegistry;
+use text::TextSection;
+use ui::Ui;
+
+/// How long to wait before asking for a device again when a lost one could not be replaced.
+pub const DEVICE_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screen {
+    Home,
+    /// Adapter details, opened from the debug panel.
+    Details,
+}
+
+pub struct ApplicationState<'window> {
+    pub gfx: Option<GfxState<'window>>,
+    /// Outlives the window, see [`GpuContext`].
+    pub gpu: Option<GpuContext>,
+    /// When to ask for a device again, set while a lost device cannot be replaced.
+    pub device_retry_at: Option<Instant>,
+    /// Everything created from `gpu.device`, rebuilt when the device is replaced.
+    pub resources: ResourceRegistry<GpuContext>,
+    pub renderer: Renderer,
+    /// Set once the demo meshes are uploaded.
+    pub demo: Option<DemoScene>,
+    /// Texture for the demo quad instead of the checkerboard, a name for [`texture::load_texture`].
+    pub demo_texture: Option<String>,
+    /// glTF model shown instead of the demo meshes, a name for [`model::load_model`].
+    pub demo_model: Option<String>,
+    /// Particles to spawn over the demo scene, pulled towards the pointer.
+    pub demo_particles: Option<usize>,
+    /// Cameras of the demo model, with their transforms relative to it.
+    pub model_cameras: Vec<(model::ModelCamera, Matrix)>,
+    /// Index into `model_cameras` to look through instead of `camera`.
+    pub model_camera: Option<usize>,
+    /// Files on disk on desktop, APK assets on Android.
+    pub assets: Assets,
+    pub animation: Animation,
+    /// Runs [`Animation::update`] at `config.update_rate`.
+    pub timestep: FixedTimestep,
+    /// Schedules frames for `config.run_mode`.
+    pub pacer: FramePacer,
+    /// Frame timings, shown by `renderer.overlay`.
+    pub stats: FrameStats,
+    /// Looks at the meshes, moved by `camera_controller`.
+    pub camera: OrbitCamera,
+    /// Where "Reset camera" goes back to, framing the demo model once there is one.
+    pub camera_home: OrbitCamera,
+    /// Bounds of the demo model, `camera_home` frames them again when the viewport changes.
+    model_bounds: Option<([f32; 3], [f32; 3])>,
+    camera_controller: OrbitController,
+    /// egui input and widget state, painted by `renderer.ui`.
+    pub ui: Ui,
+    /// Typed into the debug panel, to try the soft keyboard.
+    pub notes: String,
+    /// Set while handling an event egui consumed.
+    ui_captured: bool,
+    /// Used when the GPU context is first created.
+    pub config: RendererConfig,
+    pub navigation: NavigationStack<Screen>,
+    pub gestures: GestureRecognizer,
+    pub pointers: PointerTracker,
+    pub recorder: Option<Recorder>,
+    /// Where screenshots go unless `config.screenshot` names a file.
+    pub screenshot_dir: PathBuf,
+    /// Capture the next frame, set by F12 or a long-press.
+    pub screenshot_requested: bool,
+    /// Set when the app wants the event loop to stop.
+    pub exit_requested: bool,
+    /// Reports the safe area for `ui.insets`.
+    #[cfg(target_os = "android")]
+    pub android_app: Option<::android_activity::AndroidApp>,
 }
-struct GfxState<'window> {
-    pub window: Arc<winit::window::Window>,
-    pub surface_configured: bool,
-    pub surface: ::wgpu::Surface<'window>,
-    pub adapter: ::wgpu::Adapter,
-    pub device: ::wgpu::Device,
-    pub queue: ::wgpu::Queue,
+pub struct GfxState<'window> {
+    pub window: Arc<dyn AppWindow>,
+    /// `None` when running against a headless window.
+    pub surface: Option<SurfaceState<'window>>,
     pub cursor_position: ::winit::dpi::PhysicalPosition<f64>,
-    pub format: ::wgpu::TextureFormat,
     pub did_resize: bool,
     pub size: (u32, u32),
+    /// Redraw once this time is reached, set while backing off from surface timeouts.
+    pub redraw_at: Option<Instant>,
+    /// Frames presented to this window.
+    pub frames: u64,
+}
+
+/// The demo triangle spinning, updated at a fixed rate.
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    /// Radians, before and after the last update.
+    pub previous_angle: f32,
+    pub angle: f32,
 }
+
+impl Animation {
+    /// Radians per second.
+    pub const SPEED: f32 = 1.0;
+
+    pub fn update(&mut self, step: ::std::time::Duration) {
+        self.previous_angle = self.angle;
+        self.angle = (self.angle + Self::SPEED * step.as_secs_f32()) % ::std::f32::consts::TAU;
+        if self.angle < self.previous_angle {
+            // wrapped around, keep interpolating forwards
+            self.previous_angle -= ::std::f32::consts::TAU;
+        }
+    }
+
+    /// The angle `alpha` of the way from the previous update to the last one.
+    pub fn interpolated(&self, alpha: f32) -> f32 {
+        self.previous_angle + (self.angle - self.previous_angle) * alpha
+    }
+}
+
+impl <'window>Default for ApplicationState<'window> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl <'window>ApplicationState<'window>  {
     pub fn new () -> Self {
+        let config = RendererConfig::default();
         Self {
             gfx: None,
+            gpu: None,
+            device_retry_at: None,
+            resources: ResourceRegistry::new(),
+            renderer: Renderer::new(),
+            demo: None,
+            demo_texture: None,
+            demo_model: None,
+            demo_particles: None,
+            model_cameras: vec![],
+            model_camera: None,
+            assets: Assets::default(),
+            animation: Animation::default(),
+            timestep: FixedTimestep::from_rate(config.update_rate),
+            pacer: FramePacer::new(config.run_mode, config.frame_rate),
+            stats: FrameStats::new(),
+            camera: OrbitCamera::default(),
+            camera_home: OrbitCamera::default(),
+            model_bounds: None,
+            camera_controller: OrbitController::new(),
+            ui: Ui::new(),
+            notes: String::new(),
+            ui_captured: false,
+            config,
+            navigation: NavigationStack::new(Screen::Home),
+            gestures: GestureRecognizer::default(),
+            pointers: PointerTracker::new(),
+            recorder: None,
+            screenshot_dir: PathBuf::from("."),
+            screenshot_requested: false,
+            exit_requested: false,
+            #[cfg(target_os = "android")]
+            android_app: None,
         }
     }
-}
-impl <'window>ApplicationState<'window> {
-    fn init_wgpu(&mut self, instance: &Instance, surface: &Surface) -> (wgpu::TextureFormat, wgpu::Adapter, wgpu::Device, wgpu::Queue) {
-        let adapter = ::futures::executor::block_on(async {
-             ::wgpu::util::initialize_adapter_from_env_or_default(
-                instance,
-                Some(surface)
-            ).await.expect("cannot create adapter from env or default")
+
+    pub fn with_config(&mut self, config: RendererConfig) -> &mut Self {
+        self.timestep = FixedTimestep::from_rate(config.update_rate);
+        self.pacer = FramePacer::new(config.run_mode, config.frame_rate);
+        self.renderer.overlay.visible = config.overlay;
+        self.config = config;
+        self
+    }
+
+    /// Write every window event to `recorder`, see [`record::replay`](::android_rust_example_apk_common::record::replay).
+    pub fn with_recorder(&mut self, recorder: Recorder) -> &mut Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn push_screen(&mut self, screen: Screen) {
+        self.navigation.push(screen);
+    }
+
+    fn apply_back_outcome(&mut self, outcome: BackOutcome<Screen>) {
+        match outcome {
+            BackOutcome::Popped(screen) => info!("left screen {:?}, now on {:?}", screen, self.navigation.current()),
+            BackOutcome::ConfirmExit => info!("press back again to exit"),
+            BackOutcome::Exit => self.exit_requested = true,
+        }
+    }
+
+    fn on_pointer(&mut self, pointer: PointerEvent, now: Instant) {
+        if let Some(gfx) = self.gfx.as_mut() {
+            gfx.cursor_position = pointer.position;
+            if let Some(demo) = self.demo {
+                let position = self.renderer.sprites.camera.screen_to_world(pointer.position);
+                let cursor = self.renderer.sprites.sprite_mut(demo.cursor);
+                cursor.position = position;
+                cursor.visible = true;
+                gfx.window.request_redraw();
+            }
+        }
+        // a touch on a widget is not a gesture, and ends any that was in progress
+        let pointer = match pointer.contact_phase() {
+            Some(_) if self.ui_captured => PointerEvent { action: PointerAction::Cancelled, ..pointer },
+            _ => pointer,
+        };
+        self.renderer.particles.attractor = match pointer.contact_phase() {
+            Some(TouchPhase::Started | TouchPhase::Moved) => Some([pointer.position.x as f32, pointer.position.y as f32]),
+            Some(_) => None,
+            None => self.renderer.particles.attractor,
+        };
+        if !self.ui_captured || pointer.action == PointerAction::Cancelled {
+            if let Some(gfx) = self.gfx.as_ref() {
+                let size = gfx.window.inner_size();
+                self.camera.viewport = (size.width, size.height);
+                if self.camera_controller.pointer_event(&mut self.camera, &pointer) {
+                    gfx.window.request_redraw();
+                }
+            }
+        }
+        for gesture in self.gestures.pointer_event(&pointer, now) {
+            self.on_gesture(gesture);
+        }
+    }
+
+    fn on_gesture(&mut self, gesture: Gesture) {
+        info!("gesture {:?}", gesture);
+        match gesture {
+            Gesture::LongPress { .. } => self.request_screenshot(),
+            Gesture::DoubleTap { .. } => self.toggle_overlay(),
+            _ => (),
+        }
+    }
+
+    /// Show or hide the frame time graph.
+    pub fn toggle_overlay(&mut self) {
+        self.renderer.overlay.visible = !self.renderer.overlay.visible;
+        if let Some(gfx) = self.gfx.as_ref() {
+            gfx.window.request_redraw();
+        }
+    }
+
+    pub fn advance_to(&mut self, now: Instant) {
+        for gesture in self.gestures.poll(now) {
+            self.on_gesture(gesture);
+        }
+    }
+
+    /// Build this frame's egui output for `renderer.ui`.
+    fn run_ui(&mut self, now: Instant) {
+        let Some(window) = self.gfx.as_ref().and_then(|gfx| gfx.surface.as_ref()).map(|surface| surface.target.clone()) else {
+            return;
+        };
+        if self.renderer.ui.take_textures_lost() {
+            self.ui.reset(Some(&window));
+        }
+        #[cfg(target_os = "android")]
+        if let Some(app) = self.android_app.as_ref() {
+            let content = app.content_rect();
+            let size = window.inner_size();
+            self.ui.insets = ui::Insets::outside((size.width, size.height), [content.left, content.top, content.right, content.bottom]);
+        }
+        let renderer = &mut self.renderer;
+        let notes = &mut self.notes;
+        let camera = &mut self.camera;
+        let camera_home = self.camera_home;
+        let (model_cameras, model_camera) = (self.model_cameras.len(), &mut self.model_camera);
+        let simulation = renderer.particles.simulation();
+        let pacing = format!("{:?}, {} fps, {} updates/s", self.pacer.mode, self.config.frame_rate, self.config.update_rate);
+        let on_details = *self.navigation.current() == Screen::Details;
+        let adapter = self.gpu.as_ref().map(|gpu| gpu.adapter.get_info());
+        let (mut open_details, mut close_details) = (false, false);
+        let frame = self.ui.run(&window, |ctx| {
+            ::egui::Window::new("Renderer")
+                .default_open(false)
+                .default_pos([16.0, 48.0])
+                .show(ctx, |ui| {
+                    ui.label(pacing.as_str());
+                    ui.checkbox(&mut renderer.overlay.visible, "Frame times (F3)");
+                    ui.horizontal(|ui| {
+                        let color = renderer.clear_color;
+                        let mut rgb = [color.r as f32, color.g as f32, color.b as f32];
+                        if ui.color_edit_button_rgb(&mut rgb).changed() {
+                            renderer.clear_color = ::wgpu::Color { r: rgb[0].into(), g: rgb[1].into(), b: rgb[2].into(), a: color.a };
+                        }
+                        ui.label("Clear color");
+                    });
+                    ui.horizontal(|ui| {
+                        let mut orthographic = matches!(camera.projection, camera::Projection::Orthographic { .. });
+                        if ui.checkbox(&mut orthographic, "Orthographic").changed() {
+                            // same size in the plane of the target
+                            let height = camera.world_per_pixel() * camera.viewport.1.max(1) as f32;
+                            let fov_y = ::std::f32::consts::FRAC_PI_4;
+                            camera.projection = match orthographic {
+                                true => camera::Projection::Orthographic { height },
+                                false => camera::Projection::Perspective { fov_y },
+                            };
+                            if !orthographic {
+                                camera.distance = height / 2.0 / (fov_y / 2.0).tan();
+                            }
+                        }
+                        if ui.button("Reset camera").clicked() {
+                            *camera = OrbitCamera { viewport: camera.viewport, ..camera_home };
+                        }
+                    });
+                    if model_cameras > 0 {
+                        ::egui::ComboBox::from_label("View")
+                            .selected_text(model_camera.map_or("Orbit".to_string(), |index| format!("Model camera {}", index)))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(model_camera, None, "Orbit");
+                                for index in 0..model_cameras {
+                                    ui.selectable_value(model_camera, Some(index), format!("Model camera {}", index));
+                                }
+                            });
+                    }
+                    if !renderer.particles.is_empty() {
+                        ui.horizontal(|ui| {
+                            let label = format!("{} particles", renderer.particles.len());
+                            ui.checkbox(&mut renderer.particles.visible, label);
+                            match simulation {
+                                Some(particles::Simulation::Gpu) => ui.label("compute shader"),
+                                Some(particles::Simulation::Cpu) => ui.label("CPU fallback"),
+                                None => ui.label(""),
+                            };
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Notes");
+                        ui.text_edit_singleline(notes);
+                    });
+                    if !on_details && ui.button("Details").clicked() {
+                        open_details = true;
+                    }
+                });
+            if on_details {
+                // closing it is the same as pressing back
+                let mut open = true;
+                ::egui::Window::new("Details")
+                    .open(&mut open)
+                    .show(ctx, |ui| {
+                        match adapter.as_ref() {
+                            Some(info) => {
+                                ui.label(format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type));
+                                ui.label(format!("{} {}", info.driver, info.driver_info));
+                            },
+                            None => {
+                                ui.label("no adapter");
+                            },
+                        }
+                    });
+                close_details |= !open;
+            }
         });
-        let capabilities = surface.get_capabilities(&adapter);
-        let format = capabilities.formats
-            .iter()
-            .copied()
-            .find(wgpu::TextureFormat::is_srgb)
-            .or_else (|| capabilities.formats.first().copied())
-            .expect("get preferred format");
-        let (device, queue) = futures::executor::block_on(async {
-            adapter.request_device(
-                &wgpu::DeviceDescriptor{
-                    label: None,
-                    // required_features: adapter.features(),
-                    required_features: ::wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::downlevel_webgl2_defaults()
-                        .using_resolution(adapter.limits()),
-                    memory_hints: wgpu::MemoryHints::MemoryUsage,
+        if open_details {
+            self.push_screen(Screen::Details);
+        }
+        if close_details {
+            self.navigate_back(now);
+        }
+        if let Some(frame) = frame {
+            if frame.repaint {
+                window.request_redraw();
+            }
+            self.renderer.ui.set_frame(frame);
+        }
+    }
+
+    fn create_demo_scene(&mut self, gpu: &GpuContext) {
+        let demo = self.renderer.demo_scene(gpu, &mut self.resources);
+        if let Some(name) = self.demo_texture.as_deref() {
+            match texture::load_texture(gpu, &mut self.resources, &self.assets, name, texture::TextureOptions::default()) {
+                Ok(loaded) => self.renderer.set_texture(demo.quad, &loaded),
+                Err(e) => info!("keeping the checkerboard, {}: {}", name, e),
+            }
+        }
+        if let Some(name) = self.demo_model.as_deref() {
+            match model::load_model(&self.assets, name) {
+                Ok(loaded) => {
+                    self.renderer.models.clear();
+                    self.renderer.models.add(gpu, &mut self.resources, &loaded);
+                    self.renderer.mesh_mut(demo.triangle).visible = false;
+                    self.renderer.mesh_mut(demo.quad).visible = false;
+                    // framed for the real viewport in `apply_camera`
+                    self.model_bounds = loaded.bounds();
+                    self.model_cameras = loaded.world_transforms().into_iter()
+                        .filter_map(|(node, world)| loaded.nodes[node].camera.map(|camera| (loaded.cameras[camera], world)))
+                        .collect();
                 },
-                None
-            ).await.expect("Request device")
+                Err(e) => info!("keeping the demo meshes, {}: {}", name, e),
+            }
+        }
+        if let Some(count) = self.demo_particles {
+            self.renderer.particles.spawn(count);
+        }
+        self.demo = Some(demo);
+        match text::load_font(&self.assets, text::DEFAULT_FONT) {
+            Ok(font) => self.renderer.text.font = Some(font),
+            Err(e) => info!("drawing without text, {}: {}", text::DEFAULT_FONT, e),
+        }
+    }
+
+    /// Run the updates due at `now` and move the meshes to where they are
+    /// between the last two updates.  Nothing moves in `on_demand` mode,
+    /// there would be no frames to show it.
+    fn animate(&mut self, now: Instant) {
+        if self.pacer.mode == RunMode::OnDemand {
+            return;
+        }
+        let steps = self.timestep.advance(now);
+        for _ in 0..steps.count {
+            self.animation.update(self.timestep.step);
+            self.renderer.particles.advance(self.timestep.step);
+        }
+        self.apply_animation(steps.alpha);
+    }
+
+    fn apply_animation(&mut self, alpha: f32) {
+        if let Some(demo) = self.demo {
+            let angle = self.animation.interpolated(alpha);
+            self.renderer.mesh_mut(demo.triangle).transform = multiply(&translation(-0.45, 0.0, 0.0), &rotation_z(angle));
+        }
+    }
+
+    /// Look through `camera`, or the selected model camera, at a `viewport` sized target.
+    fn apply_camera(&mut self, viewport: (u32, u32)) {
+        if let Some((min, max)) = self.model_bounds.filter(|_| self.camera_home.viewport != viewport) {
+            // a camera nobody moved yet keeps framing the model
+            let untouched = OrbitCamera { viewport: self.camera_home.viewport, ..self.camera } == self.camera_home;
+            self.camera_home.viewport = viewport;
+            self.camera_home.frame(min, max);
+            if untouched {
+                self.camera = self.camera_home;
+            }
+        }
+        self.camera.viewport = viewport;
+        self.renderer.view_proj = self.camera.view_proj();
+        self.renderer.eye = self.camera.eye();
+        if let Some((camera, world)) = self.model_camera.and_then(|index| self.model_cameras.get(index)) {
+            self.renderer.view_proj = camera.view_proj(world, self.camera.aspect());
+            self.renderer.eye = [world[3][0], world[3][1], world[3][2]];
+        }
+    }
+
+    /// Save the next frame as a PNG, see [`ApplicationState::save_screenshot`].
+    pub fn request_screenshot(&mut self) {
+        match self.gfx.as_ref() {
+            Some(gfx) if gfx.surface.is_some() => {
+                self.screenshot_requested = true;
+                gfx.window.request_redraw();
+            },
+            _ => info!("no surface to take a screenshot of"),
+        }
+    }
+
+    /// Write a captured frame to `config.screenshot`, or to a new timestamped
+    /// file in `screenshot_dir` when no file was configured.
+    pub fn save_screenshot(&self, size: (u32, u32), pixels: &[u8]) {
+        let path = self.config.screenshot.clone().unwrap_or_else(|| {
+            let millis = ::std::time::SystemTime::now()
+                .duration_since(::std::time::UNIX_EPOCH)
+                .map(|since| since.as_millis())
+                .unwrap_or_default();
+            self.screenshot_dir.join(format!("screenshot-{}.png", millis))
         });
+        match offscreen::write_png(&path, size, pixels) {
+            Ok(()) => info!("saved screenshot to {}", path.display()),
+            Err(e) => info!("cannot save screenshot to {}: {}", path.display(), e),
+        }
+    }
+
+    fn on_gpu_event(&mut self, event: GpuEvent, now: Instant) {
+        info!("gpu event {:?}", event);
+        if event == GpuEvent::DeviceLost {
+            self.recover_device(now);
+        }
+    }
+
+    /// Replace a lost device: new adapter, device and queue, then every
+    /// registered resource and the surface configuration on top of them.
+    /// Without any device to be had this tries again after
+    /// [`DEVICE_RETRY_INTERVAL`], see `device_retry_at`.
+    pub fn recover_device(&mut self, now: Instant) {
+        let gpu = match self.gpu.as_mut() {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        if self.device_retry_at.is_some_and(|at| at > now) {
+            return;
+        }
+        let surface = self.gfx.as_mut().and_then(|gfx| gfx.surface.as_mut());
+        if !gpu.rebuild(surface.as_ref().map(|surface| &surface.surface)) {
+            self.device_retry_at = Some(now + DEVICE_RETRY_INTERVAL);
+            return;
+        }
+        self.device_retry_at = None;
+        self.resources.rebuild(gpu);
+        if let Some(surface) = surface {
+            surface.reconfigure_for(gpu);
+        }
+        if let Some(gfx) = self.gfx.as_ref() {
+            gfx.window.request_redraw();
+        }
+    }
+
+    /// Render `config.frames` frames (at least one) of `config.window.size`
+    /// offscreen and return the last one as RGBA8 pixels.  Creates a GPU
+    /// context without a surface if there is none yet, `None` when no adapter is available.
+    pub fn render_headless(&mut self) -> Option<((u32, u32), Vec<u8>)> {
+        if self.gpu.is_none() {
+            let gpu = GpuContext::headless(self.config.clone())?;
+            self.create_demo_scene(&gpu);
+            self.gpu = Some(gpu);
+        }
+        let size = self.config.window.size.unwrap_or((800, 600));
+        let target = offscreen::OffscreenTarget::new(self.gpu.as_ref().expect("gpu context was just ensured"), size);
+        let mut pixels = vec![];
+        for frame in 0..self.config.frames.unwrap_or(1).max(1) {
+            // one update per frame after the first, so the output does not depend on how fast the GPU is
+            if frame > 0 && self.pacer.mode != RunMode::OnDemand {
+                self.animation.update(self.timestep.step);
+                self.renderer.particles.advance(self.timestep.step);
+                self.apply_animation(1.0);
+            }
+            self.apply_camera(size);
+            let gpu = self.gpu.as_ref().expect("gpu context was just ensured");
+            pixels = target.render(gpu, &mut self.renderer, &self.resources);
+        }
+        Some((size, pixels))
+    }
 
-        (
-            format,
-            adapter,
-            device,
-            queue,
-        )
+    /// With a frame limit configured keep redrawing until it is reached, then exit.
+    fn check_frame_limit(&mut self) {
+        let (limit, gfx) = match (self.config.frames, self.gfx.as_ref()) {
+            (Some(limit), Some(gfx)) => (limit, gfx),
+            _ => return,
+        };
+        if gfx.frames < u64::from(limit) {
+            gfx.window.request_redraw();
+            return;
+        }
+        info!("presented {} frames, exiting", gfx.frames);
+        self.exit_requested = true;
+    }
+
+    pub fn navigate_back(&mut self, now: Instant) {
+        let outcome = self.navigation.back_at(now);
+        self.apply_back_outcome(outcome);
     }
 
+    pub fn back_gesture(&mut self, gesture: BackGesture, now: Instant) {
+        if let Some(outcome) = self.navigation.back_gesture_at(gesture, now) {
+            self.apply_back_outcome(outcome);
+        }
+    }
+}
+impl <'window>ApplicationState<'window> {
+    /// Create a surface for `window`.  The GPU context is created on first
+    /// use and reused afterwards, so resuming only rebuilds the surface.
     pub fn ensure_render_state_for_surface(&mut self, window: Arc<::winit::window::Window>) {
-        let instance = ::wgpu::Instance::new(&::wgpu::InstanceDescriptor {
-            backends: ::wgpu::Backends::all(),
-            ..Default::default()
-        });
+        let instance = match self.gpu.as_ref() {
+            Some(gpu) => gpu.instance.clone(),
+            None => GpuContext::create_instance(&self.config),
+        };
         let surface = instance.create_surface(window.clone()).expect("create surface from window");
+        match self.gpu.as_mut() {
+            Some(gpu) => {
+                if gpu.ensure_compatible(&surface) {
+                    info!("GPU context replaced for the new surface");
+                    self.resources.rebuild(gpu);
+                }
+            },
+            None => {
+                let gpu = GpuContext::new(instance, &surface, self.config.clone());
+                self.create_demo_scene(&gpu);
+                self.gpu = Some(gpu);
+            },
+        }
+        let gpu = self.gpu.as_ref().expect("gpu context was just ensured");
 
-        let (format, adapter, device, queue) = self.init_wgpu(&instance, &surface);
+        let physical_size = window.inner_size();
+        self.renderer.text.set_scale_factor(window.scale_factor() as f32);
+        self.ui.attach(&window);
+        let mut surface = SurfaceState::new(window.clone(), surface, gpu);
+        surface.configure(gpu, (physical_size.width, physical_size.height));
+
+        self.resume_with_window(window);
+        if let Some(gfx) = self.gfx.as_mut() {
+            gfx.surface = Some(surface);
+        }
+    }
 
+    /// Track `window` for input and sizing.  Rendering is set up separately
+    /// by [`ApplicationState::ensure_render_state_for_surface`].
+    pub fn resume_with_window(&mut self, window: Arc<dyn AppWindow>) {
         let physical_size = window.inner_size();
-        surface.configure(
-            &device,
-            &wgpu::SurfaceConfiguration {
-                usage                        : ::wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format                       : format,
-                width                        : physical_size.width,
-                height                       : physical_size.height,
-                present_mode                 : ::wgpu::PresentMode::AutoVsync,
-                alpha_mode                   : ::wgpu::CompositeAlphaMode::Auto,
-                view_formats                 : vec![],
-                desired_maximum_frame_latency: 10,
-            },
-        );
+        // time spent suspended is not simulated
+        self.timestep.reset();
+        self.pacer.reset();
+        self.stats.pause();
         self.gfx = Some(GfxState{
-            window: window.clone(),
-            surface_configured: true,
-            surface,
-            adapter,
-            device,
-            queue,
+            window,
+            surface: None,
             cursor_position: ::winit::dpi::PhysicalPosition::<f64> {x: 0.0, y: 0.0},
-            format,
             did_resize: false,
             size: (physical_size.width, physical_size.height),
-
+            redraw_at: None,
+            frames: 0,
         });
     }
+
+    /// The window is gone.  Drop its surface but keep the GPU context.
+    pub fn suspend(&mut self) {
+        self.gfx = None;
+        self.ui.detach();
+        self.camera_controller.reset();
+    }
 }
 impl <'window>ApplicationHandler for ApplicationState<'window> {
-    // fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-    //     if self.gfx.is_none() {
-    //         event_loop.exit();
-    //     }
-    // }
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let now = Instant::now();
+        self.advance_to(now);
+        // the device lost callback may fire while nothing is being drawn
+        if self.gpu.as_ref().is_some_and(GpuContext::is_lost) {
+            self.recover_device(now);
+        }
+        // backing off after a surface timeout
+        let mut redraw_at = self.gfx.as_ref().and_then(|gfx| gfx.redraw_at);
+        if redraw_at.is_some_and(|at| at <= now) {
+            if let Some(gfx) = self.gfx.as_mut() {
+                gfx.redraw_at = None;
+                gfx.window.request_redraw();
+            }
+            redraw_at = None;
+        }
+        // the next frame in `fixed_rate` mode
+        let mut next_frame_at = self.pacer.next_frame_at().filter(|_| self.gfx.is_some());
+        if next_frame_at.is_some_and(|at| at <= now) {
+            if let Some(gfx) = self.gfx.as_ref() {
+                gfx.window.request_redraw();
+            }
+            next_frame_at = None;
+        }
+        // and in time to report a long-press on a resting finger
+        // and to ask for a device again after a failed recovery
+        let deadlines = redraw_at.into_iter().chain(next_frame_at).chain(self.gestures.next_deadline()).chain(self.device_retry_at);
+        match deadlines.min() {
+            Some(at) => event_loop.set_control_flow(::winit::event_loop::ControlFlow::WaitUntil(at)),
+            None => event_loop.set_control_flow(::winit::event_loop::ControlFlow::Wait),
+        }
+    }
 
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         info!("Window resumed");
         let window = Arc::new(
-            event_loop.create_window(winit::window::WindowAttributes::default())
+            event_loop.create_window(self.config.window.attributes())
                 .expect("cannot create window")
         );
         self.ensure_render_state_for_surface(window);
     }
 
+    fn suspended(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        info!("Window suspended, dropping surface");
+        self.suspend();
+    }
+
     fn window_event(
         &mut self,
         event_loop: &winit::event_loop::ActiveEventLoop,
@@ -132,72 +714,66 @@ impl <'window>ApplicationHandler for ApplicationState<'window> {
         if self.gfx.is_none() {
             return;
         }
-        let window = match self.gfx.as_ref() {
-            Some(gfx) => gfx.window.clone(),
-            None => return,
-        };
-        let GfxState {
-            window,
-            surface_configured,
-            surface,
-            adapter,
-            device,
-            queue,
-            cursor_position,
-            format,
-            did_resize,
-            size,
-        } = self.gfx.as_mut().expect("cannot destruct gfxstate, even though it is not none");
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(&event) {
+                info!("stopped recording input: {}", e);
+                self.recorder = None;
+            }
+        }
+        let window = self.gfx.as_ref().and_then(|gfx| gfx.surface.as_ref()).map(|surface| surface.target.clone());
+        if let Some(window) = window {
+            let response = self.ui.on_window_event(&window, &event);
+            if response.repaint {
+                window.request_redraw();
+            }
+            self.ui_captured = response.consumed;
+        }
+        self.handle_window_event(event, Instant::now());
+        self.ui_captured = false;
+        if self.exit_requested {
+            event_loop.exit();
+        }
+    }
+}
+
+impl <'window>ApplicationState<'window> {
+    /// Apply one window event.  Does not need a running event loop, which
+    /// lets recordings be replayed without a window.
+    pub fn handle_window_event(&mut self, event: winit::event::WindowEvent, now: Instant) {
+        if let Some(pointer) = self.pointers.window_event(&event) {
+            self.on_pointer(pointer, now);
+        }
 
         match event {
             ::winit::event::WindowEvent::CloseRequested => {
                 self.gfx = None;
-                event_loop.exit();
-                ()
-            },
-            ::winit::event::WindowEvent::Touch(touch) => {
-                *cursor_position = touch.location;
+                self.exit_requested = true;
             },
+            // pointer input is handled by `on_pointer` before this match
+            ::winit::event::WindowEvent::Touch(touch) => (),
+            // typed into a text field
+            ::winit::event::WindowEvent::KeyboardInput { .. } if self.ui_captured => (),
             ::winit::event::WindowEvent::KeyboardInput { device_id, event, is_synthetic } => {
-                #[cfg(target_os="android")]
-                if let PhysicalKey::Unidentified(ncode) = event.physical_key {
-                    match ncode {
-                        NativeKeyCode::Android(val) if val == ::android_activity::input::Button::Back.into() => {
-                            match event.state {
-                                ElementState::Pressed => info!("back motion motioned"),
-                                ElementState::Released => info!("back motion released"),
-                            }
-                        },
-                        NativeKeyCode::Android(val) if val == ::android_activity::input::Keycode::Back.into() => {
-                            match event.state {
-                                ElementState::Pressed => info!("back button pressed"),
-                                ElementState::Released => event_loop.exit(),
-                            }
-                        },
-                        _ => ()
-                    }
-                }
-                if let PhysicalKey::Code(key_code) = event.physical_key {
-                    if event.state.is_pressed() {
-                        info!("key pressed {}", key_code as u32);
-                    } else {
-                        info!("key released {}", key_code as u32);
-                    }
-                }
+                self.key_input(event.physical_key, event.state, now);
             },
             ::winit::event::WindowEvent::ActivationTokenDone { serial, token } => (),
             ::winit::event::WindowEvent::Resized(physical_size) => {
                 info!("resized {} x {}", physical_size.width, physical_size.height);
-                // size = (physical_size.width, physical_size.height);
-                *did_resize = true;
-                window.request_redraw();
+                if let Some(gfx) = self.gfx.as_mut() {
+                    gfx.did_resize = true;
+                    gfx.window.request_redraw();
+                }
             },
             ::winit::event::WindowEvent::Moved(physical_position) => (),
             ::winit::event::WindowEvent::Destroyed => (),
             ::winit::event::WindowEvent::DroppedFile(path_buf) => (),
             ::winit::event::WindowEvent::HoveredFile(path_buf) => (),
             ::winit::event::WindowEvent::HoveredFileCancelled => (),
-            ::winit::event::WindowEvent::Focused(_) => (),
+            ::winit::event::WindowEvent::Focused(false) => {
+                // the release of a drag may go to another window
+                self.camera_controller.reset();
+            },
+            ::winit::event::WindowEvent::Focused(true) => (),
             ::winit::event::WindowEvent::ModifiersChanged(modifiers) => (),
             ::winit::event::WindowEvent::Ime(ime) => (),
             ::winit::event::WindowEvent::CursorMoved { device_id, position } => (),
@@ -211,90 +787,175 @@ impl <'window>ApplicationHandler for ApplicationState<'window> {
             ::winit::event::WindowEvent::RotationGesture { device_id, delta, phase } => (),
             ::winit::event::WindowEvent::TouchpadPressure { device_id, pressure, stage } => (),
             ::winit::event::WindowEvent::AxisMotion { device_id, axis, value } => (),
-            ::winit::event::WindowEvent::ScaleFactorChanged { scale_factor, inner_size_writer } => (),
+            ::winit::event::WindowEvent::ScaleFactorChanged { scale_factor, inner_size_writer } => {
+                self.renderer.text.set_scale_factor(scale_factor as f32);
+                if let Some(gfx) = self.gfx.as_ref() {
+                    gfx.window.request_redraw();
+                }
+            },
             ::winit::event::WindowEvent::ThemeChanged(theme) => (),
             ::winit::event::WindowEvent::Occluded(_) => (),
             ::winit::event::WindowEvent::RedrawRequested => {
-                   if *did_resize {
-                        let size = window.inner_size();
-
-                        surface.configure(
-                            device,
-                            &wgpu::SurfaceConfiguration {
-                                format                       : *format,
-                                usage                        : ::wgpu::TextureUsages::RENDER_ATTACHMENT,
-                                width                        : size.width,
-                                height                       : size.height,
-                                present_mode                 : ::wgpu::PresentMode::AutoVsync,
-                                alpha_mode                   : ::wgpu::CompositeAlphaMode::Auto,
-                                view_formats                 : vec![],
-                                desired_maximum_frame_latency: 10,
-                            },
-                        );
-
-                        *did_resize = false;
+                if self.gpu.as_ref().is_some_and(GpuContext::is_lost) {
+                    self.recover_device(now);
+                    if self.gpu.as_ref().is_some_and(GpuContext::is_lost) {
+                        // nothing drawn with the lost device would show up, about_to_wait tries again
+                        return;
                     }
+                }
+                // the last frame of a limited run is saved when a screenshot file is configured
+                let last_frame = match (self.config.frames, self.gfx.as_ref()) {
+                    (Some(limit), Some(gfx)) => gfx.frames + 1 >= u64::from(limit),
+                    _ => false,
+                };
+                let capture = self.screenshot_requested || (last_frame && self.config.screenshot.is_some());
+                let started = Instant::now();
+                self.animate(now);
+                self.run_ui(now);
+                if let Some(size) = self.gfx.as_ref().map(|gfx| gfx.window.inner_size()) {
+                    self.apply_camera((size.width, size.height));
+                }
+                if self.renderer.overlay.visible {
+                    self.renderer.overlay.update(&self.stats);
+                    if let Some(interval) = self.stats.present_interval.mean() {
+                        let fps = 1.0 / interval.as_secs_f64();
+                        let label = format!("{:.0} fps  {:.1} ms", fps, interval.as_secs_f64() * 1000.0);
+                        self.renderer.text.queue(TextSection::new(label, [8.0, 8.0], 16.0));
+                    }
+                }
+                let presented = self.gfx.as_ref().map_or(0, |gfx| gfx.frames);
+                let (events, captured) = match self.gfx.as_mut() {
+                    Some(gfx) => gfx.redraw(self.gpu.as_ref(), &mut self.renderer, &self.resources, capture, now),
+                    None => (vec![], None),
+                };
+                for event in events {
+                    self.on_gpu_event(event, now);
+                }
+                if let Some(gpu_time) = self.gpu.as_ref().and_then(|gpu| self.renderer.gpu_time(gpu)) {
+                    self.stats.record_gpu(gpu_time);
+                }
+                if let Some(gfx) = self.gfx.as_ref().filter(|gfx| gfx.frames > presented) {
+                    self.stats.record_cpu(started.elapsed());
+                    self.stats.record_present(now);
+                    self.pacer.frame_drawn(now);
+                    if self.pacer.redraw_after_present() {
+                        gfx.window.request_redraw();
+                    }
+                }
+                if let (Some(pixels), Some(gfx)) = (captured, self.gfx.as_ref()) {
+                    self.screenshot_requested = false;
+                    self.save_screenshot(gfx.size, &pixels);
+                }
+                self.check_frame_limit();
+            }
+        }
+    }
 
-                    match surface.get_current_texture() {
-                        Ok(frame) => {
-                            info!("got frame inside redraw requested");
-                            let mut encoder = device.create_command_encoder(
-                                &::wgpu::CommandEncoderDescriptor { label: None },
-                            );
-                            let view = frame.texture.create_view(&::wgpu::TextureViewDescriptor::default());
-                            {
-                                let rpass = encoder.begin_render_pass(&::wgpu::RenderPassDescriptor {
-                                    label: Some("Render Pass"),
-                                    color_attachments: &[Some(::wgpu::RenderPassColorAttachment {
-                                        view: &view,
-                                        resolve_target: None,
-                                        ops: ::wgpu::Operations {
-                                            load: ::wgpu::LoadOp::Clear(Color{r: 0.5, g: 0.76, b: 0.5, a: 1.0}),
-                                            store: ::wgpu::StoreOp::Store,
-                                        }
-                                    })],
-                                    depth_stencil_attachment: None,
-                                    timestamp_writes: None,
-                                    occlusion_query_set: None,
-                                });
-                                // rpass.set_pipeline(pipeline);
-                                // rpass.draw(0..0, 0..0);
-                            }
+    pub fn key_input(&mut self, physical_key: PhysicalKey, state: ElementState, now: Instant) {
+        // not behind cfg(target_os = "android"), so device recordings replay on desktop
+        match physical_key {
+            PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_BUTTON_BACK)) => {
+                match state {
+                    ElementState::Pressed => self.back_gesture(BackGesture::Started, now),
+                    ElementState::Released => self.back_gesture(BackGesture::Committed, now),
+                }
+            },
+            PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_KEYCODE_BACK)) => {
+                match state {
+                    ElementState::Pressed => info!("back button pressed"),
+                    ElementState::Released => self.navigate_back(now),
+                }
+            },
+            _ => ()
+        }
+        if physical_key == PhysicalKey::Code(KeyCode::F12) && state.is_pressed() {
+            self.request_screenshot();
+        }
+        if physical_key == PhysicalKey::Code(KeyCode::F3) && state.is_pressed() {
+            self.toggle_overlay();
+        }
+        if let PhysicalKey::Code(key_code) = physical_key {
+            if state.is_pressed() {
+                info!("key pressed {}", key_code as u32);
+            } else {
+                info!("key released {}", key_code as u32);
+            }
+        }
+    }
 
+}
 
-                            queue.submit(Some(encoder.finish()));
-                            frame.present();
-                            // window.request_redraw();
-                        }
-                        Err(error) => match error {
-                            wgpu::SurfaceError::OutOfMemory => {
-                                panic!(
-                                    "Swapchain error: {error}. \
-                                Rendering cannot continue."
-                                )
-                            }
-                            _ => {
-                                // Try rendering again next frame.
-                                window.request_redraw();
-                            }
-                        },
-                    }
+impl <'window>GfxState<'window> {
+    /// Draw a frame.  Returns what went wrong along the way and, with
+    /// `capture` set, the frame's pixels.
+    pub fn redraw(&mut self, gpu: Option<&GpuContext>, renderer: &mut Renderer, resources: &ResourceRegistry<GpuContext>, capture: bool, now: Instant) -> (Vec<GpuEvent>, Option<Vec<u8>>) {
+        let mut events = vec![];
+        let mut captured = None;
+        self.redraw_at = None;
+        if self.did_resize {
+            let size = self.window.inner_size();
+            self.size = (size.width, size.height);
+            // also recreates the depth and MSAA textures at the new size
+            if let (Some(surface), Some(gpu)) = (self.surface.as_mut(), gpu) {
+                surface.configure(gpu, self.size);
+            }
+            self.did_resize = false;
+        }
+        if let (Some(surface), Some(gpu)) = (self.surface.as_mut(), gpu) {
+            match surface.draw(gpu, renderer, resources, capture, now, &mut events) {
+                Acquired::Frame(pixels) => {
+                    self.frames += 1;
+                    captured = pixels;
+                },
+                Acquired::RetryAt(at) => self.redraw_at = Some(at),
+                Acquired::RetryNextFrame => self.window.request_redraw(),
+                Acquired::Stop => info!("rendering stopped"),
             }
         }
+        (events, captured)
     }
 }
 
-pub fn _main( event_loop: EventLoop<()>) -> Result<(), winit::error::EventLoopError>
+impl <'window>HeadlessApp for ApplicationState<'window> {
+    fn resume_with_window(&mut self, window: Arc<dyn AppWindow>) {
+        ApplicationState::resume_with_window(self, window);
+    }
+
+    fn suspend(&mut self) {
+        ApplicationState::suspend(self);
+    }
+
+    fn handle_window_event(&mut self, event: winit::event::WindowEvent, now: Instant) {
+        ApplicationState::handle_window_event(self, event, now);
+    }
+
+    fn key_input(&mut self, physical_key: PhysicalKey, state: ElementState, now: Instant) {
+        ApplicationState::key_input(self, physical_key, state, now);
+    }
+
+    fn exit_requested(&self) -> bool {
+        self.exit_requested
+    }
+
+    fn advance_to(&mut self, now: Instant) {
+        ApplicationState::advance_to(self, now);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.gestures.next_deadline()
+    }
+}
+
+pub fn _main(event_loop: EventLoop<()>, app: &mut ApplicationState) -> Result<(), winit::error::EventLoopError>
 {
-        log::info!("_main , app state new() ...");
-    let mut app = ApplicationState::new();
     event_loop.set_control_flow(::winit::event_loop::ControlFlow::Wait);
-    event_loop.run_app(&mut app)
+    event_loop.run_app(app)
 }
 
 #[cfg(target_os = "android")]
 mod android {
-    use super::_main;
+    use super::{_main, ApplicationState, Assets, RendererConfig};
+    use ::android_rust_example_apk_common::record::Recorder;
     use android_activity::AndroidApp;
     use winit::platform::android::EventLoopBuilderExtAndroid;
     #[no_mangle]
@@ -307,10 +968,34 @@ mod android {
         );
         // ::android_logger::Config
         log::info!("android_main started");
+        let mut state = ApplicationState::new();
+        if let Some(dir) = app.internal_data_path() {
+            state.screenshot_dir = dir;
+        }
+        state.assets = Assets::Apk(app.asset_manager());
+        state.android_app = Some(app.clone());
+
+        // optional `files/renderer.toml`, pushed with `adb shell run-as <package>`
+        if let Some(path) = app.internal_data_path().map(|dir| dir.join("renderer.toml")).filter(|path| path.exists()) {
+            match RendererConfig::from_file(&path) {
+                Ok(config) => { state.with_config(config); },
+                Err(e) => log::info!("using default renderer config: {}", e),
+            }
+        }
+
+        // `record_input = true` in renderer.toml keeps an input recording to pull with
+        // `adb shell run-as <package> cat files/input.jsonl`
+        if let Some(dir) = app.internal_data_path().filter(|_| state.config.record_input) {
+            match Recorder::create(dir.join("input.jsonl")) {
+                Ok(recorder) => { state.with_recorder(recorder); },
+                Err(e) => log::info!("cannot record input: {}", e),
+            }
+        }
+
         let event_loop = ::winit::event_loop::EventLoop::builder()
             .with_android_app(app).build().unwrap();
 
-        let _ = _main(event_loop);
+        let _ = _main(event_loop, &mut state);
     }
 
 }
@@ -1,12 +1,159 @@
-use android_rust_example_apk_wgpu::_main;
+use ::std::path::PathBuf;
+
+use ::android_rust_example_apk_common::record::{self, Recorder};
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::config::{parse_setting, Backend, PresentMode, RendererConfig};
+use ::android_rust_example_apk_wgpu::pacing::RunMode;
+use ::android_rust_example_apk_wgpu::{_main, offscreen, ApplicationState};
+use ::clap::Parser;
+
+/// Desktop runner for the wgpu example.  Flags override the renderer config
+/// loaded from `RENDERER_CONFIG` and `RENDERER_*` variables.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Backend to use, can be repeated (vulkan, metal, dx12, gl or browser_webgpu)
+    #[arg(long = "backend", value_parser = parse_setting::<Backend>)]
+    backends: Vec<Backend>,
+    /// Use the first adapter whose name contains this
+    #[arg(long)]
+    adapter: Option<String>,
+    /// auto_vsync, auto_no_vsync, fifo, fifo_relaxed, immediate or mailbox
+    #[arg(long, value_parser = parse_setting::<PresentMode>)]
+    present_mode: Option<PresentMode>,
+    /// on_demand, continuous or fixed_rate
+    #[arg(long, value_parser = parse_setting::<RunMode>)]
+    run_mode: Option<RunMode>,
+    /// Frames per second with --run-mode fixed_rate
+    #[arg(long)]
+    frame_rate: Option<u32>,
+    /// Simulation updates per second
+    #[arg(long)]
+    update_rate: Option<u32>,
+    /// MSAA samples per pixel, e.g. 4
+    #[arg(long)]
+    msaa: Option<u32>,
+    /// Folder to read textures, models and fonts from, in place of the first of
+    /// `assets` next to the executable, `./assets` or `./android/app/src/main/assets`
+    #[arg(long, env = "ASSETS_DIR")]
+    assets: Option<PathBuf>,
+    /// Texture for the demo quad, e.g. textures/bricks (see the README for the files tried)
+    #[arg(long)]
+    texture: Option<String>,
+    /// glTF model to show in place of the demo meshes, e.g. models/demo.gltf
+    #[arg(long)]
+    model: Option<String>,
+    /// Particles pulled towards the pointer, e.g. 20000.  They move with --run-mode continuous or fixed_rate
+    #[arg(long)]
+    particles: Option<usize>,
+    /// Show the frame time graph
+    #[arg(long)]
+    overlay: bool,
+    /// Window size in physical pixels, e.g. 1280x720
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(u32, u32)>,
+    #[arg(long)]
+    fullscreen: bool,
+    /// off, error, warn, info, debug or trace.  RUST_LOG still applies on top.
+    #[arg(long, default_value = "info")]
+    log_level: ::log::LevelFilter,
+    /// Exit after presenting this many frames
+    #[arg(long)]
+    frames: Option<u32>,
+    /// Render offscreen without a window, e.g. on a machine without a display
+    #[arg(long)]
+    headless: bool,
+    /// Write the last frame to this PNG, used with --headless or --frames
+    #[arg(long)]
+    screenshot: Option<PathBuf>,
+    /// Write input to a file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Feed recorded input back without a window
+    #[arg(long)]
+    replay: Option<PathBuf>,
+}
+
+impl Args {
+    fn apply(&self, config: &mut RendererConfig) {
+        if !self.backends.is_empty() {
+            config.backends = self.backends.clone();
+        }
+        if let Some(adapter) = self.adapter.as_ref() {
+            config.adapter = Some(adapter.clone());
+        }
+        if let Some(present_mode) = self.present_mode {
+            config.present_mode = present_mode;
+        }
+        if let Some(run_mode) = self.run_mode {
+            config.run_mode = run_mode;
+        }
+        if let Some(frame_rate) = self.frame_rate {
+            config.frame_rate = frame_rate;
+        }
+        if let Some(update_rate) = self.update_rate {
+            config.update_rate = update_rate;
+        }
+        if let Some(msaa) = self.msaa {
+            config.msaa_samples = msaa;
+        }
+        config.overlay |= self.overlay;
+        if let Some(size) = self.size {
+            config.window.size = Some(size);
+        }
+        config.window.fullscreen |= self.fullscreen;
+        if let Some(frames) = self.frames {
+            config.frames = Some(frames);
+        }
+        config.headless |= self.headless;
+        if let Some(screenshot) = self.screenshot.as_ref() {
+            config.screenshot = Some(screenshot.clone());
+        }
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value.split_once('x').ok_or("expected WIDTHxHEIGHT")?;
+    let width = width.parse().map_err(|e| format!("width: {}", e))?;
+    let height = height.parse().map_err(|e| format!("height: {}", e))?;
+    Ok((width, height))
+}
 
 fn main() {
-    env_logger::builder()
-        .filter_level(::log::LevelFilter::Info) // Default Log Level
+    let args = Args::parse();
+    ::env_logger::builder()
+        .filter_level(args.log_level)
         .parse_default_env()
         .init();
 
+    let mut config = RendererConfig::load().expect("cannot load renderer config");
+    args.apply(&mut config);
+    let mut app = ApplicationState::new();
+    app.with_config(config);
+    if let Some(dir) = args.assets.as_ref() {
+        app.assets = Assets::Directory(dir.clone());
+    }
+    app.demo_texture = args.texture.clone();
+    app.demo_model = args.model.clone();
+    app.demo_particles = args.particles;
+
+    if let Some(path) = args.replay.as_ref() {
+        let recording = record::load(path).expect("cannot read recording");
+        record::replay(&mut app, &recording);
+        return;
+    }
+    if app.config.headless {
+        let (size, pixels) = app.render_headless().expect("no adapter available for headless rendering");
+        match app.config.screenshot.as_ref() {
+            Some(path) => offscreen::write_png(path, size, &pixels).expect("cannot write screenshot"),
+            None => ::log::info!("rendered {}x{} offscreen", size.0, size.1),
+        }
+        return;
+    }
+    if let Some(path) = args.record.as_ref() {
+        app.with_recorder(Recorder::create(path).expect("cannot create recording"));
+    }
+
     let event_loop = ::winit::event_loop::EventLoop::builder().build()
         .expect("unable to create event loop");
-    let _ = _main(event_loop);
+    let _ = _main(event_loop, &mut app);
 }
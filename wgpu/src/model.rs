@@ -0,0 +1,517 @@
+use ::std::fmt;
+use ::std::io;
+
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::assets::Assets;
+use crate::camera::{look_at, orthographic, perspective, Projection};
+use crate::renderer::{multiply, Matrix, IDENTITY};
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    Gltf(::gltf::Error),
+    /// A valid file this loader cannot use.
+    Unsupported(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(e) => write!(f, "cannot read model: {}", e),
+            ModelError::Gltf(e) => write!(f, "invalid glTF: {}", e),
+            ModelError::Unsupported(reason) => write!(f, "unsupported model: {}", reason),
+        }
+    }
+}
+
+impl ::std::error::Error for ModelError {}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct PbrVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// Linear RGBA, multiplies the base color.
+    pub color: [f32; 4],
+}
+
+impl PbrVertex {
+    const ATTRIBUTES: [::wgpu::VertexAttribute; 4] = ::wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4];
+
+    pub fn layout() -> ::wgpu::VertexBufferLayout<'static> {
+        ::wgpu::VertexBufferLayout {
+            array_stride: ::std::mem::size_of::<PbrVertex>() as ::wgpu::BufferAddress,
+            step_mode: ::wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Triangles sharing one material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Primitive {
+    pub vertices: Vec<PbrVertex>,
+    pub indices: Vec<u32>,
+    /// `None` for the glTF default material.
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fragments below `alpha_cutoff` are discarded, the rest are opaque.
+    Mask,
+    Blend,
+}
+
+/// A glTF sampler.  Filters the file leaves open are linear, with mipmaps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelSampler {
+    pub address_mode_u: ::wgpu::AddressMode,
+    pub address_mode_v: ::wgpu::AddressMode,
+    pub mag_filter: ::wgpu::FilterMode,
+    pub min_filter: ::wgpu::FilterMode,
+    /// `None` for the minification filters that do not use mipmaps.
+    pub mipmap_filter: Option<::wgpu::FilterMode>,
+}
+
+impl Default for ModelSampler {
+    /// What glTF prescribes for textures without a sampler.
+    fn default() -> Self {
+        Self {
+            address_mode_u: ::wgpu::AddressMode::Repeat,
+            address_mode_v: ::wgpu::AddressMode::Repeat,
+            mag_filter: ::wgpu::FilterMode::Linear,
+            min_filter: ::wgpu::FilterMode::Linear,
+            mipmap_filter: Some(::wgpu::FilterMode::Linear),
+        }
+    }
+}
+
+impl ModelSampler {
+    fn from_gltf(sampler: ::gltf::texture::Sampler) -> Self {
+        use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+        use ::wgpu::{AddressMode, FilterMode};
+        let address_mode = |mode| match mode {
+            WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+            WrappingMode::Repeat => AddressMode::Repeat,
+        };
+        let (min_filter, mipmap_filter) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (FilterMode::Nearest, None),
+            Some(MinFilter::Linear) => (FilterMode::Linear, None),
+            Some(MinFilter::NearestMipmapNearest) => (FilterMode::Nearest, Some(FilterMode::Nearest)),
+            Some(MinFilter::LinearMipmapNearest) => (FilterMode::Linear, Some(FilterMode::Nearest)),
+            Some(MinFilter::NearestMipmapLinear) => (FilterMode::Nearest, Some(FilterMode::Linear)),
+            Some(MinFilter::LinearMipmapLinear) | None => (FilterMode::Linear, Some(FilterMode::Linear)),
+        };
+        Self {
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            mag_filter: match sampler.mag_filter() {
+                Some(MagFilter::Nearest) => FilterMode::Nearest,
+                Some(MagFilter::Linear) | None => FilterMode::Linear,
+            },
+            min_filter,
+            mipmap_filter,
+        }
+    }
+
+    pub fn descriptor(&self) -> ::wgpu::SamplerDescriptor<'static> {
+        ::wgpu::SamplerDescriptor {
+            label: Some("model"),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter.unwrap_or(::wgpu::FilterMode::Nearest),
+            // only the base level then
+            lod_max_clamp: if self.mipmap_filter.is_some() { 32.0 } else { 0.0 },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<ModelSampler> for ::wgpu::SamplerDescriptor<'static> {
+    fn from(sampler: ModelSampler) -> Self {
+        sampler.descriptor()
+    }
+}
+
+/// A texture of a [`Material`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRef {
+    /// Into [`Model::images`].
+    pub image: usize,
+    pub sampler: ModelSampler,
+    /// The `TEXCOORD_n` set it is sampled with.
+    pub tex_coord: u32,
+}
+
+/// A glTF metallic-roughness material.  Normal and occlusion maps are not used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Roughness in green, metalness in blue.
+    pub metallic_roughness_texture: Option<TextureRef>,
+    /// Linear RGB, already multiplied by `KHR_materials_emissive_strength`.
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    /// The material glTF prescribes for primitives without one.
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    /// The `TEXCOORD_n` set its textures are sampled with.  Vertices carry
+    /// one set, so textures asking for different ones all get the first.
+    pub fn tex_coord(&self) -> u32 {
+        let mut sets = [self.base_color_texture, self.metallic_roughness_texture, self.emissive_texture].into_iter()
+            .flatten()
+            .map(|texture| texture.tex_coord);
+        let first = sets.next().unwrap_or(0);
+        if sets.any(|set| set != first) {
+            info!("material samples several TEXCOORD sets, using TEXCOORD_{} for all of them", first);
+        }
+        first
+    }
+}
+
+/// An encoded image, decoded when the model is uploaded because only then
+/// is it known whether it holds colors or data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelImage {
+    /// The asset it came from, or where it is in the model.
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub name: Option<String>,
+    /// Relative to the parent.
+    pub transform: Matrix,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelCamera {
+    /// An orthographic camera's height is twice its `ymag`.
+    pub projection: Projection,
+    pub near: f32,
+    /// glTF perspective cameras may have no far plane, they get `near * 1000`.
+    pub far: f32,
+}
+
+impl ModelCamera {
+    /// World to clip space for the camera at `world`, as returned by
+    /// [`Model::world_transforms`].  The viewport's `aspect` is used rather
+    /// than the one stored in the file, so the image is not stretched.
+    pub fn view_proj(&self, world: &Matrix, aspect: f32) -> Matrix {
+        let eye = [world[3][0], world[3][1], world[3][2]];
+        // cameras look down their local -z with y up
+        let forward = [-world[2][0], -world[2][1], -world[2][2]];
+        let target = [eye[0] + forward[0], eye[1] + forward[1], eye[2] + forward[2]];
+        let view = look_at(eye, target, [world[1][0], world[1][1], world[1][2]]);
+        let projection = match self.projection {
+            Projection::Perspective { fov_y } => perspective(fov_y, aspect, self.near, self.far),
+            Projection::Orthographic { height } => orthographic(height * aspect, height, self.near, self.far),
+        };
+        multiply(&projection, &view)
+    }
+}
+
+/// A glTF 2.0 scene on the CPU side, ready for `ModelRenderer::add`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<Material>,
+    pub images: Vec<ModelImage>,
+    pub nodes: Vec<Node>,
+    /// Top level nodes of the default scene, or of the first one.
+    pub roots: Vec<usize>,
+    pub cameras: Vec<ModelCamera>,
+}
+
+/// Read a `.gltf` or `.glb` file and the buffers and images it refers to
+/// from `assets`.
+pub fn load_model(assets: &Assets, name: &str) -> Result<Model, ModelError> {
+    let bytes = assets.read(name).map_err(ModelError::Io)?;
+    let model = Model::from_slice(&bytes, name, assets)?;
+    info!("loaded model {} ({} meshes, {} materials, {} images, {} nodes)", name, model.meshes.len(), model.materials.len(), model.images.len(), model.nodes.len());
+    Ok(model)
+}
+
+/// `uri` from a file called `base`, as an asset name.  Percent escapes are
+/// decoded and `.` and `..` segments resolved, the APK's asset manager
+/// knows neither.
+pub fn resolve_uri(base: &str, uri: &str) -> String {
+    let directory = base.rsplit_once('/').map_or("", |(directory, _)| directory);
+    let mut segments: Vec<String> = vec![];
+    for segment in directory.split('/').chain(uri.split('/')) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            },
+            segment => segments.push(percent_decode(segment)),
+        }
+    }
+    segments.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The contents of a `data:` URI, `None` for any other URI.
+fn data_uri(uri: &str) -> Option<Result<Vec<u8>, ModelError>> {
+    let (header, payload) = uri.strip_prefix("data:")?.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return Some(Ok(percent_decode(payload).into_bytes()));
+    }
+    let decoded = ::base64::Engine::decode(&::base64::engine::general_purpose::STANDARD, payload)
+        .map_err(|e| ModelError::Unsupported(format!("bad base64 in data URI: {}", e)));
+    Some(decoded)
+}
+
+fn read_uri(assets: &Assets, base: &str, uri: &str) -> Result<(String, Vec<u8>), ModelError> {
+    if let Some(data) = data_uri(uri) {
+        return data.map(|bytes| ("data URI".to_string(), bytes));
+    }
+    let name = resolve_uri(base, uri);
+    let bytes = assets.read(&name).map_err(ModelError::Io)?;
+    Ok((name, bytes))
+}
+
+impl Model {
+    /// Parse a `.gltf` or `.glb` file read from `name`.  External files are
+    /// read from `assets`, relative to `name`.
+    pub fn from_slice(bytes: &[u8], name: &str, assets: &Assets) -> Result<Self, ModelError> {
+        let ::gltf::Gltf { document, mut blob } = ::gltf::Gltf::from_slice(bytes).map_err(ModelError::Gltf)?;
+        let buffers = document.buffers().map(|buffer| {
+            let data = match buffer.source() {
+                ::gltf::buffer::Source::Bin => blob.take().ok_or_else(|| ModelError::Unsupported("no binary chunk".to_string()))?,
+                ::gltf::buffer::Source::Uri(uri) => read_uri(assets, name, uri)?.1,
+            };
+            if data.len() < buffer.length() {
+                return Err(ModelError::Unsupported(format!("buffer {} is {} bytes, expected {}", buffer.index(), data.len(), buffer.length())));
+            }
+            Ok(data)
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let images = document.images().map(|image| match image.source() {
+            ::gltf::image::Source::View { view, .. } => {
+                let start = view.offset();
+                let bytes = buffers[view.buffer().index()].get(start..start + view.length())
+                    .ok_or_else(|| ModelError::Unsupported(format!("image {} is outside its buffer", image.index())))?;
+                Ok(ModelImage { name: format!("{} image {}", name, image.index()), bytes: bytes.to_vec() })
+            },
+            ::gltf::image::Source::Uri { uri, .. } => {
+                let (name, bytes) = read_uri(assets, name, uri)?;
+                Ok(ModelImage { name, bytes })
+            },
+        }).collect::<Result<Vec<_>, ModelError>>()?;
+
+        let texture = |info: Option<::gltf::texture::Info>| info.map(|info| TextureRef {
+            image: info.texture().source().index(),
+            sampler: ModelSampler::from_gltf(info.texture().sampler()),
+            tex_coord: info.tex_coord(),
+        });
+        let materials = document.materials().map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let strength = material.emissive_strength().unwrap_or(1.0);
+            Material {
+                base_color: pbr.base_color_factor(),
+                base_color_texture: texture(pbr.base_color_texture()),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                metallic_roughness_texture: texture(pbr.metallic_roughness_texture()),
+                emissive: material.emissive_factor().map(|channel| channel * strength),
+                emissive_texture: texture(material.emissive_texture()),
+                alpha_mode: match material.alpha_mode() {
+                    ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                    ::gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                    ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                },
+                alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+                double_sided: material.double_sided(),
+            }
+        }).collect::<Vec<_>>();
+
+        let meshes = document.meshes().map(|mesh| {
+            let primitives = mesh.primitives().filter_map(|primitive| {
+                if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                    info!("skipping {:?} primitive of mesh {}, only triangles are drawn", primitive.mode(), mesh.index());
+                    return None;
+                }
+                let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+                let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+                let tex_coord = primitive.material().index().and_then(|material| materials.get(material)).map_or(0, Material::tex_coord);
+                let uvs: Vec<[f32; 2]> = reader.read_tex_coords(tex_coord).map_or(vec![], |uvs| uvs.into_f32().collect());
+                let colors: Vec<[f32; 4]> = reader.read_colors(0).map_or(vec![], |colors| colors.into_rgba_f32().collect());
+                if indices.iter().any(|&i| i as usize >= positions.len()) {
+                    info!("skipping primitive of mesh {} with indices out of range", mesh.index());
+                    return None;
+                }
+                if normals.as_ref().is_some_and(|normals| normals.len() != positions.len()) {
+                    info!("skipping primitive of mesh {} with {} positions but a different number of normals", mesh.index(), positions.len());
+                    return None;
+                }
+                let vertex = |i: usize, normal| PbrVertex {
+                    position: positions[i],
+                    normal,
+                    uv: uvs.get(i).copied().unwrap_or([0.0; 2]),
+                    color: colors.get(i).copied().unwrap_or([1.0; 4]),
+                };
+                let (vertices, indices) = match normals {
+                    Some(normals) => ((0..positions.len()).map(|i| vertex(i, normals[i])).collect(), indices),
+                    // glTF asks for flat shading then, every triangle gets vertices of its own
+                    None => {
+                        let vertices: Vec<PbrVertex> = indices.chunks_exact(3).flat_map(|triangle| {
+                            let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+                            let normal = face_normal(a, b, c);
+                            triangle.iter().map(move |&i| vertex(i as usize, normal)).collect::<Vec<_>>()
+                        }).collect();
+                        let indices = (0..vertices.len() as u32).collect();
+                        (vertices, indices)
+                    },
+                };
+                Some(Primitive { vertices, indices, material: primitive.material().index() })
+            }).collect();
+            ModelMesh { name: mesh.name().map(str::to_string), primitives }
+        }).collect();
+
+        let nodes = document.nodes().map(|node| Node {
+            name: node.name().map(str::to_string),
+            transform: node.transform().matrix(),
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            camera: node.camera().map(|camera| camera.index()),
+            children: node.children().map(|child| child.index()).collect(),
+        }).collect();
+
+        let cameras = document.cameras().map(|camera| match camera.projection() {
+            ::gltf::camera::Projection::Perspective(perspective) => ModelCamera {
+                projection: Projection::Perspective { fov_y: perspective.yfov() },
+                near: perspective.znear(),
+                far: perspective.zfar().unwrap_or(perspective.znear() * 1000.0),
+            },
+            ::gltf::camera::Projection::Orthographic(orthographic) => ModelCamera {
+                projection: Projection::Orthographic { height: 2.0 * orthographic.ymag() },
+                near: orthographic.znear(),
+                far: orthographic.zfar(),
+            },
+        }).collect();
+
+        let roots = document.default_scene().or_else(|| document.scenes().next())
+            .map_or(vec![], |scene| scene.nodes().map(|node| node.index()).collect());
+        Ok(Self { meshes, materials, images, nodes, roots, cameras })
+    }
+
+    /// Every node reachable from [`Model::roots`] with its transform
+    /// relative to the model, parents before their children.
+    pub fn world_transforms(&self) -> Vec<(usize, Matrix)> {
+        let mut out = vec![];
+        let mut pending: Vec<(usize, Matrix)> = self.roots.iter().rev().map(|&root| (root, IDENTITY)).collect();
+        while let Some((index, parent)) = pending.pop() {
+            // glTF forbids cycles, but a broken file should not hang the app
+            if out.len() > self.nodes.len() {
+                break;
+            }
+            let node = &self.nodes[index];
+            let world = multiply(&parent, &node.transform);
+            out.push((index, world));
+            pending.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        out
+    }
+
+    /// Smallest and largest corner of a box around every drawn vertex,
+    /// `None` without any.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut bounds: Option<([f32; 3], [f32; 3])> = None;
+        for (index, world) in self.world_transforms() {
+            let Some(mesh) = self.nodes[index].mesh else {
+                continue;
+            };
+            let points = self.meshes[mesh].primitives.iter().flat_map(|primitive| primitive.vertices.iter());
+            for vertex in points {
+                let [x, y, z] = vertex.position;
+                let point: [f32; 3] = ::std::array::from_fn(|row| world[0][row] * x + world[1][row] * y + world[2][row] * z + world[3][row]);
+                bounds = Some(match bounds {
+                    Some((min, max)) => (::std::array::from_fn(|i| min[i].min(point[i])), ::std::array::from_fn(|i| max[i].max(point[i]))),
+                    None => (point, point),
+                });
+            }
+        }
+        bounds
+    }
+}
+
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+    let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if length > 0.0 {
+        normal.map(|component| component / length)
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
@@ -0,0 +1,114 @@
+use ::std::fs::File;
+use ::std::io::{self, BufWriter};
+use ::std::path::Path;
+
+use crate::gpu::GpuContext;
+use crate::renderer::Renderer;
+use crate::resources::ResourceRegistry;
+use crate::targets::RenderTargets;
+
+/// Format of [`OffscreenTarget`] and of the pixels read back from it.
+pub const FORMAT: ::wgpu::TextureFormat = ::wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// A texture to render into instead of a window surface.
+pub struct OffscreenTarget {
+    pub texture: ::wgpu::Texture,
+    pub size: (u32, u32),
+    pub targets: RenderTargets,
+}
+
+impl OffscreenTarget {
+    pub fn new(gpu: &GpuContext, size: (u32, u32)) -> Self {
+        Self::with_format(gpu, size, FORMAT)
+    }
+
+    /// A target of another 4 bytes per pixel `format`, whose pixels
+    /// [`OffscreenTarget::render`] returns as they are.
+    pub fn with_format(gpu: &GpuContext, size: (u32, u32), format: ::wgpu::TextureFormat) -> Self {
+        let texture = gpu.device.create_texture(&::wgpu::TextureDescriptor {
+            label: Some("offscreen"),
+            size: ::wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: ::wgpu::TextureDimension::D2,
+            format,
+            usage: ::wgpu::TextureUsages::RENDER_ATTACHMENT | ::wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        Self { texture, size, targets: RenderTargets::for_format(gpu, format, size) }
+    }
+
+    /// Render one frame with `renderer` and return it as tightly packed RGBA8 rows, top row first.
+    pub fn render(&self, gpu: &GpuContext, renderer: &mut Renderer, resources: &ResourceRegistry<GpuContext>) -> Vec<u8> {
+        let mut encoder = gpu.device.create_command_encoder(&::wgpu::CommandEncoderDescriptor { label: Some("offscreen") });
+        let view = self.texture.create_view(&::wgpu::TextureViewDescriptor::default());
+        renderer.render(gpu, resources, &mut encoder, &view, &self.targets);
+        gpu.queue.submit(Some(encoder.finish()));
+        read_texture(gpu, &self.texture)
+    }
+}
+
+/// Bytes per row of a `width` pixels wide RGBA8 copy, padded as `copy_texture_to_buffer` requires.
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = ::wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+/// Copy a 4 bytes per pixel texture (with `COPY_SRC` usage) to the CPU,
+/// blocking until the GPU is done.  The row padding is removed.
+pub fn read_texture(gpu: &GpuContext, texture: &::wgpu::Texture) -> Vec<u8> {
+    let (width, height) = (texture.width(), texture.height());
+    let padded = padded_bytes_per_row(width);
+    let buffer = gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: u64::from(padded) * u64::from(height),
+        usage: ::wgpu::BufferUsages::COPY_DST | ::wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = gpu.device.create_command_encoder(&::wgpu::CommandEncoderDescriptor { label: Some("readback") });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        ::wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: ::wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = ::std::sync::mpsc::channel();
+    slice.map_async(::wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    gpu.device.poll(::wgpu::Maintain::Wait);
+    receiver.recv()
+        .expect("map_async callback runs during poll")
+        .expect("cannot map readback buffer");
+
+    let mapped = slice.get_mapped_range();
+    let row = (width * 4) as usize;
+    let pixels = mapped
+        .chunks(padded as usize)
+        .flat_map(|padded_row| &padded_row[..row])
+        .copied()
+        .collect();
+    drop(mapped);
+    buffer.unmap();
+    pixels
+}
+
+/// Write RGBA8 pixels to a PNG file.
+pub fn write_png<P: AsRef<Path>>(path: P, size: (u32, u32), rgba: &[u8]) -> io::Result<()> {
+    let mut encoder = ::png::Encoder::new(BufWriter::new(File::create(path)?), size.0, size.1);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder.set_source_srgb(::png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(rgba).map_err(io::Error::other)
+}
@@ -0,0 +1,214 @@
+use ::std::time::Duration;
+
+use ::android_rust_example_apk_common::timing::FrameStats;
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::gpu::{GpuContext, PerDevice};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct OverlayVertex {
+    /// Clip space
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl OverlayVertex {
+    const ATTRIBUTES: [::wgpu::VertexAttribute; 2] = ::wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    pub fn layout() -> ::wgpu::VertexBufferLayout<'static> {
+        ::wgpu::VertexBufferLayout {
+            array_stride: ::std::mem::size_of::<OverlayVertex>() as ::wgpu::BufferAddress,
+            step_mode: ::wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const BUDGET_LINE: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+const WITHIN_BUDGET: [f32; 4] = [0.2, 0.8, 0.2, 0.9];
+const OVER_BUDGET: [f32; 4] = [0.9, 0.8, 0.1, 0.9];
+const DROPPED: [f32; 4] = [0.9, 0.2, 0.2, 0.9];
+const CPU: [f32; 4] = [0.2, 0.6, 1.0, 0.9];
+const GPU: [f32; 4] = [0.9, 0.3, 0.9, 0.9];
+
+struct OverlayObjects {
+    shader: ::wgpu::ShaderModule,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    vertices: Option<::wgpu::Buffer>,
+}
+
+/// A graph of recent frame times drawn over the scene.
+///
+/// One column per frame: the present interval as a bar, green within
+/// `budget`, yellow within two budgets and red above, with the CPU time
+/// (blue, left half) and GPU time (magenta, right half) in front of it.  The
+/// white line marks `budget`, the top of the graph is `scale`.
+pub struct PerfOverlay {
+    pub visible: bool,
+    pub budget: Duration,
+    pub scale: Duration,
+    /// Clip space rectangle of the graph, `[left, bottom, right, top]`.
+    pub area: [f32; 4],
+    vertices: Vec<OverlayVertex>,
+    device: PerDevice<OverlayObjects, ::wgpu::TextureFormat>,
+}
+
+impl Default for PerfOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PerfOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            budget: Duration::from_secs(1) / 60,
+            scale: Duration::from_secs(1) / 20,
+            area: [-0.95, -0.95, 0.15, -0.45],
+            vertices: vec![],
+            device: PerDevice::new(),
+        }
+    }
+
+    /// Rebuild the graph from `stats`, drawn by the next [`PerfOverlay::render`].
+    pub fn update(&mut self, stats: &FrameStats) {
+        self.vertices = self.vertices(stats);
+    }
+
+    /// The graph for `stats` as a triangle list.
+    pub fn vertices(&self, stats: &FrameStats) -> Vec<OverlayVertex> {
+        let [left, bottom, right, top] = self.area;
+        let mut vertices = Vec::new();
+        rectangle(&mut vertices, left, bottom, right, top, BACKGROUND);
+
+        let columns = stats.present_interval.capacity().max(1);
+        let width = (right - left) / columns as f32;
+        let height = |duration: Duration| {
+            let share = (duration.as_secs_f32() / self.scale.as_secs_f32()).min(1.0);
+            bottom + (top - bottom) * share
+        };
+        // newest column on the right
+        let column = |index: usize, len: usize| left + width * (columns - len + index) as f32;
+
+        let present = &stats.present_interval;
+        for (index, interval) in present.samples().enumerate() {
+            let color = if interval <= self.budget.mul_f32(1.05) {
+                WITHIN_BUDGET
+            } else if interval <= self.budget.mul_f32(2.05) {
+                OVER_BUDGET
+            } else {
+                DROPPED
+            };
+            let x = column(index, present.len());
+            rectangle(&mut vertices, x, bottom, x + width, height(interval), color);
+        }
+        for (histogram, offset, color) in [(&stats.cpu, 0.0, CPU), (&stats.gpu, 0.5, GPU)] {
+            for (index, duration) in histogram.samples().enumerate() {
+                let x = column(index, histogram.len()) + width * offset;
+                rectangle(&mut vertices, x, bottom, x + width * 0.5, height(duration), color);
+            }
+        }
+
+        let budget = height(self.budget);
+        let thickness = (top - bottom) * 0.01;
+        rectangle(&mut vertices, left, budget - thickness, right, budget + thickness, BUDGET_LINE);
+        vertices
+    }
+
+    /// Draw the graph on top of what is already in `view`, when visible.
+    pub fn render(&mut self, gpu: &GpuContext, encoder: &mut ::wgpu::CommandEncoder, view: &::wgpu::TextureView, format: ::wgpu::TextureFormat) {
+        if !self.visible || self.vertices.is_empty() {
+            return;
+        }
+        let objects = self.device.get_or_create(gpu, Self::create_device_objects);
+        objects.ensure_pipeline(format, |objects, format| {
+            info!("creating overlay pipeline for {:?}", format);
+            Self::create_pipeline(gpu, objects, format)
+        });
+
+        let bytes: &[u8] = ::bytemuck::cast_slice(&self.vertices);
+        if objects.vertices.as_ref().is_none_or(|buffer| buffer.size() < bytes.len() as u64) {
+            objects.vertices = Some(gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+                label: Some("overlay vertices"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: ::wgpu::BufferUsages::VERTEX | ::wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let buffer = objects.vertices.as_ref().expect("overlay vertex buffer was just created");
+        gpu.queue.write_buffer(buffer, 0, bytes);
+
+        let mut rpass = encoder.begin_render_pass(&::wgpu::RenderPassDescriptor {
+            label: Some("overlay"),
+            color_attachments: &[Some(::wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: ::wgpu::Operations {
+                    load: ::wgpu::LoadOp::Load,
+                    store: ::wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(objects.pipeline(&format));
+        rpass.set_vertex_buffer(0, buffer.slice(..bytes.len() as u64));
+        rpass.draw(0..self.vertices.len() as u32, 0..1);
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> OverlayObjects {
+        info!("creating overlay objects for device generation {}", gpu.generation);
+        let shader = gpu.device.create_shader_module(::wgpu::include_wgsl!("shaders/overlay.wgsl"));
+        let pipeline_layout = gpu.device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("overlay"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        OverlayObjects {
+            shader,
+            pipeline_layout,
+            vertices: None,
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &OverlayObjects, format: ::wgpu::TextureFormat) -> ::wgpu::RenderPipeline {
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("overlay"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[OverlayVertex::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: ::wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+fn rectangle(vertices: &mut Vec<OverlayVertex>, left: f32, bottom: f32, right: f32, top: f32, color: [f32; 4]) {
+    let corner = |x, y| OverlayVertex { position: [x, y], color };
+    vertices.extend_from_slice(&[
+        corner(left, bottom), corner(right, bottom), corner(right, top),
+        corner(left, bottom), corner(right, top), corner(left, top),
+    ]);
+}
@@ -0,0 +1,130 @@
+use ::std::time::{Duration, Instant};
+
+use ::serde::{Deserialize, Serialize};
+
+/// When frames are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    /// Only when something asks for a redraw, e.g. input or a resize.  Saves battery.
+    OnDemand,
+    /// As fast as presenting allows, which with a vsync present mode is the display rate.
+    Continuous,
+    /// At `frame_rate` frames per second, woken up with `ControlFlow::WaitUntil`.
+    FixedRate,
+}
+
+/// Result of [`FixedTimestep::advance`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Steps {
+    /// Number of updates to run before drawing.
+    pub count: u32,
+    /// How far the frame is between the last update and the next one, from
+    /// 0.0 to 1.0.  Draw `previous + (current - previous) * alpha` to move smoothly
+    /// when the frame rate and the update rate differ.
+    pub alpha: f32,
+}
+
+/// Runs updates at a fixed rate no matter how often frames are drawn.
+///
+/// Time between frames is collected and spent in `step` sized updates.  After
+/// a long pause (the app in the background, a debugger) at most
+/// `max_steps` updates are run and the rest of the time is dropped, instead
+/// of freezing while the simulation catches up.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    pub step: Duration,
+    pub max_steps: u32,
+    accumulated: Duration,
+    last: Option<Instant>,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            step,
+            max_steps: 8,
+            accumulated: Duration::ZERO,
+            last: None,
+        }
+    }
+
+    /// Updates per second.
+    pub fn from_rate(rate: u32) -> Self {
+        Self::new(Duration::from_secs(1) / rate.max(1))
+    }
+
+    /// Forget the time of the last frame, so the next [`FixedTimestep::advance`]
+    /// does not count the time in between.  Call when the app resumes.
+    pub fn reset(&mut self) {
+        self.accumulated = Duration::ZERO;
+        self.last = None;
+    }
+
+    pub fn advance(&mut self, now: Instant) -> Steps {
+        if let Some(last) = self.last {
+            self.accumulated += now.saturating_duration_since(last);
+        }
+        self.last = Some(now);
+
+        let mut count = 0;
+        while self.accumulated >= self.step && count < self.max_steps {
+            self.accumulated -= self.step;
+            count += 1;
+        }
+        if count == self.max_steps && self.accumulated >= self.step {
+            // too far behind, drop the rest
+            self.accumulated = Duration::ZERO;
+        }
+        Steps {
+            count,
+            alpha: self.accumulated.as_secs_f32() / self.step.as_secs_f32(),
+        }
+    }
+}
+
+/// Decides when the next frame is due for a [`RunMode`].
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    pub mode: RunMode,
+    pub interval: Duration,
+    next: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(mode: RunMode, frame_rate: u32) -> Self {
+        Self {
+            mode,
+            interval: Duration::from_secs(1) / frame_rate.max(1),
+            next: None,
+        }
+    }
+
+    /// Note that a frame was drawn at `now`.
+    pub fn frame_drawn(&mut self, now: Instant) {
+        if self.mode != RunMode::FixedRate {
+            return;
+        }
+        // keep the cadence, unless the frame was so late that catching up
+        // would mean drawing several frames back to back
+        let next = self.next.map_or(now, |next| next + self.interval);
+        self.next = Some(if next <= now { now + self.interval } else { next });
+    }
+
+    /// Whether to ask for a redraw straight after presenting.
+    pub fn redraw_after_present(&self) -> bool {
+        self.mode == RunMode::Continuous
+    }
+
+    /// When to wake up for the next frame, only set in `FixedRate` mode.
+    pub fn next_frame_at(&self) -> Option<Instant> {
+        match self.mode {
+            RunMode::FixedRate => self.next,
+            RunMode::OnDemand | RunMode::Continuous => None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.next = None;
+    }
+}
@@ -0,0 +1,495 @@
+use ::std::time::Duration;
+
+use ::bytemuck::{Pod, Zeroable};
+use ::log::{info, warn};
+
+use crate::camera::OrthoCamera;
+use crate::compute::{self, ComputeKernel};
+use crate::gpu::{GpuContext, PerDevice};
+use crate::renderer::Matrix;
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+
+/// Keeps the pull finite right at the attractor, pixels squared.  Handed to
+/// `shaders/particles_compute.wgsl` as an override.
+const SOFTENING: f32 = 256.0;
+
+/// Longest simulation step, longer frames are split into several steps.
+const MAX_STEP: Duration = Duration::from_millis(20);
+
+/// Steps per frame at most, time beyond that is dropped instead of catching up.
+const MAX_STEPS: u32 = 4;
+
+/// One particle, in the storage buffer the compute kernel updates and in the
+/// instance buffer it is drawn from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Particle {
+    /// Physical pixels, y down.
+    pub position: [f32; 2],
+    /// Per second.
+    pub velocity: [f32; 2],
+}
+
+impl Particle {
+    const ATTRIBUTES: [::wgpu::VertexAttribute; 2] = ::wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2];
+
+    pub fn layout() -> ::wgpu::VertexBufferLayout<'static> {
+        ::wgpu::VertexBufferLayout {
+            array_stride: ::std::mem::size_of::<Particle>() as ::wgpu::BufferAddress,
+            step_mode: ::wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    /// Where this particle is after one step, the CPU version of `shaders/particles_compute.wgsl`.
+    pub fn step(&self, params: &SimParams) -> Particle {
+        let [mut vx, mut vy] = self.velocity;
+        if let Some([x, y]) = params.attractor {
+            let (dx, dy) = (x - self.position[0], y - self.position[1]);
+            let pull = params.attraction * params.dt / (dx * dx + dy * dy + SOFTENING);
+            vx += dx * pull;
+            vy += dy * pull;
+        }
+        let damping = 1.0 + params.drag * params.dt;
+        (vx, vy) = (vx / damping, vy / damping);
+        let speed = (vx * vx + vy * vy).sqrt();
+        if speed > params.max_speed {
+            (vx, vy) = (vx * (params.max_speed / speed), vy * (params.max_speed / speed));
+        }
+
+        let mut position = [self.position[0] + vx * params.dt, self.position[1] + vy * params.dt];
+        let mut velocity = [vx, vy];
+        // bounce off the edges
+        for axis in 0..2 {
+            let bound = params.bounds[axis];
+            if position[axis] < 0.0 {
+                position[axis] = (-position[axis]).min(bound);
+                velocity[axis] = velocity[axis].abs();
+            } else if position[axis] > bound {
+                position[axis] = (2.0 * bound - position[axis]).max(0.0);
+                velocity[axis] = -velocity[axis].abs();
+            }
+        }
+        Particle { position, velocity }
+    }
+}
+
+/// Everything one simulation step depends on besides the particles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimParams {
+    /// Particles are pulled towards this point while it is set.
+    pub attractor: Option<[f32; 2]>,
+    /// Particles bounce around inside `[0, 0]` to `bounds`.
+    pub bounds: [f32; 2],
+    /// Seconds.
+    pub dt: f32,
+    /// Velocity gained per second towards the attractor, divided by the
+    /// distance to it in pixels.
+    pub attraction: f32,
+    /// How quickly particles slow down, per second.
+    pub drag: f32,
+    /// Pixels per second.
+    pub max_speed: f32,
+}
+
+/// Most particles one buffer can hold under `limits`, when `storage` also
+/// bound as a storage buffer for the compute kernel.
+pub fn max_particles(limits: &::wgpu::Limits, storage: bool) -> usize {
+    let mut bytes = limits.max_buffer_size;
+    if storage {
+        bytes = bytes.min(u64::from(limits.max_storage_buffer_binding_size));
+    }
+    (bytes / ::std::mem::size_of::<Particle>() as u64) as usize
+}
+
+/// Step the particles on the CPU, for devices that cannot run compute shaders.
+pub fn step(particles: &mut [Particle], params: &SimParams) {
+    for particle in particles.iter_mut() {
+        *particle = particle.step(params);
+    }
+}
+
+/// How the particles were moved in the last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simulation {
+    /// In a compute pass, the particles stay on the GPU.
+    Gpu,
+    /// With [`step`], uploaded every frame.
+    Cpu,
+}
+
+/// `Params` of `shaders/particles_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ParamsUniform {
+    attractor: [f32; 2],
+    bounds: [f32; 2],
+    dt: f32,
+    attraction: f32,
+    drag: f32,
+    max_speed: f32,
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl ParamsUniform {
+    fn new(params: &SimParams, count: u32) -> Self {
+        Self {
+            attractor: params.attractor.unwrap_or_default(),
+            bounds: params.bounds,
+            dt: params.dt,
+            attraction: if params.attractor.is_some() { params.attraction } else { 0.0 },
+            drag: params.drag,
+            max_speed: params.max_speed,
+            count,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CameraUniform {
+    view_proj: Matrix,
+    size: f32,
+    max_speed: f32,
+    _padding: [f32; 2],
+}
+
+/// The compute side, only on devices where [`compute::supported`] holds.
+struct Simulator {
+    kernel: ComputeKernel,
+    params: ::wgpu::Buffer,
+    /// For the current particle buffer.
+    bind_group: Option<::wgpu::BindGroup>,
+}
+
+struct ParticleObjects {
+    shader: ::wgpu::ShaderModule,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    camera_buffer: ::wgpu::Buffer,
+    camera_bind_group: ::wgpu::BindGroup,
+    simulator: Option<Simulator>,
+    /// Storage buffer of the simulation and instance buffer of the draw,
+    /// sized for the particles spawned last.
+    particles: Option<::wgpu::Buffer>,
+}
+
+/// What [`ParticleSystem::prepare`] set up for one frame.
+pub struct PreparedParticles {
+    key: (::wgpu::TextureFormat, u32),
+    count: u32,
+}
+
+/// Particles bouncing around the screen, pulled towards `attractor`, drawn
+/// with `shaders/particles.wgsl` on top of the models.
+///
+/// Where the device runs compute shaders they are simulated by
+/// `shaders/particles_compute.wgsl` in a compute pass before the render pass
+/// and never leave the GPU.  Elsewhere, as on many GLES devices, [`step`] does
+/// the same on the CPU and the result is uploaded every frame.  Time comes in
+/// through [`ParticleSystem::advance`], so nothing moves without updates.
+///
+/// The CPU copy is only kept current in the fallback, after a device loss the
+/// GPU simulation starts over from where the particles were spawned.
+pub struct ParticleSystem {
+    pub visible: bool,
+    /// Where the pointer is held down, in physical pixels.
+    pub attractor: Option<[f32; 2]>,
+    /// See [`SimParams`].
+    pub attraction: f32,
+    pub drag: f32,
+    pub max_speed: f32,
+    /// Diameter of a particle in physical pixels.
+    pub size: f32,
+    /// Follows the size of the target drawn into, the particles bounce off its edges.
+    camera: OrthoCamera,
+    count: usize,
+    particles: Vec<Particle>,
+    /// `particles` changed since they were last uploaded.
+    dirty: bool,
+    /// Simulation time not stepped yet.
+    pending: Duration,
+    simulation: Option<Simulation>,
+    device: PerDevice<ParticleObjects>,
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            visible: true,
+            attractor: None,
+            attraction: 400_000.0,
+            drag: 0.5,
+            max_speed: 1500.0,
+            size: 6.0,
+            camera: OrthoCamera::default(),
+            count: 0,
+            particles: vec![],
+            dirty: false,
+            pending: Duration::ZERO,
+            simulation: None,
+            device: PerDevice::new(),
+        }
+    }
+
+    /// Replace the particles with `count` new ones, scattered over the
+    /// viewport of the next frame.  Fewer are kept when they do not fit
+    /// into one buffer of the device, see [`max_particles`].
+    pub fn spawn(&mut self, count: usize) {
+        self.count = count;
+        self.particles.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// How the particles were moved in the last frame, `None` before the first.
+    pub fn simulation(&self) -> Option<Simulation> {
+        self.simulation
+    }
+
+    /// Let `step` more time pass, simulated with the next frame.
+    pub fn advance(&mut self, step: Duration) {
+        if self.visible {
+            self.pending += step;
+        }
+    }
+
+    /// Step the particles by the time passed since the last frame and upload
+    /// the camera for a frame drawn into `targets`.  The compute pass, if
+    /// any, is recorded into `encoder` and has to come before the render pass.
+    /// `None` when there is nothing to draw.
+    pub fn prepare(&mut self, gpu: &GpuContext, encoder: &mut ::wgpu::CommandEncoder, targets: &RenderTargets) -> Option<PreparedParticles> {
+        if !self.visible || self.count == 0 {
+            return None;
+        }
+        self.camera.viewport = targets.size;
+        let bounds = [targets.size.0 as f32, targets.size.1 as f32];
+        // a new device starts with an empty particle buffer
+        self.dirty |= !self.device.is_current(gpu);
+        let objects = self.device.get_or_create(gpu, Self::create_device_objects);
+        let max_count = max_particles(&gpu.device.limits(), objects.simulator.is_some());
+        if self.count > max_count {
+            warn!("{} particles do not fit into one buffer on this device, keeping {}", self.count, max_count);
+            self.count = max_count;
+            self.particles.truncate(max_count);
+        }
+        if self.particles.len() != self.count {
+            self.particles = scatter(self.count, bounds, self.max_speed / 10.0);
+            self.dirty = true;
+        }
+        let key = (targets.format, targets.sample_count);
+        objects.ensure_pipeline(key, |objects, (format, sample_count)| {
+            info!("creating particle pipeline for {:?} with {}x MSAA", format, sample_count);
+            Self::create_pipeline(gpu, objects, format, sample_count)
+        });
+        // fields are borrowed separately below
+        let objects: &mut ParticleObjects = objects;
+
+        let steps = (self.pending.as_secs_f32() / MAX_STEP.as_secs_f32()).ceil().min(MAX_STEPS as f32) as u32;
+        let params = SimParams {
+            attractor: self.attractor,
+            bounds,
+            dt: if steps == 0 { 0.0 } else { self.pending.min(MAX_STEP * MAX_STEPS).as_secs_f32() / steps as f32 },
+            attraction: self.attraction,
+            drag: self.drag,
+            max_speed: self.max_speed,
+        };
+        self.pending = Duration::ZERO;
+        let bytes: &[u8] = ::bytemuck::cast_slice(&self.particles);
+        let usage = match objects.simulator {
+            Some(_) => ::wgpu::BufferUsages::STORAGE | ::wgpu::BufferUsages::VERTEX | ::wgpu::BufferUsages::COPY_DST,
+            None => ::wgpu::BufferUsages::VERTEX | ::wgpu::BufferUsages::COPY_DST,
+        };
+        if objects.particles.as_ref().is_none_or(|buffer| buffer.size() != bytes.len() as u64) {
+            objects.particles = Some(gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+                label: Some("particles"),
+                size: bytes.len() as u64,
+                usage,
+                mapped_at_creation: false,
+            }));
+            if let Some(simulator) = objects.simulator.as_mut() {
+                simulator.bind_group = None;
+            }
+            self.dirty = true;
+        }
+        let buffer = objects.particles.as_ref().expect("particle buffer was just created");
+
+        match objects.simulator.as_mut() {
+            Some(simulator) => {
+                if self.dirty {
+                    gpu.queue.write_buffer(buffer, 0, bytes);
+                    self.dirty = false;
+                }
+                gpu.queue.write_buffer(&simulator.params, 0, ::bytemuck::bytes_of(&ParamsUniform::new(&params, self.count as u32)));
+                let kernel = &simulator.kernel;
+                let bind_group = simulator.bind_group.get_or_insert_with(|| {
+                    kernel.bind_group(gpu, &[simulator.params.as_entire_binding(), buffer.as_entire_binding()])
+                });
+                let mut pass = encoder.begin_compute_pass(&::wgpu::ComputePassDescriptor { label: Some("particles"), timestamp_writes: None });
+                for _ in 0..steps {
+                    kernel.dispatch(gpu, &mut pass, bind_group, self.count as u32);
+                }
+                self.simulation = Some(Simulation::Gpu);
+            },
+            None => {
+                for _ in 0..steps {
+                    step(&mut self.particles, &params);
+                }
+                gpu.queue.write_buffer(buffer, 0, ::bytemuck::cast_slice(&self.particles));
+                self.dirty = false;
+                self.simulation = Some(Simulation::Cpu);
+            },
+        }
+        let camera = CameraUniform { view_proj: self.camera.view_proj(), size: self.size, max_speed: self.max_speed, _padding: [0.0; 2] };
+        gpu.queue.write_buffer(&objects.camera_buffer, 0, ::bytemuck::bytes_of(&camera));
+        Some(PreparedParticles { key, count: self.count as u32 })
+    }
+
+    /// Draw what [`ParticleSystem::prepare`] set up.
+    pub fn draw(&self, rpass: &mut ::wgpu::RenderPass<'_>, prepared: &PreparedParticles) {
+        let objects = self.device.get().expect("particles were prepared");
+        let buffer = objects.particles.as_ref().expect("particles were prepared");
+        rpass.set_pipeline(objects.pipeline(&prepared.key));
+        rpass.set_bind_group(0, &objects.camera_bind_group, &[]);
+        rpass.set_vertex_buffer(0, buffer.slice(..));
+        rpass.draw(0..6, 0..prepared.count);
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> ParticleObjects {
+        info!("creating particle objects for device generation {}", gpu.generation);
+        let device = &gpu.device;
+        let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/particles.wgsl"));
+        let camera_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("particle camera"),
+            entries: &[::wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ::wgpu::ShaderStages::VERTEX,
+                ty: ::wgpu::BindingType::Buffer {
+                    ty: ::wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("particles"),
+            bind_group_layouts: &[&camera_layout],
+            push_constant_ranges: &[],
+        });
+        let camera_buffer = device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some("particle camera"),
+            size: ::std::mem::size_of::<CameraUniform>() as u64,
+            usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("particle camera"),
+            layout: &camera_layout,
+            entries: &[::wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+        let simulator = match compute::supported(gpu) {
+            true => Some(Simulator {
+                kernel: ComputeKernel::new(
+                    gpu,
+                    "particles",
+                    ::wgpu::include_wgsl!("shaders/particles_compute.wgsl"),
+                    "cs_main",
+                    &[("SOFTENING", f64::from(SOFTENING))],
+                    &[compute::uniform_buffer(0), compute::storage_buffer(1, false)],
+                ),
+                params: device.create_buffer(&::wgpu::BufferDescriptor {
+                    label: Some("particle params"),
+                    size: ::std::mem::size_of::<ParamsUniform>() as u64,
+                    usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                bind_group: None,
+            }),
+            false => {
+                info!("no compute shaders on this device, simulating particles on the CPU");
+                None
+            },
+        };
+        ParticleObjects {
+            shader,
+            pipeline_layout,
+            camera_buffer,
+            camera_bind_group,
+            simulator,
+            particles: None,
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &ParticleObjects, format: ::wgpu::TextureFormat, sample_count: u32) -> ::wgpu::RenderPipeline {
+        let additive = ::wgpu::BlendComponent {
+            src_factor: ::wgpu::BlendFactor::SrcAlpha,
+            dst_factor: ::wgpu::BlendFactor::One,
+            operation: ::wgpu::BlendOperation::Add,
+        };
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("particles"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[Particle::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(::wgpu::BlendState { color: additive, alpha: ::wgpu::BlendComponent::OVER }),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState::default(),
+            // drawn over the scene like the sprites, in whatever order they are in
+            depth_stencil: Some(::wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: ::wgpu::CompareFunction::Always,
+                stencil: ::wgpu::StencilState::default(),
+                bias: ::wgpu::DepthBiasState::default(),
+            }),
+            multisample: ::wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+/// `count` particles spread evenly over `bounds`, moving in all directions
+/// at up to `speed`.  The same every time, so frames can be compared.
+fn scatter(count: usize, bounds: [f32; 2], speed: f32) -> Vec<Particle> {
+    // xorshift32, plenty for scattering dots
+    let mut state = 0x9e37_79b9u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 8) as f32 / (1 << 24) as f32
+    };
+    (0..count).map(|_| {
+        let position = [random() * bounds[0], random() * bounds[1]];
+        let (sin, cos) = (random() * ::std::f32::consts::TAU).sin_cos();
+        let speed = random() * speed;
+        Particle { position, velocity: [cos * speed, sin * speed] }
+    }).collect()
+}
@@ -0,0 +1,520 @@
+use ::std::collections::HashMap;
+
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::gpu::{white_texture, GpuContext, PerDevice, Samplers};
+use crate::model::{AlphaMode, Material, Model, ModelSampler, PbrVertex, TextureRef};
+use crate::renderer::{multiply, Matrix, IDENTITY};
+use crate::resources::{Handle, ResourceRegistry};
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+use crate::texture::{self, SamplerPreset, TextureOptions};
+
+/// The one light of the scene, plus a constant ambient term.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    /// Direction the light travels in, world space.
+    pub direction: [f32; 3],
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Linear RGB added to every surface, standing in for indirect light.
+    pub ambient: [f32; 3],
+}
+
+impl Default for Light {
+    /// Sunlight from above, over the left shoulder of the default camera.
+    fn default() -> Self {
+        Self { direction: [0.4, -1.0, -0.6], color: [1.0, 0.98, 0.95], intensity: 3.0, ambient: [0.08, 0.08, 0.1] }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct SceneUniform {
+    view_proj: Matrix,
+    eye: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawUniform {
+    model: Matrix,
+    normal: Matrix,
+    base_color: [f32; 4],
+    emissive: [f32; 4],
+    /// metallic, roughness, alpha cutoff, 1.0 when opaque
+    params: [f32; 4],
+}
+
+/// Cofactor of an element of the upper 3x3 of `m`.
+fn cofactor(m: &Matrix, column: usize, row: usize) -> f32 {
+    let (c1, c2) = ((column + 1) % 3, (column + 2) % 3);
+    let (r1, r2) = ((row + 1) % 3, (row + 2) % 3);
+    m[c1][r1] * m[c2][r2] - m[c1][r2] * m[c2][r1]
+}
+
+/// True when `m` turns geometry inside out, its upper 3x3 has a negative
+/// determinant.  glTF flips the winding of front faces for such nodes.
+pub fn is_mirrored(m: &Matrix) -> bool {
+    (0..3).map(|row| m[0][row] * cofactor(m, 0, row)).sum::<f32>() < 0.0
+}
+
+/// Inverse transpose of the upper 3x3 of `m`, up to a positive factor,
+/// which is all normals need before they are normalized.
+pub fn normal_matrix(m: &Matrix) -> Matrix {
+    let sign = if is_mirrored(m) { -1.0 } else { 1.0 };
+    let mut out = IDENTITY;
+    for (column, out_column) in out.iter_mut().enumerate().take(3) {
+        for (row, value) in out_column.iter_mut().enumerate().take(3) {
+            *value = sign * cofactor(m, column, row);
+        }
+    }
+    out
+}
+
+struct GpuPrimitive {
+    vertices: Handle<::wgpu::Buffer>,
+    indices: Handle<::wgpu::Buffer>,
+    index_format: ::wgpu::IndexFormat,
+    index_count: u32,
+    /// Into [`ModelInstance::materials`], the last one is the glTF default material.
+    material: usize,
+}
+
+/// A texture and the sampler it is drawn with.
+type GpuTexture = (Handle<::wgpu::Texture>, ModelSampler);
+
+struct GpuMaterial {
+    material: Material,
+    base_color: Option<GpuTexture>,
+    metallic_roughness: Option<GpuTexture>,
+    emissive: Option<GpuTexture>,
+}
+
+/// A model uploaded with [`ModelRenderer::add`].
+pub struct ModelInstance {
+    /// Placement of the whole model in the world.
+    pub transform: Matrix,
+    pub visible: bool,
+    meshes: Vec<Vec<GpuPrimitive>>,
+    materials: Vec<GpuMaterial>,
+    /// Mesh nodes with their transforms relative to the model.
+    nodes: Vec<(usize, Matrix)>,
+    /// One per material, made on first draw and again for a new device generation.
+    bind_groups: Option<(u64, Vec<::wgpu::BindGroup>)>,
+}
+
+pub type ModelId = usize;
+
+/// Pipelines differ in culling, blending and, for mirrored nodes, which winding faces the front.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    format: ::wgpu::TextureFormat,
+    sample_count: u32,
+    double_sided: bool,
+    blend: bool,
+    mirrored: bool,
+}
+
+struct PbrObjects {
+    shader: ::wgpu::ShaderModule,
+    draw_layout: ::wgpu::BindGroupLayout,
+    material_layout: ::wgpu::BindGroupLayout,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    scene_buffer: ::wgpu::Buffer,
+    scene_bind_group: ::wgpu::BindGroup,
+    /// One per glTF sampler in use.
+    samplers: Samplers<ModelSampler>,
+    /// Stands in for missing textures, white leaves the factors unchanged.
+    white: ::wgpu::Texture,
+    /// One [`DrawUniform`] per draw, `stride` bytes apart.  Grows to the most draws so far.
+    draws: Option<(::wgpu::Buffer, ::wgpu::BindGroup)>,
+    stride: u64,
+}
+
+struct PreparedDraw {
+    model: ModelId,
+    mesh: usize,
+    primitive: usize,
+    key: PipelineKey,
+    offset: u32,
+}
+
+/// What [`ModelRenderer::prepare`] uploaded for one frame.
+pub struct PreparedModels {
+    draws: Vec<PreparedDraw>,
+}
+
+/// Draws glTF [`Model`]s with `shaders/pbr.wgsl`, after the meshes of the
+/// same render pass and sharing their depth buffer.
+///
+/// Everything stays within `wgpu::Limits::downlevel_webgl2_defaults()`:
+/// uniform buffers only, three bind groups, 16 bit indices where they fit
+/// and textures scaled down to `max_texture_dimension_2d`.  Opaque and
+/// masked primitives are drawn first, blended ones after them, farthest first.
+pub struct ModelRenderer {
+    pub light: Light,
+    models: Vec<ModelInstance>,
+    device: PerDevice<PbrObjects, PipelineKey>,
+}
+
+impl Default for ModelRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelRenderer {
+    pub fn new() -> Self {
+        Self { light: Light::default(), models: vec![], device: PerDevice::new() }
+    }
+
+    /// Upload the meshes and textures of `model`.  Buffers and textures are
+    /// registered with `resources`, so they survive a device loss.  Images
+    /// that cannot be decoded are left out and the material's factors used alone.
+    pub fn add(&mut self, gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>, model: &Model) -> ModelId {
+        let id = self.models.len();
+        let max_dimension = gpu.device.limits().max_texture_dimension_2d;
+        let mut textures: HashMap<(usize, bool), Option<Handle<::wgpu::Texture>>> = HashMap::new();
+        let mut texture = |texture: Option<TextureRef>, srgb: bool| {
+            let TextureRef { image, sampler, .. } = texture?;
+            let handle = *textures.entry((image, srgb)).or_insert_with(|| {
+                let source = model.images.get(image)?;
+                match texture::decode(&source.name, &source.bytes, srgb) {
+                    Ok(data) => {
+                        let data = texture::downscale(data, max_dimension);
+                        let options = TextureOptions { srgb, mipmaps: true, sampler: SamplerPreset::Trilinear };
+                        Some(texture::upload(gpu, resources, &source.name, data, options).texture)
+                    },
+                    Err(e) => {
+                        info!("leaving out {}: {}", source.name, e);
+                        None
+                    },
+                }
+            });
+            handle.map(|handle| (handle, sampler))
+        };
+        let materials: Vec<GpuMaterial> = model.materials.iter()
+            .chain(::std::iter::once(&Material::default()))
+            .map(|material| GpuMaterial {
+                material: *material,
+                base_color: texture(material.base_color_texture, true),
+                metallic_roughness: texture(material.metallic_roughness_texture, false),
+                emissive: texture(material.emissive_texture, true),
+            })
+            .collect();
+
+        let default_material = materials.len() - 1;
+        let wide_indices = gpu.adapter.get_downlevel_capabilities().flags.contains(::wgpu::DownlevelFlags::FULL_DRAW_INDEX_UINT32);
+        let meshes = model.meshes.iter().enumerate().map(|(m, mesh)| {
+            mesh.primitives.iter().enumerate().map(|(p, primitive)| {
+                let label = format!("model {} mesh {} primitive {}", id, m, p);
+                let vertices = resources.buffer(gpu, &format!("{} vertices", label), ::wgpu::BufferUsages::VERTEX, ::bytemuck::cast_slice::<PbrVertex, u8>(&primitive.vertices).to_vec());
+                let (index_format, bytes) = if primitive.vertices.len() <= usize::from(u16::MAX) + 1 {
+                    let mut indices: Vec<u16> = primitive.indices.iter().map(|&i| i as u16).collect();
+                    // buffer sizes must be a multiple of 4
+                    if indices.len() % 2 == 1 {
+                        indices.push(0);
+                    }
+                    (::wgpu::IndexFormat::Uint16, ::bytemuck::cast_slice(&indices).to_vec())
+                } else {
+                    if !wide_indices {
+                        info!("{} has {} vertices, the device may only draw the first 2^24", label, primitive.vertices.len());
+                    }
+                    (::wgpu::IndexFormat::Uint32, ::bytemuck::cast_slice(&primitive.indices).to_vec())
+                };
+                let indices = resources.buffer(gpu, &format!("{} indices", label), ::wgpu::BufferUsages::INDEX, bytes);
+                GpuPrimitive {
+                    vertices,
+                    indices,
+                    index_format,
+                    index_count: primitive.indices.len() as u32,
+                    material: primitive.material.filter(|&material| material < default_material).unwrap_or(default_material),
+                }
+            }).collect()
+        }).collect();
+        let nodes = model.world_transforms().into_iter()
+            .filter_map(|(node, world)| model.nodes[node].mesh.map(|mesh| (mesh, world)))
+            .collect();
+        self.models.push(ModelInstance { transform: IDENTITY, visible: true, meshes, materials, nodes, bind_groups: None });
+        id
+    }
+
+    pub fn model_mut(&mut self, id: ModelId) -> &mut ModelInstance {
+        &mut self.models[id]
+    }
+
+    /// Remove every model, ids handed out before are no longer valid.
+    pub fn clear(&mut self) {
+        self.models.clear();
+    }
+
+    /// Upload the uniforms for a frame seen through `view_proj` from `eye`,
+    /// `None` when there is nothing to draw.
+    pub fn prepare(&mut self, gpu: &GpuContext, resources: &ResourceRegistry<GpuContext>, targets: &RenderTargets, view_proj: &Matrix, eye: [f32; 3]) -> Option<PreparedModels> {
+        if !self.models.iter().any(|model| model.visible && !model.nodes.is_empty()) {
+            return None;
+        }
+        let objects = self.device.get_or_create(gpu, Self::create_device_objects);
+
+        let mut uniforms = vec![];
+        let mut draws = vec![];
+        let mut blended = vec![];
+        for (id, model) in self.models.iter().enumerate().filter(|(_, model)| model.visible) {
+            for &(mesh, world) in model.nodes.iter() {
+                let transform = multiply(&model.transform, &world);
+                for (p, primitive) in model.meshes[mesh].iter().enumerate() {
+                    let material = &model.materials[primitive.material].material;
+                    let blend = material.alpha_mode == AlphaMode::Blend;
+                    let key = PipelineKey {
+                        format: targets.format,
+                        sample_count: targets.sample_count,
+                        double_sided: material.double_sided,
+                        blend,
+                        mirrored: is_mirrored(&transform),
+                    };
+                    let draw = PreparedDraw { model: id, mesh, primitive: p, key, offset: (uniforms.len() as u64 * objects.stride) as u32 };
+                    uniforms.push(DrawUniform {
+                        model: transform,
+                        normal: normal_matrix(&transform),
+                        base_color: material.base_color,
+                        emissive: [material.emissive[0], material.emissive[1], material.emissive[2], 0.0],
+                        params: [
+                            material.metallic,
+                            material.roughness,
+                            if material.alpha_mode == AlphaMode::Mask { material.alpha_cutoff } else { -1.0 },
+                            if material.alpha_mode == AlphaMode::Blend { 0.0 } else { 1.0 },
+                        ],
+                    });
+                    if blend {
+                        let origin = [transform[3][0] - eye[0], transform[3][1] - eye[1], transform[3][2] - eye[2]];
+                        blended.push((origin.iter().map(|c| c * c).sum::<f32>(), draw));
+                    } else {
+                        draws.push(draw);
+                    }
+                }
+            }
+        }
+        blended.sort_by(|a, b| b.0.total_cmp(&a.0));
+        draws.extend(blended.into_iter().map(|(_, draw)| draw));
+
+        for draw in draws.iter() {
+            objects.ensure_pipeline(draw.key, |objects, key| {
+                info!("creating model pipeline for {:?}", key);
+                Self::create_pipeline(gpu, objects, key)
+            });
+        }
+
+        let stride = objects.stride as usize;
+        let mut bytes = vec![0u8; uniforms.len() * stride];
+        for (i, uniform) in uniforms.iter().enumerate() {
+            bytes[i * stride..i * stride + ::std::mem::size_of::<DrawUniform>()].copy_from_slice(::bytemuck::bytes_of(uniform));
+        }
+        if objects.draws.as_ref().is_none_or(|(buffer, _)| buffer.size() < bytes.len() as u64) {
+            let buffer = gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+                label: Some("model draws"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = gpu.device.create_bind_group(&::wgpu::BindGroupDescriptor {
+                label: Some("model draws"),
+                layout: &objects.draw_layout,
+                entries: &[::wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: ::wgpu::BindingResource::Buffer(::wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: ::wgpu::BufferSize::new(::std::mem::size_of::<DrawUniform>() as u64),
+                    }),
+                }],
+            });
+            objects.draws = Some((buffer, bind_group));
+        }
+        let (buffer, _) = objects.draws.as_ref().expect("model draw buffer was just created");
+        gpu.queue.write_buffer(buffer, 0, &bytes);
+        let light = &self.light;
+        let scene = SceneUniform {
+            view_proj: *view_proj,
+            eye: [eye[0], eye[1], eye[2], 1.0],
+            light_direction: [light.direction[0], light.direction[1], light.direction[2], 0.0],
+            light_color: [light.color[0] * light.intensity, light.color[1] * light.intensity, light.color[2] * light.intensity, 1.0],
+            ambient: [light.ambient[0], light.ambient[1], light.ambient[2], 1.0],
+        };
+        gpu.queue.write_buffer(&objects.scene_buffer, 0, ::bytemuck::bytes_of(&scene));
+
+        for model in self.models.iter_mut().filter(|model| model.visible) {
+            if model.bind_groups.as_ref().is_none_or(|(generation, _)| *generation != gpu.generation) {
+                let bind_groups = model.materials.iter().map(|material| Self::create_material_bind_group(gpu, resources, objects, material)).collect();
+                model.bind_groups = Some((gpu.generation, bind_groups));
+            }
+        }
+        Some(PreparedModels { draws })
+    }
+
+    /// Draw what [`ModelRenderer::prepare`] uploaded.
+    pub fn draw(&self, rpass: &mut ::wgpu::RenderPass<'_>, resources: &ResourceRegistry<GpuContext>, prepared: &PreparedModels) {
+        let objects = self.device.get().expect("models were prepared");
+        let (_, draws) = objects.draws.as_ref().expect("models were prepared");
+        rpass.set_bind_group(0, &objects.scene_bind_group, &[]);
+        for draw in prepared.draws.iter() {
+            let model = &self.models[draw.model];
+            let primitive = &model.meshes[draw.mesh][draw.primitive];
+            let (_, materials) = model.bind_groups.as_ref().expect("models were prepared");
+            rpass.set_pipeline(objects.pipeline(&draw.key));
+            rpass.set_bind_group(1, draws, &[draw.offset]);
+            rpass.set_bind_group(2, &materials[primitive.material], &[]);
+            rpass.set_vertex_buffer(0, resources.get(primitive.vertices).slice(..));
+            rpass.set_index_buffer(resources.get(primitive.indices).slice(..), primitive.index_format);
+            rpass.draw_indexed(0..primitive.index_count, 0, 0..1);
+        }
+    }
+
+    fn create_material_bind_group(gpu: &GpuContext, resources: &ResourceRegistry<GpuContext>, objects: &mut PbrObjects, material: &GpuMaterial) -> ::wgpu::BindGroup {
+        let textures = [material.base_color, material.metallic_roughness, material.emissive];
+        // the white stand-in is sampled with the default sampler
+        let samplers = textures.map(|texture| texture.map_or(ModelSampler::default(), |(_, sampler)| sampler));
+        for sampler in samplers {
+            objects.samplers.insert(gpu, sampler);
+        }
+        let views = textures.map(|texture| texture.map_or(&objects.white, |(texture, _)| resources.get(texture))
+            .create_view(&::wgpu::TextureViewDescriptor::default()));
+        let samplers = samplers.map(|sampler| objects.samplers.get(sampler));
+        gpu.device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("model material"),
+            layout: &objects.material_layout,
+            entries: &[
+                ::wgpu::BindGroupEntry { binding: 0, resource: ::wgpu::BindingResource::TextureView(&views[0]) },
+                ::wgpu::BindGroupEntry { binding: 1, resource: ::wgpu::BindingResource::TextureView(&views[1]) },
+                ::wgpu::BindGroupEntry { binding: 2, resource: ::wgpu::BindingResource::TextureView(&views[2]) },
+                ::wgpu::BindGroupEntry { binding: 3, resource: ::wgpu::BindingResource::Sampler(samplers[0]) },
+                ::wgpu::BindGroupEntry { binding: 4, resource: ::wgpu::BindingResource::Sampler(samplers[1]) },
+                ::wgpu::BindGroupEntry { binding: 5, resource: ::wgpu::BindingResource::Sampler(samplers[2]) },
+            ],
+        })
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> PbrObjects {
+        info!("creating model objects for device generation {}", gpu.generation);
+        let device = &gpu.device;
+        let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/pbr.wgsl"));
+        let uniform = |dynamic, size: usize| ::wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ::wgpu::ShaderStages::VERTEX | ::wgpu::ShaderStages::FRAGMENT,
+            ty: ::wgpu::BindingType::Buffer {
+                ty: ::wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: dynamic,
+                min_binding_size: ::wgpu::BufferSize::new(size as u64),
+            },
+            count: None,
+        };
+        let scene_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("model scene"),
+            entries: &[uniform(false, ::std::mem::size_of::<SceneUniform>())],
+        });
+        let draw_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("model draws"),
+            entries: &[uniform(true, ::std::mem::size_of::<DrawUniform>())],
+        });
+        let texture = |binding| ::wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ::wgpu::ShaderStages::FRAGMENT,
+            ty: ::wgpu::BindingType::Texture {
+                sample_type: ::wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: ::wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| ::wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ::wgpu::ShaderStages::FRAGMENT,
+            ty: ::wgpu::BindingType::Sampler(::wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let material_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("model material"),
+            entries: &[
+                texture(0),
+                texture(1),
+                texture(2),
+                sampler(3),
+                sampler(4),
+                sampler(5),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("model"),
+            bind_group_layouts: &[&scene_layout, &draw_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let scene_buffer = device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some("model scene"),
+            size: ::std::mem::size_of::<SceneUniform>() as u64,
+            usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let scene_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("model scene"),
+            layout: &scene_layout,
+            entries: &[::wgpu::BindGroupEntry { binding: 0, resource: scene_buffer.as_entire_binding() }],
+        });
+        let alignment = u64::from(device.limits().min_uniform_buffer_offset_alignment);
+        let stride = (::std::mem::size_of::<DrawUniform>() as u64).div_ceil(alignment) * alignment;
+        PbrObjects {
+            shader,
+            draw_layout,
+            material_layout,
+            pipeline_layout,
+            scene_buffer,
+            scene_bind_group,
+            samplers: Samplers::new(gpu, []),
+            white: white_texture(gpu),
+            draws: None,
+            stride,
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &PbrObjects, PipelineKey { format, sample_count, double_sided, blend, mirrored }: PipelineKey) -> ::wgpu::RenderPipeline {
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("model"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[PbrVertex::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: blend.then_some(::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState {
+                front_face: if mirrored { ::wgpu::FrontFace::Cw } else { ::wgpu::FrontFace::Ccw },
+                cull_mode: (!double_sided).then_some(::wgpu::Face::Back),
+                ..Default::default()
+            },
+            // blended surfaces are tested against the depth buffer but do not hide what is behind them
+            depth_stencil: Some(::wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: !blend,
+                depth_compare: ::wgpu::CompareFunction::LessEqual,
+                stencil: ::wgpu::StencilState::default(),
+                bias: ::wgpu::DepthBiasState::default(),
+            }),
+            multisample: ::wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
@@ -0,0 +1,514 @@
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::gpu::{white_texture, GpuContext, PerDevice, Samplers};
+use crate::overlay::PerfOverlay;
+use crate::particles::ParticleSystem;
+use crate::pbr::ModelRenderer;
+use crate::resources::{Handle, ResourceRegistry};
+use crate::sprites::{Sprite, SpriteBatch, SpriteId};
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+use crate::text::TextRenderer;
+use crate::ui::UiPainter;
+use crate::texture::{LoadedTexture, SamplerPreset};
+use crate::timing::GpuTimer;
+
+pub type Matrix = [[f32; 4]; 4];
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Column-major 4x4 matrix product `a * b`.
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+    for (column, out_column) in out.iter_mut().enumerate() {
+        for (row, value) in out_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    out
+}
+
+pub fn translation(x: f32, y: f32, z: f32) -> Matrix {
+    let mut matrix = IDENTITY;
+    matrix[3] = [x, y, z, 1.0];
+    matrix
+}
+
+/// Counterclockwise rotation around the Z axis, `angle` in radians.
+pub fn rotation_z(angle: f32) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+    let mut matrix = IDENTITY;
+    matrix[0] = [cos, sin, 0.0, 0.0];
+    matrix[1] = [-sin, cos, 0.0, 0.0];
+    matrix
+}
+
+pub fn scale(x: f32, y: f32, z: f32) -> Matrix {
+    let mut matrix = IDENTITY;
+    matrix[0][0] = x;
+    matrix[1][1] = y;
+    matrix[2][2] = z;
+    matrix
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    /// Linear RGBA
+    pub color: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    const ATTRIBUTES: [::wgpu::VertexAttribute; 3] = ::wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4, 2 => Float32x2];
+
+    pub fn layout() -> ::wgpu::VertexBufferLayout<'static> {
+        ::wgpu::VertexBufferLayout {
+            array_stride: ::std::mem::size_of::<Vertex>() as ::wgpu::BufferAddress,
+            step_mode: ::wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Mesh data on the CPU side.  16 bit indices, so it also works on GLES
+/// devices without `FULL_DRAW_INDEX_UINT32`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl Mesh {
+    pub fn triangle(colors: [[f32; 4]; 3]) -> Self {
+        let positions = [[0.0, 0.5, 0.0], [-0.5, -0.5, 0.0], [0.5, -0.5, 0.0]];
+        let uvs = [[0.5, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        Self {
+            vertices: (0..3).map(|i| Vertex { position: positions[i], color: colors[i], uv: uvs[i] }).collect(),
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    /// Unit quad centered on the origin, textures are mapped upright.
+    pub fn quad(color: [f32; 4]) -> Self {
+        let corners = [([-0.5, 0.5], [0.0, 0.0]), ([-0.5, -0.5], [0.0, 1.0]), ([0.5, -0.5], [1.0, 1.0]), ([0.5, 0.5], [1.0, 0.0])];
+        Self {
+            vertices: corners.iter().map(|([x, y], uv)| Vertex { position: [*x, *y, 0.0], color, uv: *uv }).collect(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CameraUniform {
+    view_proj: Matrix,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ModelUniform {
+    transform: Matrix,
+    tint: [f32; 4],
+}
+
+pub type MeshId = usize;
+
+/// Meshes added by [`Renderer::demo_scene`].
+#[derive(Debug, Clone, Copy)]
+pub struct DemoScene {
+    pub triangle: MeshId,
+    pub quad: MeshId,
+    /// Follows the pointer, hidden until the first pointer event.
+    pub cursor: SpriteId,
+}
+
+/// Device generation, texture and sampler a mesh bind group was made for.
+type BindGroupKey = (u64, Option<Handle<::wgpu::Texture>>, SamplerPreset);
+
+/// A mesh uploaded with [`Renderer::add_mesh`].
+pub struct MeshInstance {
+    vertices: Handle<::wgpu::Buffer>,
+    indices: Handle<::wgpu::Buffer>,
+    model: Handle<::wgpu::Buffer>,
+    index_count: u32,
+    texture: Option<Handle<::wgpu::Texture>>,
+    pub sampler: SamplerPreset,
+    pub transform: Matrix,
+    /// Multiplied with the vertex colors.
+    pub tint: [f32; 4],
+    pub visible: bool,
+    /// Made again when its key changes.
+    bind_group: Option<(BindGroupKey, ::wgpu::BindGroup)>,
+}
+
+/// Objects tied to one device, created on first use and again after the device changed.
+struct MeshObjects {
+    shader: ::wgpu::ShaderModule,
+    camera_layout: ::wgpu::BindGroupLayout,
+    model_layout: ::wgpu::BindGroupLayout,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    camera_buffer: ::wgpu::Buffer,
+    camera_bind_group: ::wgpu::BindGroup,
+    samplers: Samplers<SamplerPreset>,
+    /// Stands in for the texture of untextured meshes.
+    white: ::wgpu::Texture,
+    /// Times the mesh pass, when the device supports timestamp queries.
+    timer: Option<GpuTimer>,
+}
+
+/// Draws meshes with `shaders/mesh.wgsl`.
+///
+/// Buffers and textures live in the [`ResourceRegistry`] so they survive a
+/// device loss; the shader, layouts and pipelines are rebuilt here when
+/// [`GpuContext::generation`] changes.
+pub struct Renderer {
+    pub clear_color: ::wgpu::Color,
+    pub view_proj: Matrix,
+    /// Camera position in world space, for the specular highlights of `models`.
+    pub eye: [f32; 3],
+    /// glTF models, drawn after the meshes in the same pass.
+    pub models: ModelRenderer,
+    /// Drawn after the models in the same pass, simulated in a compute pass
+    /// before it when the device can.
+    pub particles: ParticleSystem,
+    /// Drawn after the particles, in the same pass.
+    pub sprites: SpriteBatch,
+    /// Drawn after the sprites, in the same pass.
+    pub text: TextRenderer,
+    /// egui, in a pass of its own after the main one.
+    pub ui: UiPainter,
+    /// Drawn last, on top of the meshes.
+    pub overlay: PerfOverlay,
+    meshes: Vec<MeshInstance>,
+    /// With one pipeline per target format and sample count, a surface may
+    /// change format between resumes.
+    device: PerDevice<MeshObjects>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self {
+            clear_color: ::wgpu::Color { r: 0.5, g: 0.76, b: 0.5, a: 1.0 },
+            view_proj: IDENTITY,
+            // behind the near plane of clip space, as seen through `IDENTITY`
+            eye: [0.0, 0.0, -1.0],
+            models: ModelRenderer::new(),
+            particles: ParticleSystem::new(),
+            sprites: SpriteBatch::new(),
+            text: TextRenderer::new(),
+            ui: UiPainter::new(),
+            overlay: PerfOverlay::new(),
+            meshes: vec![],
+            device: PerDevice::new(),
+        }
+    }
+
+    /// A colored triangle next to a textured quad, and a sprite for the pointer.
+    pub fn demo_scene(&mut self, gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>) -> DemoScene {
+        let triangle = self.add_mesh(gpu, resources, "triangle", &Mesh::triangle([
+            [1.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0, 1.0],
+        ]), None);
+        self.meshes[triangle].transform = translation(-0.45, 0.0, 0.0);
+
+        let checker = checkerboard(8, 8, [255, 255, 255, 255], [40, 40, 40, 255]);
+        let texture = Self::create_texture(gpu, resources, "checkerboard", 8, 8, checker);
+        let quad = self.add_mesh(gpu, resources, "quad", &Mesh::quad([1.0, 1.0, 1.0, 1.0]), Some(texture));
+        self.meshes[quad].transform = multiply(&translation(0.45, 0.0, 0.0), &scale(0.7, 0.7, 1.0));
+
+        let cursor = self.sprites.add(Sprite {
+            texture: Some(texture),
+            color: [1.0, 0.8, 0.2, 0.8],
+            rotation: ::std::f32::consts::FRAC_PI_4,
+            visible: false,
+            ..Sprite::new([0.0, 0.0], [32.0, 32.0])
+        });
+        DemoScene { triangle, quad, cursor }
+    }
+
+    /// Upload `mesh`, drawn with `texture` when given and its vertex colors otherwise.
+    pub fn add_mesh(&mut self, gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>, label: &str, mesh: &Mesh, texture: Option<Handle<::wgpu::Texture>>) -> MeshId {
+        // odd index counts are padded, buffer sizes must be a multiple of 4
+        let mut indices = mesh.indices.clone();
+        if indices.len() % 2 == 1 {
+            indices.push(0);
+        }
+        let vertices = resources.buffer(gpu, &format!("{} vertices", label), ::wgpu::BufferUsages::VERTEX, ::bytemuck::cast_slice(&mesh.vertices).to_vec());
+        let index_buffer = resources.buffer(gpu, &format!("{} indices", label), ::wgpu::BufferUsages::INDEX, ::bytemuck::cast_slice(&indices).to_vec());
+        let model = resources.buffer(
+            gpu,
+            &format!("{} model", label),
+            ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            vec![0; ::std::mem::size_of::<ModelUniform>()],
+        );
+        self.meshes.push(MeshInstance {
+            vertices,
+            indices: index_buffer,
+            model,
+            index_count: mesh.indices.len() as u32,
+            texture,
+            sampler: SamplerPreset::Pixel,
+            transform: IDENTITY,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            visible: true,
+            bind_group: None,
+        });
+        self.meshes.len() - 1
+    }
+
+    /// Draw `mesh` with a texture from [`crate::texture::load_texture`] and its sampler.
+    pub fn set_texture(&mut self, mesh: MeshId, texture: &LoadedTexture) {
+        let mesh = &mut self.meshes[mesh];
+        mesh.texture = Some(texture.texture);
+        mesh.sampler = texture.sampler;
+    }
+
+    pub fn mesh_mut(&mut self, id: MeshId) -> &mut MeshInstance {
+        &mut self.meshes[id]
+    }
+
+    /// An sRGB RGBA8 texture for [`Renderer::add_mesh`].
+    pub fn create_texture(gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>, label: &str, width: u32, height: u32, rgba: Vec<u8>) -> Handle<::wgpu::Texture> {
+        resources.texture(gpu, label, ::wgpu::TextureDescriptor {
+            label: None,
+            size: ::wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: ::wgpu::TextureDimension::D2,
+            format: ::wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: ::wgpu::TextureUsages::TEXTURE_BINDING | ::wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        }, rgba)
+    }
+
+    /// Clear `view` and draw every visible mesh into it, then the models, particles, sprites and text, then egui and the overlay.
+    /// `targets` supply the depth buffer and, with MSAA, the multisampled
+    /// color texture that is resolved into `view`.
+    pub fn render(&mut self, gpu: &GpuContext, resources: &ResourceRegistry<GpuContext>, encoder: &mut ::wgpu::CommandEncoder, view: &::wgpu::TextureView, targets: &RenderTargets) {
+        let key = (targets.format, targets.sample_count);
+        self.device.get_or_create(gpu, Self::create_device_objects).ensure_pipeline(key, |objects, (format, sample_count)| {
+            info!("creating mesh pipeline for {:?} with {}x MSAA", format, sample_count);
+            Self::create_pipeline(gpu, objects, format, sample_count)
+        });
+        let models = self.models.prepare(gpu, resources, targets, &self.view_proj, self.eye);
+        let particles = self.particles.prepare(gpu, encoder, targets);
+        let sprites = self.sprites.prepare(gpu, resources, targets);
+        let text = self.text.prepare(gpu, targets);
+        let objects = self.device.get().expect("device objects were just created");
+        let pipeline = objects.pipeline(&key);
+
+        gpu.queue.write_buffer(&objects.camera_buffer, 0, ::bytemuck::bytes_of(&CameraUniform { view_proj: self.view_proj }));
+        for mesh in self.meshes.iter_mut().filter(|mesh| mesh.visible) {
+            gpu.queue.write_buffer(resources.get(mesh.model), 0, ::bytemuck::bytes_of(&ModelUniform { transform: mesh.transform, tint: mesh.tint }));
+            let key = (gpu.generation, mesh.texture, mesh.sampler);
+            if mesh.bind_group.as_ref().is_none_or(|(current, _)| *current != key) {
+                mesh.bind_group = Some((key, Self::create_bind_group(gpu, resources, objects, mesh)));
+            }
+        }
+
+        let timer = objects.timer.as_ref();
+        let mut rpass = encoder.begin_render_pass(&::wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(targets.color_attachment(view, ::wgpu::LoadOp::Clear(self.clear_color)))],
+            depth_stencil_attachment: Some(targets.depth_attachment()),
+            timestamp_writes: timer.and_then(GpuTimer::timestamp_writes),
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, &objects.camera_bind_group, &[]);
+        for mesh in self.meshes.iter().filter(|mesh| mesh.visible) {
+            let (_, bind_group) = mesh.bind_group.as_ref().expect("bind groups were just created");
+            rpass.set_bind_group(1, bind_group, &[]);
+            rpass.set_vertex_buffer(0, resources.get(mesh.vertices).slice(..));
+            rpass.set_index_buffer(resources.get(mesh.indices).slice(..), ::wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+        if let Some(models) = models.as_ref() {
+            self.models.draw(&mut rpass, resources, models);
+        }
+        if let Some(particles) = particles.as_ref() {
+            self.particles.draw(&mut rpass, particles);
+        }
+        if let Some(sprites) = sprites.as_ref() {
+            self.sprites.draw(&mut rpass, sprites);
+        }
+        if let Some(text) = text.as_ref() {
+            self.text.draw(&mut rpass, text);
+        }
+        drop(rpass);
+
+        if let Some(timer) = self.device.get_mut().and_then(|objects| objects.timer.as_mut()) {
+            timer.resolve(encoder);
+        }
+        self.ui.render(gpu, encoder, view, targets);
+        self.overlay.render(gpu, encoder, view, targets.format);
+    }
+
+    /// Queue the text and egui frame of the last [`Renderer::render`] again,
+    /// so the next one draws the same frame.  Both are used up by a render.
+    pub fn repeat_frame(&mut self) {
+        self.text.requeue_drawn();
+        self.ui.repaint_last();
+    }
+
+    /// The encoder passed to [`Renderer::render`] was submitted.
+    pub fn frame_submitted(&mut self) {
+        if let Some(timer) = self.device.get_mut().and_then(|objects| objects.timer.as_mut()) {
+            timer.submitted();
+        }
+    }
+
+    /// GPU time of the mesh pass of an earlier frame, whenever a new
+    /// measurement arrived.  Always `None` without timestamp queries.
+    pub fn gpu_time(&mut self, gpu: &GpuContext) -> Option<::std::time::Duration> {
+        self.device.get_mut()
+            .filter(|objects| objects.generation == gpu.generation)
+            .and_then(|objects| objects.timer.as_mut())
+            .and_then(|timer| timer.poll(gpu))
+    }
+
+    fn create_bind_group(gpu: &GpuContext, resources: &ResourceRegistry<GpuContext>, objects: &MeshObjects, mesh: &MeshInstance) -> ::wgpu::BindGroup {
+        let texture = mesh.texture.map_or_else(|| &objects.white, |texture| resources.get(texture))
+            .create_view(&::wgpu::TextureViewDescriptor::default());
+        gpu.device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("mesh model"),
+            layout: &objects.model_layout,
+            entries: &[
+                ::wgpu::BindGroupEntry { binding: 0, resource: resources.get(mesh.model).as_entire_binding() },
+                ::wgpu::BindGroupEntry { binding: 1, resource: ::wgpu::BindingResource::TextureView(&texture) },
+                ::wgpu::BindGroupEntry { binding: 2, resource: ::wgpu::BindingResource::Sampler(objects.samplers.get(mesh.sampler)) },
+            ],
+        })
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> MeshObjects {
+        info!("creating mesh renderer objects for device generation {}", gpu.generation);
+        let device = &gpu.device;
+        let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/mesh.wgsl"));
+        let uniform = |binding, visibility| ::wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: ::wgpu::BindingType::Buffer {
+                ty: ::wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let camera_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera"),
+            entries: &[uniform(0, ::wgpu::ShaderStages::VERTEX)],
+        });
+        let model_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh model"),
+            entries: &[
+                uniform(0, ::wgpu::ShaderStages::VERTEX),
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Texture {
+                        sample_type: ::wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: ::wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Sampler(::wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("mesh"),
+            bind_group_layouts: &[&camera_layout, &model_layout],
+            push_constant_ranges: &[],
+        });
+        let camera_buffer = device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some("camera"),
+            size: ::std::mem::size_of::<CameraUniform>() as u64,
+            usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("camera"),
+            layout: &camera_layout,
+            entries: &[::wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+        MeshObjects {
+            shader,
+            camera_layout,
+            model_layout,
+            pipeline_layout,
+            camera_buffer,
+            camera_bind_group,
+            samplers: Samplers::new(gpu, SamplerPreset::ALL),
+            white: white_texture(gpu),
+            timer: GpuTimer::new(gpu),
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &MeshObjects, format: ::wgpu::TextureFormat, sample_count: u32) -> ::wgpu::RenderPipeline {
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("mesh"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[Vertex::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState {
+                topology: ::wgpu::PrimitiveTopology::TriangleList,
+                front_face: ::wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // less or equal, so meshes at the same depth still draw in order
+            depth_stencil: Some(::wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: ::wgpu::CompareFunction::LessEqual,
+                stencil: ::wgpu::StencilState::default(),
+                bias: ::wgpu::DepthBiasState::default(),
+            }),
+            multisample: ::wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+/// RGBA8 checkerboard with one pixel squares.
+pub fn checkerboard(width: u32, height: u32, a: [u8; 4], b: [u8; 4]) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| if (x + y) % 2 == 0 { a } else { b }))
+        .flatten()
+        .collect()
+}
@@ -0,0 +1,111 @@
+use ::std::any::Any;
+use ::std::marker::PhantomData;
+
+use ::log::info;
+
+/// Refers to a resource in a [`ResourceRegistry`].  Stays valid when the
+/// registry is rebuilt, only the resource behind it changes.
+#[derive(Debug)]
+pub struct Handle<T> {
+    index: usize,
+    _resource: PhantomData<fn() -> T>,
+}
+
+impl <T>Handle<T> {
+    /// A handle to the `index`th registered resource, without a registry.
+    /// Only meant for tests that need handles but no resources behind them.
+    #[doc(hidden)]
+    pub fn from_index(index: usize) -> Self {
+        Self { index, _resource: PhantomData }
+    }
+}
+
+impl <T>Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <T>Copy for Handle<T> {}
+
+impl <T>PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl <T>Eq for Handle<T> {}
+
+impl <T>::std::hash::Hash for Handle<T> {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+type Recipe<C> = Box<dyn Fn(&C) -> Box<dyn Any>>;
+
+struct Entry<C> {
+    label: String,
+    rebuild: Recipe<C>,
+    resource: Box<dyn Any>,
+}
+
+/// GPU resources together with how to create them.
+///
+/// Everything created from a device goes away with it.  Registering the
+/// recipe (a closure over the descriptor and any data to upload) instead of
+/// only the result lets [`ResourceRegistry::rebuild`] create it all again on a
+/// new device after the old one was lost.  `C` is whatever the recipes are
+/// created from, [`crate::gpu::GpuContext`] in the app.
+pub struct ResourceRegistry<C> {
+    entries: Vec<Entry<C>>,
+}
+
+impl <C>Default for ResourceRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <C>ResourceRegistry<C> {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// Create a resource with `recipe` and keep the recipe for [`ResourceRegistry::rebuild`].
+    pub fn register<T: 'static, F>(&mut self, context: &C, label: &str, recipe: F) -> Handle<T>
+    where
+        F: Fn(&C) -> T + 'static,
+    {
+        let resource: Box<dyn Any> = Box::new(recipe(context));
+        self.entries.push(Entry {
+            label: label.to_string(),
+            rebuild: Box::new(move |context| Box::new(recipe(context))),
+            resource,
+        });
+        Handle { index: self.entries.len() - 1, _resource: PhantomData }
+    }
+
+    pub fn get<T: 'static>(&self, handle: Handle<T>) -> &T {
+        self.entries[handle.index].resource
+            .downcast_ref()
+            .expect("handle belongs to this registry")
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Create every registered resource again, in registration order.
+    pub fn rebuild(&mut self, context: &C) {
+        info!("rebuilding {} gpu resources", self.entries.len());
+        for entry in self.entries.iter_mut() {
+            info!("rebuilding {}", entry.label);
+            entry.resource = (entry.rebuild)(context);
+        }
+    }
+}
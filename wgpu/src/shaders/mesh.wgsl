@@ -0,0 +1,43 @@
+// Colored and textured meshes.  Untextured meshes are drawn with a 1x1 white
+// texture, so the vertex color comes through unchanged.
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+struct Model {
+    transform: mat4x4<f32>,
+    tint: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@group(1) @binding(0) var<uniform> model: Model;
+@group(1) @binding(1) var color_texture: texture_2d<f32>;
+@group(1) @binding(2) var color_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = camera.view_proj * model.transform * vec4<f32>(in.position, 1.0);
+    out.color = in.color * model.tint;
+    out.uv = in.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(color_texture, color_sampler, in.uv);
+}
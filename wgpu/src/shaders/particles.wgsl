@@ -0,0 +1,45 @@
+// Particles as soft round dots, one instance per particle read straight from
+// the buffer the simulation writes.  Colored by speed, blended additively.
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    // diameter in world units, speed drawn fully hot
+    size: f32,
+    max_speed: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Instance {
+    @location(0) position: vec2<f32>,
+    @location(1) velocity: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // -1..1 across the dot
+    @location(1) offset: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: Instance) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(-1.0, 1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(1.0, -1.0),
+    );
+    let corner = corners[index];
+    let world = instance.position + corner * camera.size * 0.5;
+    let heat = clamp(length(instance.velocity) / camera.max_speed, 0.0, 1.0);
+    var out: VertexOutput;
+    out.position = camera.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.color = vec4<f32>(mix(vec3<f32>(0.1, 0.35, 1.0), vec3<f32>(1.0, 0.45, 0.1), heat), 1.0);
+    out.offset = corner;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = clamp(1.0 - length(in.offset), 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, falloff);
+}
@@ -0,0 +1,66 @@
+// One simulation step per dispatch, one invocation per particle.  Must stay
+// in step with `Particle::step` in particles.rs, the CPU fallback.
+
+struct Particle {
+    position: vec2<f32>,
+    velocity: vec2<f32>,
+}
+
+struct Params {
+    attractor: vec2<f32>,
+    bounds: vec2<f32>,
+    dt: f32,
+    // 0 while nothing touches the screen
+    attraction: f32,
+    drag: f32,
+    max_speed: f32,
+    count: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+
+// set from `compute::WORKGROUP_SIZE` and `SOFTENING` in particles.rs
+override WORKGROUP_SIZE: u32 = 64u;
+// keeps the pull finite right at the attractor, pixels squared
+override SOFTENING: f32 = 256.0;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_main(
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    // workgroups past the per-dimension limit spill over into y
+    let index = (workgroup.y * workgroups.x + workgroup.x) * WORKGROUP_SIZE + local;
+    if index >= params.count {
+        return;
+    }
+    let particle = particles[index];
+
+    let to_attractor = params.attractor - particle.position;
+    var velocity = particle.velocity + to_attractor * (params.attraction * params.dt / (dot(to_attractor, to_attractor) + SOFTENING));
+    velocity = velocity / (1.0 + params.drag * params.dt);
+    let speed = length(velocity);
+    if speed > params.max_speed {
+        velocity = velocity * (params.max_speed / speed);
+    }
+
+    var position = particle.position + velocity * params.dt;
+    // bounce off the edges
+    if position.x < 0.0 {
+        position.x = min(-position.x, params.bounds.x);
+        velocity.x = abs(velocity.x);
+    } else if position.x > params.bounds.x {
+        position.x = max(2.0 * params.bounds.x - position.x, 0.0);
+        velocity.x = -abs(velocity.x);
+    }
+    if position.y < 0.0 {
+        position.y = min(-position.y, params.bounds.y);
+        velocity.y = abs(velocity.y);
+    } else if position.y > params.bounds.y {
+        position.y = max(2.0 * params.bounds.y - position.y, 0.0);
+        velocity.y = -abs(velocity.y);
+    }
+    particles[index] = Particle(position, velocity);
+}
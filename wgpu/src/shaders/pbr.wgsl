@@ -0,0 +1,104 @@
+// glTF metallic-roughness materials lit by one directional light and a
+// constant ambient term.  Colors are linear.
+
+struct Scene {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    // direction the light travels in
+    light_direction: vec4<f32>,
+    // color times intensity
+    light_color: vec4<f32>,
+    ambient: vec4<f32>,
+}
+
+struct Draw {
+    model: mat4x4<f32>,
+    // inverse transpose of the model matrix, for normals
+    normal: mat4x4<f32>,
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    // metallic, roughness, alpha cutoff, 1.0 when opaque
+    params: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> scene: Scene;
+
+@group(1) @binding(0) var<uniform> draw: Draw;
+
+@group(2) @binding(0) var base_color_texture: texture_2d<f32>;
+@group(2) @binding(1) var metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(2) var emissive_texture: texture_2d<f32>;
+@group(2) @binding(3) var base_color_sampler: sampler;
+@group(2) @binding(4) var metallic_roughness_sampler: sampler;
+@group(2) @binding(5) var emissive_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+const PI: f32 = 3.14159265;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let world = draw.model * vec4<f32>(in.position, 1.0);
+    var out: VertexOutput;
+    out.position = scene.view_proj * world;
+    out.world = world.xyz / world.w;
+    out.normal = (draw.normal * vec4<f32>(in.normal, 0.0)).xyz;
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // sampled before the discard, while control flow is still uniform
+    let base_color = in.color * draw.base_color * textureSample(base_color_texture, base_color_sampler, in.uv);
+    let metallic_roughness = textureSample(metallic_roughness_texture, metallic_roughness_sampler, in.uv);
+    let emissive = draw.emissive.rgb * textureSample(emissive_texture, emissive_sampler, in.uv).rgb;
+    if base_color.a < draw.params.z {
+        discard;
+    }
+    let metallic = clamp(draw.params.x * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(draw.params.y * metallic_roughness.g, 0.04, 1.0);
+
+    var n = normalize(in.normal);
+    if !front_facing {
+        n = -n;
+    }
+    let v = normalize(scene.eye.xyz - in.world);
+    let l = normalize(-scene.light_direction.xyz);
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let v_dot_h = max(dot(v, h), 0.0);
+
+    // GGX distribution, Smith-Schlick geometry and Schlick Fresnel
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let d_denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d_denominator * d_denominator);
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = distribution * geometry * fresnel / max(4.0 * n_dot_l * n_dot_v, 1e-4);
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color.rgb / PI;
+    let ambient = scene.ambient.rgb * mix(base_color.rgb, f0, metallic);
+    let color = (diffuse + specular) * scene.light_color.rgb * n_dot_l + ambient + emissive;
+    let alpha_out = select(base_color.a, 1.0, draw.params.w > 0.5);
+    return vec4<f32>(color, alpha_out);
+}
@@ -0,0 +1,56 @@
+// Instanced textured quads.  Each instance is one sprite, the six vertices
+// of its two triangles come from the vertex index, there is no vertex buffer.
+// Text is drawn the same way, one instance per glyph, with `fs_glyph`.
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@group(1) @binding(0) var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1) var sprite_sampler: sampler;
+
+struct Instance {
+    // top-left corner and the edges from it, in world space
+    @location(0) origin: vec2<f32>,
+    @location(1) axis_x: vec2<f32>,
+    @location(2) axis_y: vec2<f32>,
+    // left, top, right, bottom in texture coordinates
+    @location(3) uv: vec4<f32>,
+    @location(4) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: Instance) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(1.0, 0.0),
+    );
+    let corner = corners[index];
+    let world = instance.origin + instance.axis_x * corner.x + instance.axis_y * corner.y;
+    var out: VertexOutput;
+    out.position = camera.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.color = instance.color;
+    out.uv = mix(instance.uv.xy, instance.uv.zw, corner);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(sprite_texture, sprite_sampler, in.uv);
+}
+
+// The texture is the glyph atlas, which holds coverage only, the color comes
+// from the instance.
+@fragment
+fn fs_glyph(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(sprite_texture, sprite_sampler, in.uv).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,430 @@
+use ::std::collections::HashMap;
+use ::std::ops::Range;
+
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::camera::OrthoCamera;
+use crate::gpu::{white_texture, GpuContext, PerDevice, Samplers};
+use crate::renderer::Matrix;
+use crate::resources::{Handle, ResourceRegistry};
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+use crate::texture::{LoadedTexture, SamplerPreset};
+
+/// A textured, tinted rectangle in the world of an [`OrthoCamera`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    /// Where `anchor` ends up, in world units.
+    pub position: [f32; 2],
+    /// World units.
+    pub size: [f32; 2],
+    /// Radians around `anchor`, clockwise on screen since world y points down.
+    pub rotation: f32,
+    /// The point of the sprite placed at `position`, `[0, 0]` is its
+    /// top-left corner and `[1, 1]` its bottom-right one.
+    pub anchor: [f32; 2],
+    /// Linear RGBA, multiplied with the texture.
+    pub color: [f32; 4],
+    /// Part of the texture shown, `[left, top, right, bottom]` in texture coordinates.
+    pub uv: [f32; 4],
+    /// `None` for a plain colored rectangle.
+    pub texture: Option<Handle<::wgpu::Texture>>,
+    pub sampler: SamplerPreset,
+    /// Higher is drawn on top.  Sprites with the same `z` are drawn in the order they were added.
+    pub z: f32,
+    pub visible: bool,
+}
+
+impl Sprite {
+    /// A white, untextured sprite centered on `position`.
+    pub fn new(position: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            position,
+            size,
+            rotation: 0.0,
+            anchor: [0.5, 0.5],
+            color: [1.0, 1.0, 1.0, 1.0],
+            uv: [0.0, 0.0, 1.0, 1.0],
+            texture: None,
+            sampler: SamplerPreset::Pixel,
+            z: 0.0,
+            visible: true,
+        }
+    }
+
+    /// Where the GPU draws this sprite.
+    pub fn instance(&self) -> SpriteInstance {
+        let (sin, cos) = self.rotation.sin_cos();
+        let axis_x = [cos * self.size[0], sin * self.size[0]];
+        let axis_y = [-sin * self.size[1], cos * self.size[1]];
+        let [ax, ay] = self.anchor;
+        SpriteInstance {
+            origin: [
+                self.position[0] - ax * axis_x[0] - ay * axis_y[0],
+                self.position[1] - ax * axis_x[1] - ay * axis_y[1],
+            ],
+            axis_x,
+            axis_y,
+            uv: self.uv,
+            color: self.color,
+        }
+    }
+}
+
+/// One sprite in the instance buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct SpriteInstance {
+    /// World position of the top-left corner.
+    pub origin: [f32; 2],
+    /// The top and left edges from that corner, rotated and scaled.
+    pub axis_x: [f32; 2],
+    pub axis_y: [f32; 2],
+    pub uv: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl SpriteInstance {
+    const ATTRIBUTES: [::wgpu::VertexAttribute; 5] = ::wgpu::vertex_attr_array![
+        0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32x4, 4 => Float32x4,
+    ];
+
+    pub fn layout() -> ::wgpu::VertexBufferLayout<'static> {
+        ::wgpu::VertexBufferLayout {
+            array_stride: ::std::mem::size_of::<SpriteInstance>() as ::wgpu::BufferAddress,
+            step_mode: ::wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// A part of a [`TextureAtlas`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    /// `[left, top, right, bottom]` in texture coordinates, as in [`Sprite::uv`].
+    pub uv: [f32; 4],
+    /// Pixels.
+    pub size: [f32; 2],
+}
+
+/// Named regions of one texture.  Sprites cut from the same atlas share a
+/// texture, so they are drawn in one batch.
+///
+/// Filtering reads neighbouring texels, so regions that touch may bleed into
+/// each other unless they are drawn at their pixel size with
+/// [`SamplerPreset::Pixel`] or have some padding around them.
+pub struct TextureAtlas {
+    pub texture: Handle<::wgpu::Texture>,
+    pub sampler: SamplerPreset,
+    /// Pixels.
+    pub size: (u32, u32),
+    regions: HashMap<String, Region>,
+}
+
+impl TextureAtlas {
+    pub fn new(texture: Handle<::wgpu::Texture>, size: (u32, u32), sampler: SamplerPreset) -> Self {
+        Self { texture, sampler, size, regions: HashMap::new() }
+    }
+
+    /// An atlas over a texture from [`crate::texture::load_texture`].
+    pub fn from_texture(texture: &LoadedTexture) -> Self {
+        Self::new(texture.texture, texture.size, texture.sampler)
+    }
+
+    /// Name the pixels `[x, y, width, height]`.
+    pub fn insert(&mut self, name: &str, rect: [u32; 4]) -> Region {
+        let [x, y, width, height] = rect.map(|value| value as f32);
+        let (texture_width, texture_height) = (self.size.0 as f32, self.size.1 as f32);
+        let region = Region {
+            uv: [x / texture_width, y / texture_height, (x + width) / texture_width, (y + height) / texture_height],
+            size: [width, height],
+        };
+        self.regions.insert(name.to_string(), region);
+        region
+    }
+
+    /// Split the texture into `tile` sized regions named `<name>/0`,
+    /// `<name>/1` and so on, row by row.  Returns how many there are.
+    pub fn insert_grid(&mut self, name: &str, tile: (u32, u32)) -> usize {
+        let columns = self.size.0 / tile.0.max(1);
+        let rows = self.size.1 / tile.1.max(1);
+        for index in 0..columns * rows {
+            let (column, row) = (index % columns, index / columns);
+            self.insert(&format!("{}/{}", name, index), [column * tile.0, row * tile.1, tile.0, tile.1]);
+        }
+        (columns * rows) as usize
+    }
+
+    pub fn region(&self, name: &str) -> Option<Region> {
+        self.regions.get(name).copied()
+    }
+
+    /// A sprite showing region `name` at its pixel size.
+    pub fn sprite(&self, name: &str, position: [f32; 2]) -> Option<Sprite> {
+        let region = self.region(name)?;
+        Some(Sprite {
+            uv: region.uv,
+            texture: Some(self.texture),
+            sampler: self.sampler,
+            ..Sprite::new(position, region.size)
+        })
+    }
+}
+
+/// Consecutive instances that use the same texture and sampler, drawn with one instanced draw call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub texture: Option<Handle<::wgpu::Texture>>,
+    pub sampler: SamplerPreset,
+    pub instances: Range<u32>,
+}
+
+/// Visible `sprites` back to front, and the batches to draw them in.  A new
+/// batch starts whenever the texture or sampler changes from one sprite to
+/// the next, so sprites sharing an atlas at neighbouring depths are drawn together.
+pub fn batch(sprites: &[Sprite]) -> (Vec<SpriteInstance>, Vec<Batch>) {
+    let mut order: Vec<&Sprite> = sprites.iter().filter(|sprite| sprite.visible).collect();
+    // stable, so equal depths keep the order the sprites were added in
+    order.sort_by(|a, b| a.z.total_cmp(&b.z));
+
+    let mut instances = Vec::with_capacity(order.len());
+    let mut batches: Vec<Batch> = vec![];
+    for sprite in order {
+        let index = instances.len() as u32;
+        instances.push(sprite.instance());
+        match batches.last_mut() {
+            Some(batch) if batch.texture == sprite.texture && batch.sampler == sprite.sampler => batch.instances.end = index + 1,
+            _ => batches.push(Batch { texture: sprite.texture, sampler: sprite.sampler, instances: index..index + 1 }),
+        }
+    }
+    (instances, batches)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CameraUniform {
+    view_proj: Matrix,
+}
+
+pub type SpriteId = usize;
+
+struct SpriteObjects {
+    shader: ::wgpu::ShaderModule,
+    texture_layout: ::wgpu::BindGroupLayout,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    camera_buffer: ::wgpu::Buffer,
+    camera_bind_group: ::wgpu::BindGroup,
+    samplers: Samplers<SamplerPreset>,
+    /// Stands in for the texture of untextured sprites.
+    white: ::wgpu::Texture,
+    /// Grows to the largest number of sprites drawn so far.
+    instances: Option<::wgpu::Buffer>,
+}
+
+/// What [`SpriteBatch::prepare`] uploaded for one frame.
+pub struct PreparedSprites {
+    key: (::wgpu::TextureFormat, u32),
+    batches: Vec<(Range<u32>, ::wgpu::BindGroup)>,
+}
+
+/// Draws [`Sprite`]s with `shaders/sprite.wgsl`, on top of the meshes of
+/// the same render pass.
+///
+/// Every frame the visible sprites are sorted by `z` and written to one
+/// instance buffer, then drawn with one instanced draw call per [`Batch`].
+pub struct SpriteBatch {
+    /// Its viewport follows the size of the target drawn into.
+    pub camera: OrthoCamera,
+    sprites: Vec<Sprite>,
+    device: PerDevice<SpriteObjects>,
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self { camera: OrthoCamera::default(), sprites: vec![], device: PerDevice::new() }
+    }
+
+    pub fn add(&mut self, sprite: Sprite) -> SpriteId {
+        self.sprites.push(sprite);
+        self.sprites.len() - 1
+    }
+
+    pub fn sprite_mut(&mut self, id: SpriteId) -> &mut Sprite {
+        &mut self.sprites[id]
+    }
+
+    pub fn sprites(&self) -> &[Sprite] {
+        &self.sprites
+    }
+
+    /// Remove every sprite, ids handed out before are no longer valid.
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    /// Upload the instances and camera for a frame drawn into `targets`,
+    /// `None` when no sprite is visible.
+    pub fn prepare(&mut self, gpu: &GpuContext, resources: &ResourceRegistry<GpuContext>, targets: &RenderTargets) -> Option<PreparedSprites> {
+        self.camera.viewport = targets.size;
+        let (instances, batches) = batch(&self.sprites);
+        if instances.is_empty() {
+            return None;
+        }
+        let objects = self.device.get_or_create(gpu, Self::create_device_objects);
+        let key = (targets.format, targets.sample_count);
+        objects.ensure_pipeline(key, |objects, (format, sample_count)| {
+            info!("creating sprite pipeline for {:?} with {}x MSAA", format, sample_count);
+            Self::create_pipeline(gpu, objects, format, sample_count)
+        });
+
+        let bytes: &[u8] = ::bytemuck::cast_slice(&instances);
+        if objects.instances.as_ref().is_none_or(|buffer| buffer.size() < bytes.len() as u64) {
+            objects.instances = Some(gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+                label: Some("sprite instances"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: ::wgpu::BufferUsages::VERTEX | ::wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let buffer = objects.instances.as_ref().expect("sprite instance buffer was just created");
+        gpu.queue.write_buffer(buffer, 0, bytes);
+        gpu.queue.write_buffer(&objects.camera_buffer, 0, ::bytemuck::bytes_of(&CameraUniform { view_proj: self.camera.view_proj() }));
+
+        let white = objects.white.create_view(&::wgpu::TextureViewDescriptor::default());
+        let batches = batches.into_iter().map(|batch| {
+            let texture = batch.texture
+                .map(|texture| resources.get(texture).create_view(&::wgpu::TextureViewDescriptor::default()));
+            let bind_group = gpu.device.create_bind_group(&::wgpu::BindGroupDescriptor {
+                label: Some("sprite texture"),
+                layout: &objects.texture_layout,
+                entries: &[
+                    ::wgpu::BindGroupEntry { binding: 0, resource: ::wgpu::BindingResource::TextureView(texture.as_ref().unwrap_or(&white)) },
+                    ::wgpu::BindGroupEntry { binding: 1, resource: ::wgpu::BindingResource::Sampler(objects.samplers.get(batch.sampler)) },
+                ],
+            });
+            (batch.instances, bind_group)
+        }).collect();
+        Some(PreparedSprites { key, batches })
+    }
+
+    /// Draw what [`SpriteBatch::prepare`] uploaded.
+    pub fn draw(&self, rpass: &mut ::wgpu::RenderPass<'_>, prepared: &PreparedSprites) {
+        let objects = self.device.get().expect("sprites were prepared");
+        let buffer = objects.instances.as_ref().expect("sprites were prepared");
+        rpass.set_pipeline(objects.pipeline(&prepared.key));
+        rpass.set_bind_group(0, &objects.camera_bind_group, &[]);
+        rpass.set_vertex_buffer(0, buffer.slice(..));
+        for (instances, bind_group) in prepared.batches.iter() {
+            rpass.set_bind_group(1, bind_group, &[]);
+            rpass.draw(0..6, instances.clone());
+        }
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> SpriteObjects {
+        info!("creating sprite objects for device generation {}", gpu.generation);
+        let device = &gpu.device;
+        let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/sprite.wgsl"));
+        let camera_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite camera"),
+            entries: &[::wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ::wgpu::ShaderStages::VERTEX,
+                ty: ::wgpu::BindingType::Buffer {
+                    ty: ::wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite texture"),
+            entries: &[
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Texture {
+                        sample_type: ::wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: ::wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Sampler(::wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("sprite"),
+            bind_group_layouts: &[&camera_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let camera_buffer = device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some("sprite camera"),
+            size: ::std::mem::size_of::<CameraUniform>() as u64,
+            usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("sprite camera"),
+            layout: &camera_layout,
+            entries: &[::wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+        SpriteObjects {
+            shader,
+            texture_layout,
+            pipeline_layout,
+            camera_buffer,
+            camera_bind_group,
+            samplers: Samplers::new(gpu, SamplerPreset::ALL),
+            white: white_texture(gpu),
+            instances: None,
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &SpriteObjects, format: ::wgpu::TextureFormat, sample_count: u32) -> ::wgpu::RenderPipeline {
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("sprite"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[SpriteInstance::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState::default(),
+            // shares the mesh pass's depth buffer but ignores it, sprites are
+            // sorted and always drawn over the scene
+            depth_stencil: Some(::wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: ::wgpu::CompareFunction::Always,
+                stencil: ::wgpu::StencilState::default(),
+                bias: ::wgpu::DepthBiasState::default(),
+            }),
+            multisample: ::wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
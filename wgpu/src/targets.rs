@@ -0,0 +1,86 @@
+use crate::gpu::GpuContext;
+
+pub const DEPTH_FORMAT: ::wgpu::TextureFormat = ::wgpu::TextureFormat::Depth32Float;
+
+/// The attachments drawn into besides the final color target: a
+/// multisampled color texture that is resolved into it when MSAA is on, and
+/// a depth texture.  Both depend on the target's size, so they are created
+/// again whenever the surface is configured.
+pub struct RenderTargets {
+    pub format: ::wgpu::TextureFormat,
+    pub size: (u32, u32),
+    pub sample_count: u32,
+    multisampled: Option<::wgpu::TextureView>,
+    depth: ::wgpu::TextureView,
+}
+
+impl RenderTargets {
+    pub fn new(gpu: &GpuContext, format: ::wgpu::TextureFormat, size: (u32, u32), sample_count: u32) -> Self {
+        let texture = |label, format, sample_count| {
+            gpu.device.create_texture(&::wgpu::TextureDescriptor {
+                label: Some(label),
+                size: ::wgpu::Extent3d { width: size.0.max(1), height: size.1.max(1), depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count,
+                dimension: ::wgpu::TextureDimension::D2,
+                format,
+                usage: ::wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }).create_view(&::wgpu::TextureViewDescriptor::default())
+        };
+        Self {
+            format,
+            size,
+            sample_count,
+            multisampled: (sample_count > 1).then(|| texture("multisampled color", format, sample_count)),
+            depth: texture("depth", DEPTH_FORMAT, sample_count),
+        }
+    }
+
+    /// Targets with as many samples as the config asks for and the adapter supports for `format`.
+    pub fn for_format(gpu: &GpuContext, format: ::wgpu::TextureFormat, size: (u32, u32)) -> Self {
+        Self::new(gpu, format, size, Self::sample_count(gpu, format))
+    }
+
+    /// `config.msaa_samples`, lowered to a count both `format` and the depth format support.
+    pub fn sample_count(gpu: &GpuContext, format: ::wgpu::TextureFormat) -> u32 {
+        // without this feature only the guaranteed counts (1 and 4) may be used
+        let features = gpu.device.features();
+        let flags = |format: ::wgpu::TextureFormat| if features.contains(::wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            gpu.adapter.get_texture_format_features(format).flags
+        } else {
+            format.guaranteed_format_features(features).flags
+        };
+        gpu.config.sample_count(flags(format), flags(DEPTH_FORMAT))
+    }
+
+    /// Draw into the multisampled texture and resolve into `view`, or
+    /// straight into `view` without MSAA.
+    pub fn color_attachment<'a>(&'a self, view: &'a ::wgpu::TextureView, load: ::wgpu::LoadOp<::wgpu::Color>) -> ::wgpu::RenderPassColorAttachment<'a> {
+        match self.multisampled.as_ref() {
+            Some(multisampled) => ::wgpu::RenderPassColorAttachment {
+                view: multisampled,
+                resolve_target: Some(view),
+                // only the resolved frame is needed afterwards
+                ops: ::wgpu::Operations { load, store: ::wgpu::StoreOp::Discard },
+            },
+            None => ::wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: ::wgpu::Operations { load, store: ::wgpu::StoreOp::Store },
+            },
+        }
+    }
+
+    /// Cleared to the far plane, nothing reads it after the pass.
+    pub fn depth_attachment(&self) -> ::wgpu::RenderPassDepthStencilAttachment<'_> {
+        ::wgpu::RenderPassDepthStencilAttachment {
+            view: &self.depth,
+            depth_ops: Some(::wgpu::Operations {
+                load: ::wgpu::LoadOp::Clear(1.0),
+                store: ::wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use ::std::collections::HashMap;
+use ::std::fmt;
+use ::std::io;
+
+use ::ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+use ::bytemuck::{Pod, Zeroable};
+use ::log::info;
+
+use crate::assets::Assets;
+use crate::camera::OrthoCamera;
+use crate::gpu::{GpuContext, PerDevice};
+use crate::renderer::Matrix;
+use crate::sprites::SpriteInstance;
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+use crate::texture::SamplerPreset;
+
+/// Width and height of the glyph atlas, small enough for `downlevel_webgl2` limits.
+pub const ATLAS_SIZE: u32 = 1024;
+
+/// Packaged with the app, see `fonts/LICENSE-DejaVu.txt` next to it.
+pub const DEFAULT_FONT: &str = "fonts/DejaVuSans.ttf";
+
+#[derive(Debug)]
+pub enum TextError {
+    Io(io::Error),
+    InvalidFont(::ab_glyph::InvalidFont),
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Io(e) => write!(f, "cannot read font: {}", e),
+            TextError::InvalidFont(e) => write!(f, "cannot parse font: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for TextError {}
+
+/// A TrueType or OpenType font from `assets`, e.g. `fonts/DejaVuSans.ttf`.
+pub fn load_font(assets: &Assets, name: &str) -> Result<FontArc, TextError> {
+    let bytes = assets.read(name).map_err(TextError::Io)?;
+    let font = FontArc::try_from_vec(bytes).map_err(TextError::InvalidFont)?;
+    info!("loaded font {} ({} glyphs)", name, font.glyph_count());
+    Ok(font)
+}
+
+/// A glyph placed by [`layout`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaidOutGlyph {
+    pub id: GlyphId,
+    pub character: char,
+    /// Pen position on the baseline, relative to the top-left corner of the text.
+    pub position: [f32; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    /// Visible glyphs only, spaces and line breaks just move the pen.
+    pub glyphs: Vec<LaidOutGlyph>,
+    pub lines: usize,
+    /// Width of the widest line and height of all lines.
+    pub size: [f32; 2],
+}
+
+/// Place `text` in lines `px` pixels high, kerning each pair of glyphs.
+/// `\n` starts a new line.  With `max_width` lines are wrapped between
+/// words, and words that do not fit on a line of their own between characters.
+pub fn layout(font: &FontArc, text: &str, px: f32, max_width: Option<f32>) -> TextLayout {
+    let font = font.as_scaled(PxScale::from(px));
+    let line_height = font.height() + font.line_gap();
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+    let space = font.glyph_id(' ');
+    let kern = |previous: Option<GlyphId>, id| previous.map_or(0.0, |previous| font.kern(previous, id));
+
+    let mut glyphs = vec![];
+    let mut line = 0;
+    let mut width: f32 = 0.0;
+    for (index, paragraph) in text.split('\n').enumerate() {
+        if index > 0 {
+            line += 1;
+        }
+        let mut x = 0.0;
+        let mut previous = None;
+        for (word_index, word) in paragraph.split(' ').enumerate() {
+            let ids: Vec<_> = word.chars().map(|character| (character, font.glyph_id(character))).collect();
+            if word_index > 0 {
+                let gap = kern(previous, space) + font.h_advance(space);
+                let mut word_previous = Some(space);
+                let word_width: f32 = ids.iter().map(|&(_, id)| {
+                    let advance = kern(word_previous, id) + font.h_advance(id);
+                    word_previous = Some(id);
+                    advance
+                }).sum();
+                if x > 0.0 && x + gap + word_width > max_width {
+                    line += 1;
+                    x = 0.0;
+                    previous = None;
+                } else {
+                    x += gap;
+                    previous = Some(space);
+                }
+            }
+            for (character, id) in ids {
+                x += kern(previous, id);
+                let advance = font.h_advance(id);
+                if x > 0.0 && x + advance > max_width {
+                    line += 1;
+                    x = 0.0;
+                }
+                glyphs.push(LaidOutGlyph { id, character, position: [x, line as f32 * line_height + font.ascent()] });
+                x += advance;
+                previous = Some(id);
+                width = width.max(x);
+            }
+        }
+    }
+    TextLayout { glyphs, lines: line + 1, size: [width, (line + 1) as f32 * line_height] }
+}
+
+/// Places rectangles in rows ("shelves") left to right and starts a new row
+/// below the tallest rectangle of the last one when a row is full.  Cheap,
+/// and glyphs of one size waste little space.
+#[derive(Debug, Clone)]
+pub struct ShelfPacker {
+    size: (u32, u32),
+    x: u32,
+    y: u32,
+    row_height: u32,
+}
+
+impl ShelfPacker {
+    pub fn new(size: (u32, u32)) -> Self {
+        Self { size, x: 0, y: 0, row_height: 0 }
+    }
+
+    /// Top-left corner of a free `width` x `height` area, `None` when full.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        if width > self.size.0 {
+            return None;
+        }
+        if self.x + width > self.size.0 {
+            self.y += self.row_height;
+            self.x = 0;
+            self.row_height = 0;
+        }
+        if self.y + height > self.size.1 {
+            return None;
+        }
+        let corner = [self.x, self.y];
+        self.x += width;
+        self.row_height = self.row_height.max(height);
+        Some(corner)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.size);
+    }
+}
+
+/// Where a rasterized glyph is in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CachedGlyph {
+    uv: [f32; 4],
+    /// From the pen position to the top-left corner of the bitmap.
+    offset: [f32; 2],
+    size: [f32; 2],
+}
+
+struct AtlasFull;
+
+/// Glyphs rasterized into the atlas so far, keyed by glyph and pixel size.
+struct GlyphCache {
+    packer: ShelfPacker,
+    /// `None` for glyphs without an outline, like spaces.
+    glyphs: HashMap<(GlyphId, u32), Option<CachedGlyph>>,
+    /// Rasterized but not yet written to the atlas texture: `[x, y, width, height]` and coverage rows.
+    uploads: Vec<([u32; 4], Vec<u8>)>,
+}
+
+impl GlyphCache {
+    fn new() -> Self {
+        Self { packer: ShelfPacker::new((ATLAS_SIZE, ATLAS_SIZE)), glyphs: HashMap::new(), uploads: vec![] }
+    }
+
+    /// Forget every glyph, the atlas is filled again from the top.
+    fn clear(&mut self) {
+        self.packer.clear();
+        self.glyphs.clear();
+        self.uploads.clear();
+    }
+
+    fn glyph(&mut self, font: &FontArc, id: GlyphId, px: f32) -> Result<Option<CachedGlyph>, AtlasFull> {
+        let key = (id, px.to_bits());
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Ok(*glyph);
+        }
+        let Some(outline) = font.outline_glyph(id.with_scale(px)) else {
+            self.glyphs.insert(key, None);
+            return Ok(None);
+        };
+        let bounds = outline.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        // one pixel of padding so filtering does not pick up the neighbours
+        let [x, y] = self.packer.allocate(width + 1, height + 1).ok_or(AtlasFull)?;
+        let mut coverage = vec![0; (width * height) as usize];
+        outline.draw(|gx, gy, value| {
+            if let Some(texel) = coverage.get_mut((gy * width + gx) as usize) {
+                *texel = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        });
+        self.uploads.push(([x, y, width, height], coverage));
+        let atlas = ATLAS_SIZE as f32;
+        let glyph = CachedGlyph {
+            uv: [x as f32 / atlas, y as f32 / atlas, (x + width) as f32 / atlas, (y + height) as f32 / atlas],
+            offset: [bounds.min.x, bounds.min.y],
+            size: [width as f32, height as f32],
+        };
+        self.glyphs.insert(key, Some(glyph));
+        Ok(Some(glyph))
+    }
+}
+
+/// A string to draw this frame, see [`TextRenderer::queue`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSection {
+    pub text: String,
+    /// Top-left corner in logical pixels.
+    pub position: [f32; 2],
+    /// Line height in logical pixels.
+    pub size: f32,
+    /// Linear RGBA
+    pub color: [f32; 4],
+    /// Wrap lines longer than this many logical pixels.
+    pub max_width: Option<f32>,
+}
+
+impl TextSection {
+    /// White text without wrapping.
+    pub fn new(text: impl Into<String>, position: [f32; 2], size: f32) -> Self {
+        Self { text: text.into(), position, size, color: [1.0, 1.0, 1.0, 1.0], max_width: None }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CameraUniform {
+    view_proj: Matrix,
+}
+
+struct TextObjects {
+    shader: ::wgpu::ShaderModule,
+    pipeline_layout: ::wgpu::PipelineLayout,
+    camera_buffer: ::wgpu::Buffer,
+    camera_bind_group: ::wgpu::BindGroup,
+    atlas: ::wgpu::Texture,
+    atlas_bind_group: ::wgpu::BindGroup,
+    instances: Option<::wgpu::Buffer>,
+}
+
+/// What [`TextRenderer::prepare`] uploaded for one frame.
+pub struct PreparedText {
+    key: (::wgpu::TextureFormat, u32),
+    instance_count: u32,
+}
+
+/// Draws [`TextSection`]s with `fs_glyph` of `shaders/sprite.wgsl`, after
+/// the sprites of the same render pass.
+///
+/// Sections are queued for one frame at a time.  Their glyphs are laid out
+/// with [`layout`] at the size they cover in physical pixels, rasterized
+/// into an R8 atlas the first time they are used and drawn as one instance
+/// each.  When the atlas is full it is cleared and refilled with the glyphs
+/// of the current frame.
+pub struct TextRenderer {
+    /// Nothing is drawn without a font.
+    pub font: Option<FontArc>,
+    sections: Vec<TextSection>,
+    /// The sections of the last [`TextRenderer::prepare`], see [`TextRenderer::requeue_drawn`].
+    drawn: Vec<TextSection>,
+    /// Physical pixels per logical pixel.
+    scale_factor: f32,
+    cache: GlyphCache,
+    device: PerDevice<TextObjects>,
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextRenderer {
+    pub fn new() -> Self {
+        Self { font: None, sections: vec![], drawn: vec![], scale_factor: 1.0, cache: GlyphCache::new(), device: PerDevice::new() }
+    }
+
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// From the window or `ScaleFactorChanged`.  Glyphs are rasterized again at the new size.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        if scale_factor != self.scale_factor {
+            info!("text scale factor {}", scale_factor);
+            self.scale_factor = scale_factor;
+            self.cache.clear();
+        }
+    }
+
+    /// Draw `section` in the next frame.
+    pub fn queue(&mut self, section: TextSection) {
+        self.sections.push(section);
+    }
+
+    /// Queue the sections of the last frame again, to draw that frame a second time.
+    pub fn requeue_drawn(&mut self) {
+        self.sections.clone_from(&self.drawn);
+    }
+
+    /// Size of `section` in logical pixels, `None` without a font.
+    pub fn measure(&self, section: &TextSection) -> Option<[f32; 2]> {
+        let font = self.font.as_ref()?;
+        let scale = self.scale_factor;
+        let [width, height] = layout(font, &section.text, section.size * scale, section.max_width.map(|width| width * scale)).size;
+        Some([width / scale, height / scale])
+    }
+
+    /// Lay out the queued sections, rasterize new glyphs and upload them,
+    /// `None` when there is nothing to draw.  Clears the queue.
+    pub fn prepare(&mut self, gpu: &GpuContext, targets: &RenderTargets) -> Option<PreparedText> {
+        let sections = ::std::mem::take(&mut self.sections);
+        self.drawn.clone_from(&sections);
+        let font = self.font.clone()?;
+        if sections.is_empty() {
+            return None;
+        }
+        if !self.device.is_current(gpu) {
+            self.device.get_or_create(gpu, Self::create_device_objects);
+            // the new atlas texture is empty
+            self.cache.clear();
+        }
+        let instances = match self.instances(&font, &sections) {
+            Ok(instances) => instances,
+            Err(_) => {
+                info!("glyph atlas full, starting over");
+                self.cache.clear();
+                self.instances(&font, &sections).unwrap_or_else(|partial| {
+                    info!("glyph atlas too small for this frame, drawing the first {} glyphs", partial.len());
+                    partial
+                })
+            },
+        };
+
+        let objects = self.device.get_mut().expect("text objects were just created");
+        for ([x, y, width, height], coverage) in self.cache.uploads.drain(..) {
+            gpu.queue.write_texture(
+                ::wgpu::TexelCopyTextureInfo {
+                    texture: &objects.atlas,
+                    mip_level: 0,
+                    origin: ::wgpu::Origin3d { x, y, z: 0 },
+                    aspect: ::wgpu::TextureAspect::All,
+                },
+                &coverage,
+                ::wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(width), rows_per_image: None },
+                ::wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            );
+        }
+        if instances.is_empty() {
+            return None;
+        }
+        let key = (targets.format, targets.sample_count);
+        objects.ensure_pipeline(key, |objects, (format, sample_count)| {
+            info!("creating text pipeline for {:?} with {}x MSAA", format, sample_count);
+            Self::create_pipeline(gpu, objects, format, sample_count)
+        });
+        let bytes: &[u8] = ::bytemuck::cast_slice(&instances);
+        if objects.instances.as_ref().is_none_or(|buffer| buffer.size() < bytes.len() as u64) {
+            objects.instances = Some(gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+                label: Some("text instances"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: ::wgpu::BufferUsages::VERTEX | ::wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let buffer = objects.instances.as_ref().expect("text instance buffer was just created");
+        gpu.queue.write_buffer(buffer, 0, bytes);
+        // text is placed in physical pixels
+        let view_proj = OrthoCamera::new(targets.size).view_proj();
+        gpu.queue.write_buffer(&objects.camera_buffer, 0, ::bytemuck::bytes_of(&CameraUniform { view_proj }));
+        Some(PreparedText { key, instance_count: instances.len() as u32 })
+    }
+
+    /// Draw what [`TextRenderer::prepare`] uploaded.
+    pub fn draw(&self, rpass: &mut ::wgpu::RenderPass<'_>, prepared: &PreparedText) {
+        let objects = self.device.get().expect("text was prepared");
+        let buffer = objects.instances.as_ref().expect("text was prepared");
+        rpass.set_pipeline(objects.pipeline(&prepared.key));
+        rpass.set_bind_group(0, &objects.camera_bind_group, &[]);
+        rpass.set_bind_group(1, &objects.atlas_bind_group, &[]);
+        rpass.set_vertex_buffer(0, buffer.slice(..));
+        rpass.draw(0..6, 0..prepared.instance_count);
+    }
+
+    /// One instance per visible glyph, or as many as fit when the atlas fills up.
+    fn instances(&mut self, font: &FontArc, sections: &[TextSection]) -> Result<Vec<SpriteInstance>, Vec<SpriteInstance>> {
+        let scale = self.scale_factor;
+        let mut instances = vec![];
+        for section in sections {
+            let px = section.size * scale;
+            let laid_out = layout(font, &section.text, px, section.max_width.map(|width| width * scale));
+            for glyph in laid_out.glyphs {
+                let cached = match self.cache.glyph(font, glyph.id, px) {
+                    Ok(Some(cached)) => cached,
+                    Ok(None) => continue,
+                    Err(AtlasFull) => return Err(instances),
+                };
+                // whole pixels, so the bitmap maps 1:1 onto the target
+                let pen = [
+                    (section.position[0] * scale + glyph.position[0]).round(),
+                    (section.position[1] * scale + glyph.position[1]).round(),
+                ];
+                instances.push(SpriteInstance {
+                    origin: [pen[0] + cached.offset[0], pen[1] + cached.offset[1]],
+                    axis_x: [cached.size[0], 0.0],
+                    axis_y: [0.0, cached.size[1]],
+                    uv: cached.uv,
+                    color: section.color,
+                });
+            }
+        }
+        Ok(instances)
+    }
+
+    fn create_device_objects(gpu: &GpuContext) -> TextObjects {
+        info!("creating text objects for device generation {}", gpu.generation);
+        let device = &gpu.device;
+        let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/sprite.wgsl"));
+        let camera_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("text camera"),
+            entries: &[::wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ::wgpu::ShaderStages::VERTEX,
+                ty: ::wgpu::BindingType::Buffer {
+                    ty: ::wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let atlas_layout = device.create_bind_group_layout(&::wgpu::BindGroupLayoutDescriptor {
+            label: Some("glyph atlas"),
+            entries: &[
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Texture {
+                        sample_type: ::wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: ::wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                ::wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ::wgpu::ShaderStages::FRAGMENT,
+                    ty: ::wgpu::BindingType::Sampler(::wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&::wgpu::PipelineLayoutDescriptor {
+            label: Some("text"),
+            bind_group_layouts: &[&camera_layout, &atlas_layout],
+            push_constant_ranges: &[],
+        });
+        let camera_buffer = device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some("text camera"),
+            size: ::std::mem::size_of::<CameraUniform>() as u64,
+            usage: ::wgpu::BufferUsages::UNIFORM | ::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("text camera"),
+            layout: &camera_layout,
+            entries: &[::wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+        let atlas = device.create_texture(&::wgpu::TextureDescriptor {
+            label: Some("glyph atlas"),
+            size: ::wgpu::Extent3d { width: ATLAS_SIZE, height: ATLAS_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: ::wgpu::TextureDimension::D2,
+            format: ::wgpu::TextureFormat::R8Unorm,
+            usage: ::wgpu::TextureUsages::TEXTURE_BINDING | ::wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&SamplerPreset::Pixel.descriptor());
+        let atlas_bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("glyph atlas"),
+            layout: &atlas_layout,
+            entries: &[
+                ::wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: ::wgpu::BindingResource::TextureView(&atlas.create_view(&::wgpu::TextureViewDescriptor::default())),
+                },
+                ::wgpu::BindGroupEntry { binding: 1, resource: ::wgpu::BindingResource::Sampler(&sampler) },
+            ],
+        });
+        TextObjects {
+            shader,
+            pipeline_layout,
+            camera_buffer,
+            camera_bind_group,
+            atlas,
+            atlas_bind_group,
+            instances: None,
+        }
+    }
+
+    fn create_pipeline(gpu: &GpuContext, objects: &TextObjects, format: ::wgpu::TextureFormat, sample_count: u32) -> ::wgpu::RenderPipeline {
+        gpu.device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+            label: Some("text"),
+            layout: Some(&objects.pipeline_layout),
+            vertex: ::wgpu::VertexState {
+                module: &objects.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[SpriteInstance::layout()],
+            },
+            fragment: Some(::wgpu::FragmentState {
+                module: &objects.shader,
+                entry_point: Some("fs_glyph"),
+                compilation_options: Default::default(),
+                targets: &[Some(::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: ::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: ::wgpu::PrimitiveState::default(),
+            // like sprites, on top of everything drawn before in the pass
+            depth_stencil: Some(::wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: ::wgpu::CompareFunction::Always,
+                stencil: ::wgpu::StencilState::default(),
+                bias: ::wgpu::DepthBiasState::default(),
+            }),
+            multisample: ::wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
@@ -0,0 +1,381 @@
+use ::std::fmt;
+use ::std::io;
+use ::std::path::Path;
+
+use ::log::info;
+
+use crate::assets::Assets;
+use crate::gpu::GpuContext;
+use crate::resources::{Handle, ResourceRegistry};
+
+/// How a texture is filtered and what happens outside 0..1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SamplerPreset {
+    /// Sharp texels when magnified, smooth when minified, clamped.  For pixel art and UI.
+    Pixel,
+    /// Bilinear without mipmaps, clamped.
+    Linear,
+    /// Bilinear between the two closest mip levels, repeating.
+    Trilinear,
+    /// Trilinear with 16x anisotropic filtering, for surfaces seen at a steep angle.
+    Anisotropic,
+}
+
+impl SamplerPreset {
+    pub const ALL: [SamplerPreset; 4] = [SamplerPreset::Pixel, SamplerPreset::Linear, SamplerPreset::Trilinear, SamplerPreset::Anisotropic];
+
+    pub fn descriptor(self) -> ::wgpu::SamplerDescriptor<'static> {
+        use ::wgpu::{AddressMode, FilterMode};
+        let clamped = ::wgpu::SamplerDescriptor { label: Some("pixel"), mag_filter: FilterMode::Nearest, min_filter: FilterMode::Linear, ..Default::default() };
+        let repeating = ::wgpu::SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        };
+        match self {
+            SamplerPreset::Pixel => clamped,
+            SamplerPreset::Linear => ::wgpu::SamplerDescriptor { label: Some("linear"), mag_filter: FilterMode::Linear, ..clamped },
+            SamplerPreset::Trilinear => ::wgpu::SamplerDescriptor { label: Some("trilinear"), ..repeating },
+            SamplerPreset::Anisotropic => ::wgpu::SamplerDescriptor { label: Some("anisotropic"), anisotropy_clamp: 16, ..repeating },
+        }
+    }
+}
+
+impl From<SamplerPreset> for ::wgpu::SamplerDescriptor<'static> {
+    fn from(preset: SamplerPreset) -> Self {
+        preset.descriptor()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    /// Decode PNG and JPEG as sRGB color, off for data such as normal maps.
+    /// KTX2 files say themselves whether they are sRGB.
+    pub srgb: bool,
+    /// Generate mipmaps on the GPU when the file has none.  Not possible for
+    /// compressed formats, those need them baked into the file.
+    pub mipmaps: bool,
+    pub sampler: SamplerPreset,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self { srgb: true, mipmaps: true, sampler: SamplerPreset::Trilinear }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    /// None of the files tried for a name exist.
+    NotFound(Vec<String>),
+    Io(io::Error),
+    Image(::image::ImageError),
+    Ktx2(::ktx2::ParseError),
+    /// A valid file this loader or the device cannot use.
+    Unsupported(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::NotFound(tried) => write!(f, "no texture found, tried {}", tried.join(", ")),
+            TextureError::Io(e) => write!(f, "cannot read texture: {}", e),
+            TextureError::Image(e) => write!(f, "cannot decode image: {}", e),
+            TextureError::Ktx2(e) => write!(f, "cannot parse KTX2: {}", e),
+            TextureError::Unsupported(reason) => write!(f, "unsupported texture: {}", reason),
+        }
+    }
+}
+
+impl ::std::error::Error for TextureError {}
+
+/// Decoded texture data, ready to upload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureData {
+    pub size: (u32, u32),
+    pub format: ::wgpu::TextureFormat,
+    /// Mip levels from the full size down, at least one.
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// A texture uploaded by [`load_texture`].
+#[derive(Debug, Clone, Copy)]
+pub struct LoadedTexture {
+    pub texture: Handle<::wgpu::Texture>,
+    pub size: (u32, u32),
+    pub format: ::wgpu::TextureFormat,
+    pub mip_level_count: u32,
+    pub sampler: SamplerPreset,
+}
+
+/// Compressed KTX2 variants, most preferred first, with the feature a device needs to sample them.
+const VARIANTS: [(&str, ::wgpu::Features); 3] = [
+    ("astc", ::wgpu::Features::TEXTURE_COMPRESSION_ASTC),
+    ("etc2", ::wgpu::Features::TEXTURE_COMPRESSION_ETC2),
+    ("bc", ::wgpu::Features::TEXTURE_COMPRESSION_BC),
+];
+
+const EXTENSIONS: [&str; 4] = ["ktx2", "png", "jpg", "jpeg"];
+
+/// Files to try for `name`, in order.  A name with an extension is used as
+/// is.  Without one, `name.<variant>.ktx2` for each compressed variant the
+/// device supports comes first (ASTC on most phones, BC on desktop GPUs),
+/// then uncompressed `name.ktx2`, `name.png`, `name.jpg` and `name.jpeg`.
+pub fn candidates(name: &str, features: ::wgpu::Features) -> Vec<String> {
+    let extension = Path::new(name).extension().and_then(|extension| extension.to_str());
+    if extension.is_some_and(|extension| EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())) {
+        return vec![name.to_string()];
+    }
+    VARIANTS.iter()
+        .filter(|(_, feature)| features.contains(*feature))
+        .map(|(variant, _)| format!("{}.{}.ktx2", name, variant))
+        .chain(EXTENSIONS.iter().map(|extension| format!("{}.{}", name, extension)))
+        .collect()
+}
+
+/// Read the first of [`candidates`] that exists, decode it and upload it
+/// into a texture registered with `resources`, so it survives a device loss.
+pub fn load_texture(gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>, assets: &Assets, name: &str, options: TextureOptions) -> Result<LoadedTexture, TextureError> {
+    let tried = candidates(name, gpu.device.features());
+    for file in tried.iter() {
+        let bytes = match assets.read(file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(TextureError::Io(e)),
+        };
+        let data = decode(file, &bytes, options.srgb)?;
+        let features = data.format.required_features();
+        if !gpu.device.features().contains(features) {
+            return Err(TextureError::Unsupported(format!("{} is {:?}, the device lacks {:?}", file, data.format, features)));
+        }
+        info!("loaded texture {} ({:?}, {}x{}, {} levels)", file, data.format, data.size.0, data.size.1, data.levels.len());
+        return Ok(upload(gpu, resources, file, data, options));
+    }
+    Err(TextureError::NotFound(tried))
+}
+
+/// Decode `bytes` by the extension of `file`: KTX2, or PNG/JPEG into RGBA8.
+pub fn decode(file: &str, bytes: &[u8], srgb: bool) -> Result<TextureData, TextureError> {
+    if file.to_ascii_lowercase().ends_with(".ktx2") {
+        return decode_ktx2(bytes);
+    }
+    let image = ::image::load_from_memory(bytes).map_err(TextureError::Image)?.to_rgba8();
+    Ok(TextureData {
+        size: image.dimensions(),
+        format: if srgb { ::wgpu::TextureFormat::Rgba8UnormSrgb } else { ::wgpu::TextureFormat::Rgba8Unorm },
+        levels: vec![image.into_raw()],
+    })
+}
+
+/// A 2D KTX2 texture without supercompression, with all the mip levels it holds.
+pub fn decode_ktx2(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    let reader = ::ktx2::Reader::new(bytes).map_err(TextureError::Ktx2)?;
+    let header = reader.header();
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(TextureError::Unsupported(format!("{:?} supercompression", scheme)));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count != 1 {
+        return Err(TextureError::Unsupported("only single 2D images are supported, no arrays, cube maps or volumes".to_string()));
+    }
+    let format = header.format
+        .and_then(ktx2_format)
+        .ok_or_else(|| TextureError::Unsupported(format!("format {:?}", header.format)))?;
+    Ok(TextureData {
+        size: (header.pixel_width, header.pixel_height.max(1)),
+        format,
+        levels: reader.levels().map(|level| level.data.to_vec()).collect(),
+    })
+}
+
+/// The wgpu format for a KTX2 (Vulkan) format, for the ones this loader handles.
+pub fn ktx2_format(format: ::ktx2::Format) -> Option<::wgpu::TextureFormat> {
+    use ::ktx2::Format as K;
+    use ::wgpu::TextureFormat as W;
+    let astc_blocks = [
+        ::wgpu::AstcBlock::B4x4, ::wgpu::AstcBlock::B5x4, ::wgpu::AstcBlock::B5x5, ::wgpu::AstcBlock::B6x5,
+        ::wgpu::AstcBlock::B6x6, ::wgpu::AstcBlock::B8x5, ::wgpu::AstcBlock::B8x6, ::wgpu::AstcBlock::B8x8,
+        ::wgpu::AstcBlock::B10x5, ::wgpu::AstcBlock::B10x6, ::wgpu::AstcBlock::B10x8, ::wgpu::AstcBlock::B10x10,
+        ::wgpu::AstcBlock::B12x10, ::wgpu::AstcBlock::B12x12,
+    ];
+    // the LDR ASTC formats come in unorm/srgb pairs, in the order of `astc_blocks`
+    let astc = format.value().checked_sub(K::ASTC_4x4_UNORM_BLOCK.value()).and_then(|offset| {
+        let block = *astc_blocks.get(offset as usize / 2)?;
+        let channel = if offset % 2 == 0 { ::wgpu::AstcChannel::Unorm } else { ::wgpu::AstcChannel::UnormSrgb };
+        Some(W::Astc { block, channel })
+    });
+    Some(match format {
+        K::R8G8B8A8_UNORM => W::Rgba8Unorm,
+        K::R8G8B8A8_SRGB => W::Rgba8UnormSrgb,
+        K::B8G8R8A8_UNORM => W::Bgra8Unorm,
+        K::B8G8R8A8_SRGB => W::Bgra8UnormSrgb,
+        K::BC1_RGBA_UNORM_BLOCK => W::Bc1RgbaUnorm,
+        K::BC1_RGBA_SRGB_BLOCK => W::Bc1RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => W::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => W::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => W::Bc4RUnorm,
+        K::BC5_UNORM_BLOCK => W::Bc5RgUnorm,
+        K::BC7_UNORM_BLOCK => W::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => W::Bc7RgbaUnormSrgb,
+        K::ETC2_R8G8B8_UNORM_BLOCK => W::Etc2Rgb8Unorm,
+        K::ETC2_R8G8B8_SRGB_BLOCK => W::Etc2Rgb8UnormSrgb,
+        K::ETC2_R8G8B8A1_UNORM_BLOCK => W::Etc2Rgb8A1Unorm,
+        K::ETC2_R8G8B8A1_SRGB_BLOCK => W::Etc2Rgb8A1UnormSrgb,
+        K::ETC2_R8G8B8A8_UNORM_BLOCK => W::Etc2Rgba8Unorm,
+        K::ETC2_R8G8B8A8_SRGB_BLOCK => W::Etc2Rgba8UnormSrgb,
+        K::EAC_R11_UNORM_BLOCK => W::EacR11Unorm,
+        K::EAC_R11G11_UNORM_BLOCK => W::EacRg11Unorm,
+        _ => return astc,
+    })
+}
+
+/// Scale uncompressed RGBA8 `data` down to fit `max_dimension`, e.g. the
+/// device's `max_texture_dimension_2d` (2048 with the WebGL2 limits).  Only
+/// the first level is kept, mipmaps need to be generated again.
+pub fn downscale(data: TextureData, max_dimension: u32) -> TextureData {
+    let (width, height) = data.size;
+    if width.max(height) <= max_dimension || data.format.is_compressed() || data.format.block_copy_size(None) != Some(4) {
+        return data;
+    }
+    let scale = max_dimension as f64 / width.max(height) as f64;
+    let size = (((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1));
+    let Some(image) = data.levels.first().and_then(|level| ::image::RgbaImage::from_raw(width, height, level.clone())) else {
+        return data;
+    };
+    info!("scaling a {}x{} texture down to {}x{}", width, height, size.0, size.1);
+    let resized = ::image::imageops::resize(&image, size.0, size.1, ::image::imageops::FilterType::Triangle);
+    TextureData { size, format: data.format, levels: vec![resized.into_raw()] }
+}
+
+/// Register a texture holding `data`, with a full mip chain generated on the
+/// GPU when `options.mipmaps` asks for one and the file had only one level.
+pub fn upload(gpu: &GpuContext, resources: &mut ResourceRegistry<GpuContext>, label: &str, data: TextureData, options: TextureOptions) -> LoadedTexture {
+    let generate = options.mipmaps && data.levels.len() == 1 && can_generate_mipmaps(gpu, data.format);
+    if options.mipmaps && data.levels.len() == 1 && !generate {
+        info!("cannot generate mipmaps for {:?}, {} keeps one level", data.format, label);
+    }
+    let mip_level_count = if generate {
+        ::wgpu::Extent3d { width: data.size.0, height: data.size.1, depth_or_array_layers: 1 }.max_mips(::wgpu::TextureDimension::D2)
+    } else {
+        data.levels.len() as u32
+    };
+    // COPY_SRC so the levels can be read back
+    let mut usage = ::wgpu::TextureUsages::TEXTURE_BINDING | ::wgpu::TextureUsages::COPY_DST | ::wgpu::TextureUsages::COPY_SRC;
+    if generate {
+        usage |= ::wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+    let descriptor = ::wgpu::TextureDescriptor {
+        label: None,
+        size: ::wgpu::Extent3d { width: data.size.0, height: data.size.1, depth_or_array_layers: 1 },
+        mip_level_count,
+        sample_count: 1,
+        dimension: ::wgpu::TextureDimension::D2,
+        format: data.format,
+        usage,
+        view_formats: &[],
+    };
+    let (size, format) = (data.size, data.format);
+    let name = label.to_string();
+    let texture = resources.register(gpu, label, move |gpu| {
+        let texture = gpu.device.create_texture(&::wgpu::TextureDescriptor { label: Some(&name), ..descriptor.clone() });
+        for (level, bytes) in data.levels.iter().enumerate() {
+            write_level(gpu, &texture, level as u32, bytes);
+        }
+        if generate {
+            generate_mipmaps(gpu, &texture);
+        }
+        texture
+    });
+    LoadedTexture { texture, size, format, mip_level_count, sampler: options.sampler }
+}
+
+fn can_generate_mipmaps(gpu: &GpuContext, format: ::wgpu::TextureFormat) -> bool {
+    let features = format.guaranteed_format_features(gpu.device.features());
+    !format.is_compressed()
+        && features.allowed_usages.contains(::wgpu::TextureUsages::RENDER_ATTACHMENT)
+        && features.flags.contains(::wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+fn write_level(gpu: &GpuContext, texture: &::wgpu::Texture, level: u32, bytes: &[u8]) {
+    let format = texture.format();
+    let size = texture.size().mip_level_size(level, texture.dimension()).physical_size(format);
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).expect("color format");
+    gpu.queue.write_texture(
+        ::wgpu::TexelCopyTextureInfo { texture, mip_level: level, origin: ::wgpu::Origin3d::ZERO, aspect: ::wgpu::TextureAspect::All },
+        bytes,
+        ::wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width / block_width * block_size),
+            rows_per_image: Some(size.height / block_height),
+        },
+        size,
+    );
+}
+
+/// Fill mip levels 1 and up of `texture` by drawing each level downsampled
+/// from the one before.  Creates its pipeline on every call, textures are
+/// loaded rarely enough that caching it is not worth it.
+pub fn generate_mipmaps(gpu: &GpuContext, texture: &::wgpu::Texture) {
+    let device = &gpu.device;
+    let shader = device.create_shader_module(::wgpu::include_wgsl!("shaders/blit.wgsl"));
+    let pipeline = device.create_render_pipeline(&::wgpu::RenderPipelineDescriptor {
+        label: Some("mipmaps"),
+        layout: None,
+        vertex: ::wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        fragment: Some(::wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(texture.format().into())],
+        }),
+        primitive: ::wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: ::wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+    let sampler = device.create_sampler(&SamplerPreset::Linear.descriptor());
+    let level_view = |level| texture.create_view(&::wgpu::TextureViewDescriptor {
+        base_mip_level: level,
+        mip_level_count: Some(1),
+        ..Default::default()
+    });
+
+    let mut encoder = device.create_command_encoder(&::wgpu::CommandEncoderDescriptor { label: Some("mipmaps") });
+    for level in 1..texture.mip_level_count() {
+        let source = level_view(level - 1);
+        let target = level_view(level);
+        let bind_group = device.create_bind_group(&::wgpu::BindGroupDescriptor {
+            label: Some("mipmaps"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                ::wgpu::BindGroupEntry { binding: 0, resource: ::wgpu::BindingResource::TextureView(&source) },
+                ::wgpu::BindGroupEntry { binding: 1, resource: ::wgpu::BindingResource::Sampler(&sampler) },
+            ],
+        });
+        let mut rpass = encoder.begin_render_pass(&::wgpu::RenderPassDescriptor {
+            label: Some("mipmaps"),
+            color_attachments: &[Some(::wgpu::RenderPassColorAttachment {
+                view: &target,
+                resolve_target: None,
+                ops: ::wgpu::Operations { load: ::wgpu::LoadOp::Clear(::wgpu::Color::TRANSPARENT), store: ::wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&pipeline);
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+    gpu.queue.submit(Some(encoder.finish()));
+}
@@ -0,0 +1,117 @@
+use ::std::sync::mpsc::{Receiver, TryRecvError};
+use ::std::time::Duration;
+
+use ::log::info;
+
+use crate::gpu::GpuContext;
+
+enum TimerState {
+    Idle,
+    /// Timestamps were written and resolved in an encoder that is not submitted yet.
+    Written,
+    /// Waiting for the readback buffer to be mapped.
+    Mapping(Receiver<Result<(), ::wgpu::BufferAsyncError>>),
+}
+
+/// Measures how long the GPU spends in a render pass with timestamp queries.
+///
+/// Reading the result back never blocks: the timestamps of a frame are
+/// mapped after it is submitted and picked up by a later
+/// [`GpuTimer::poll`].  Frames drawn while a readback is in flight are not
+/// measured.
+pub struct GpuTimer {
+    query_set: ::wgpu::QuerySet,
+    resolve: ::wgpu::Buffer,
+    readback: ::wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    state: TimerState,
+}
+
+impl GpuTimer {
+    const SIZE: ::wgpu::BufferAddress = 2 * ::wgpu::QUERY_SIZE as ::wgpu::BufferAddress;
+
+    /// `None` unless the device was created with `Features::TIMESTAMP_QUERY`.
+    pub fn new(gpu: &GpuContext) -> Option<Self> {
+        if !gpu.device.features().contains(::wgpu::Features::TIMESTAMP_QUERY) {
+            info!("no timestamp queries, GPU time is not measured");
+            return None;
+        }
+        let query_set = gpu.device.create_query_set(&::wgpu::QuerySetDescriptor {
+            label: Some("gpu timer"),
+            ty: ::wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let buffer = |label, usage| gpu.device.create_buffer(&::wgpu::BufferDescriptor {
+            label: Some(label),
+            size: Self::SIZE,
+            usage,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve: buffer("gpu timer resolve", ::wgpu::BufferUsages::QUERY_RESOLVE | ::wgpu::BufferUsages::COPY_SRC),
+            readback: buffer("gpu timer readback", ::wgpu::BufferUsages::COPY_DST | ::wgpu::BufferUsages::MAP_READ),
+            period: gpu.queue.get_timestamp_period(),
+            state: TimerState::Idle,
+        })
+    }
+
+    /// For the render pass to measure, `None` while the last measurement is still being read.
+    pub fn timestamp_writes(&self) -> Option<::wgpu::RenderPassTimestampWrites<'_>> {
+        match self.state {
+            TimerState::Mapping(_) => None,
+            TimerState::Idle | TimerState::Written => Some(::wgpu::RenderPassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(0),
+                end_of_pass_write_index: Some(1),
+            }),
+        }
+    }
+
+    /// Copy the timestamps somewhere readable, after the pass that wrote them.
+    pub fn resolve(&mut self, encoder: &mut ::wgpu::CommandEncoder) {
+        if let TimerState::Mapping(_) = self.state {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, Self::SIZE);
+        self.state = TimerState::Written;
+    }
+
+    /// The encoder passed to [`GpuTimer::resolve`] was submitted, start reading back.
+    pub fn submitted(&mut self) {
+        if let TimerState::Written = self.state {
+            let (sender, receiver) = ::std::sync::mpsc::channel();
+            self.readback.slice(..).map_async(::wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.state = TimerState::Mapping(receiver);
+        }
+    }
+
+    /// The GPU time of the last submitted frame, once it is known.
+    pub fn poll(&mut self, gpu: &GpuContext) -> Option<Duration> {
+        let receiver = match &self.state {
+            TimerState::Mapping(receiver) => receiver,
+            TimerState::Idle | TimerState::Written => return None,
+        };
+        gpu.device.poll(::wgpu::Maintain::Poll);
+        let mapped = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(result) => result.is_ok(),
+            Err(TryRecvError::Disconnected) => false,
+        };
+        self.state = TimerState::Idle;
+        if !mapped {
+            return None;
+        }
+        let ticks: [u64; 2] = {
+            let view = self.readback.slice(..).get_mapped_range();
+            ::bytemuck::pod_read_unaligned(&view)
+        };
+        self.readback.unmap();
+        let nanos = ticks[1].saturating_sub(ticks[0]) as f64 * f64::from(self.period);
+        Some(Duration::from_nanos(nanos as u64))
+    }
+}
@@ -0,0 +1,238 @@
+use ::log::info;
+use ::winit::window::Window;
+
+use crate::gpu::{GpuContext, PerDevice};
+use crate::targets::RenderTargets;
+
+/// Space along the window edges that system bars or display cutouts cover, in physical pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Insets {
+    /// What a `size` window has outside `content`, `[left, top, right, bottom]`
+    /// edges in physical pixels the way Android reports the content rectangle.
+    /// An empty rectangle, as reported before the first layout, means no insets.
+    pub fn outside(size: (u32, u32), content: [i32; 4]) -> Self {
+        let [left, top, right, bottom] = content;
+        if right <= left || bottom <= top {
+            return Self::default();
+        }
+        Self {
+            left: left.max(0) as f32,
+            top: top.max(0) as f32,
+            right: (size.0 as i32 - right).max(0) as f32,
+            bottom: (size.1 as i32 - bottom).max(0) as f32,
+        }
+    }
+
+    /// `rect` in points without the insets.
+    pub fn shrink(&self, rect: ::egui::Rect, pixels_per_point: f32) -> ::egui::Rect {
+        let ppp = pixels_per_point.max(f32::EPSILON);
+        ::egui::Rect::from_min_max(
+            rect.min + ::egui::vec2(self.left, self.top) / ppp,
+            rect.max - ::egui::vec2(self.right, self.bottom) / ppp,
+        )
+    }
+}
+
+/// One egui frame, tessellated and ready for [`UiPainter`].
+#[derive(Clone)]
+pub struct UiFrame {
+    pub textures_delta: ::egui::TexturesDelta,
+    pub primitives: Vec<::egui::ClippedPrimitive>,
+    pub pixels_per_point: f32,
+    /// egui wants the next frame right away, e.g. while animating.
+    pub repaint: bool,
+}
+
+/// The input side of egui.  Window events go in through
+/// [`Ui::on_window_event`] and [`Ui::run`] builds a frame for
+/// [`UiPainter`].  Its platform output is applied to the window, which on
+/// Android shows the soft keyboard while a text field has focus.
+pub struct Ui {
+    pub ctx: ::egui::Context,
+    /// Kept clear of, see [`Insets::outside`].
+    pub insets: Insets,
+    /// Only while there is a window.
+    state: Option<::egui_winit::State>,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ui {
+    pub fn new() -> Self {
+        Self { ctx: ::egui::Context::default(), insets: Insets::default(), state: None }
+    }
+
+    /// Take input from `window`, called whenever a window is created.
+    pub fn attach(&mut self, window: &Window) {
+        self.state = Some(::egui_winit::State::new(
+            self.ctx.clone(),
+            ::egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            window.theme(),
+            None,
+        ));
+    }
+
+    /// The window is gone.  Widget state stays in `ctx`.
+    pub fn detach(&mut self) {
+        self.state = None;
+    }
+
+    /// Feed `event` to egui.  `consumed` is set when egui used it, e.g. a
+    /// click on a widget or typing into a text field, and the app should
+    /// ignore it.
+    pub fn on_window_event(&mut self, window: &Window, event: &::winit::event::WindowEvent) -> ::egui_winit::EventResponse {
+        match self.state.as_mut() {
+            Some(state) => state.on_window_event(window, event),
+            None => ::egui_winit::EventResponse::default(),
+        }
+    }
+
+    /// Run `build` for one frame, `None` without a window.
+    pub fn run(&mut self, window: &Window, build: impl FnMut(&::egui::Context)) -> Option<UiFrame> {
+        let state = self.state.as_mut()?;
+        let mut input = state.take_egui_input(window);
+        let pixels_per_point = ::egui_winit::pixels_per_point(&self.ctx, window);
+        input.screen_rect = input.screen_rect.map(|rect| self.insets.shrink(rect, pixels_per_point));
+        let output = self.ctx.run(input, build);
+        state.handle_platform_output(window, output.platform_output);
+        let repaint = output.viewport_output
+            .get(&::egui::ViewportId::ROOT)
+            .is_some_and(|viewport| viewport.repaint_delay.is_zero());
+        Some(UiFrame {
+            textures_delta: output.textures_delta,
+            primitives: self.ctx.tessellate(output.shapes, output.pixels_per_point),
+            pixels_per_point: output.pixels_per_point,
+            repaint,
+        })
+    }
+
+    /// Start over with a new context that keeps the widget state and style
+    /// of the old one, so egui sends all of its textures again.  Needed
+    /// after [`UiPainter`] lost them with the device.
+    pub fn reset(&mut self, window: Option<&Window>) {
+        info!("resetting egui to upload its textures again");
+        let memory = self.ctx.memory(|memory| memory.clone());
+        let style = self.ctx.style();
+        self.ctx = ::egui::Context::default();
+        self.ctx.memory_mut(|new| *new = memory);
+        self.ctx.set_style(style);
+        self.state = None;
+        if let Some(window) = window {
+            self.attach(window);
+        }
+    }
+}
+
+struct PainterObjects {
+    format: ::wgpu::TextureFormat,
+    renderer: ::egui_wgpu::Renderer,
+}
+
+/// Paints [`UiFrame`]s with egui-wgpu in a pass of its own, after the main
+/// pass has resolved into the target.
+pub struct UiPainter {
+    frame: Option<UiFrame>,
+    /// The last frame painted, without its texture changes, see [`UiPainter::repaint_last`].
+    painted: Option<UiFrame>,
+    device: PerDevice<PainterObjects>,
+    /// The egui-wgpu renderer was replaced and the textures egui sent before are gone.
+    textures_lost: bool,
+}
+
+impl Default for UiPainter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UiPainter {
+    pub fn new() -> Self {
+        Self { frame: None, painted: None, device: PerDevice::new(), textures_lost: false }
+    }
+
+    /// Paint `frame` in the next [`UiPainter::render`].  Texture changes of
+    /// a frame that was never painted are carried over.
+    pub fn set_frame(&mut self, mut frame: UiFrame) {
+        if let Some(mut unpainted) = self.frame.take() {
+            unpainted.textures_delta.append(frame.textures_delta);
+            frame.textures_delta = unpainted.textures_delta;
+        }
+        self.frame = Some(frame);
+    }
+
+    /// Paint the last painted frame again in the next [`UiPainter::render`],
+    /// unless a new one was set.
+    pub fn repaint_last(&mut self) {
+        if self.frame.is_none() {
+            self.frame = self.painted.clone();
+        }
+    }
+
+    /// True once after the textures were lost, see [`Ui::reset`].
+    pub fn take_textures_lost(&mut self) -> bool {
+        ::std::mem::take(&mut self.textures_lost)
+    }
+
+    /// Draw the last frame from [`UiPainter::set_frame`] over `view`.
+    pub fn render(&mut self, gpu: &GpuContext, encoder: &mut ::wgpu::CommandEncoder, view: &::wgpu::TextureView, targets: &RenderTargets) {
+        let Some(frame) = self.frame.take() else {
+            return;
+        };
+        if !self.device.is_current(gpu) || self.device.get().is_some_and(|objects| objects.format != targets.format) {
+            self.textures_lost = self.device.get().is_some();
+            self.device.clear();
+        }
+        let renderer = &mut self.device.get_or_create(gpu, |gpu| {
+            info!("creating egui renderer for {:?}", targets.format);
+            PainterObjects {
+                format: targets.format,
+                renderer: ::egui_wgpu::Renderer::new(&gpu.device, targets.format, None, 1, false),
+            }
+        }).renderer;
+        for (id, delta) in frame.textures_delta.set.iter() {
+            renderer.update_texture(&gpu.device, &gpu.queue, *id, delta);
+        }
+        let screen = ::egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [targets.size.0, targets.size.1],
+            pixels_per_point: frame.pixels_per_point,
+        };
+        // only paint callbacks record command buffers of their own, there are none here
+        let callbacks = renderer.update_buffers(&gpu.device, &gpu.queue, encoder, &frame.primitives, &screen);
+        if !callbacks.is_empty() {
+            gpu.queue.submit(callbacks);
+        }
+
+        let rpass = encoder.begin_render_pass(&::wgpu::RenderPassDescriptor {
+            label: Some("egui"),
+            color_attachments: &[Some(::wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: ::wgpu::Operations {
+                    load: ::wgpu::LoadOp::Load,
+                    store: ::wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render(&mut rpass.forget_lifetime(), &frame.primitives, &screen);
+        for id in frame.textures_delta.free.iter() {
+            renderer.free_texture(id);
+        }
+        self.painted = Some(UiFrame { textures_delta: Default::default(), ..frame });
+    }
+}
@@ -0,0 +1,190 @@
+use ::android_rust_example_apk_common::pointer::{PointerEvent, PointerTracker};
+use ::android_rust_example_apk_wgpu::camera::{OrbitCamera, OrbitController, Projection};
+use ::android_rust_example_apk_wgpu::renderer::Matrix;
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{DeviceId, ElementState, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent};
+
+const VIEWPORT: (u32, u32) = (800, 600);
+
+/// Normalized device coordinates of a world position.
+fn project(matrix: &Matrix, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    let clip: Vec<f32> = (0..4).map(|row| matrix[0][row] * x + matrix[1][row] * y + matrix[2][row] * z + matrix[3][row]).collect();
+    [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+}
+
+/// Physical pixels, y down.
+fn to_screen(camera: &OrbitCamera, world: [f32; 3]) -> [f32; 2] {
+    let [x, y, _] = project(&camera.view_proj(), world);
+    [(x + 1.0) / 2.0 * camera.viewport.0 as f32, (1.0 - y) / 2.0 * camera.viewport.1 as f32]
+}
+
+fn assert_near<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+    assert!(actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-3), "{:?} != {:?}", actual, expected);
+}
+
+fn touch(tracker: &mut PointerTracker, id: u64, phase: TouchPhase, [x, y]: [f64; 2]) -> PointerEvent {
+    let touch = Touch { device_id: DeviceId::dummy(), phase, location: PhysicalPosition::new(x, y), force: None, id };
+    tracker.window_event(&WindowEvent::Touch(touch)).expect("a pointer event")
+}
+
+#[test]
+fn default_camera_shows_the_clip_space_square() {
+    let camera = OrbitCamera::new(VIEWPORT);
+    assert_near(project(&camera.view_proj(), [0.0, 0.0, 0.0])[..2].try_into().unwrap(), [0.0, 0.0]);
+    assert_near(project(&camera.view_proj(), [0.0, 1.0, 0.0])[..2].try_into().unwrap(), [0.0, 1.0]);
+    // square pixels, one unit to the right is less than the half width on a wide viewport
+    assert_near(project(&camera.view_proj(), [1.0, 0.0, 0.0])[..2].try_into().unwrap(), [0.75, 0.0]);
+}
+
+#[test]
+fn depth_runs_from_near_to_far() {
+    for projection in [Projection::Perspective { fov_y: 1.0 }, Projection::Orthographic { height: 2.0 }] {
+        let camera = OrbitCamera { projection, distance: 10.0, ..OrbitCamera::new(VIEWPORT) };
+        let eye = camera.eye();
+        let near = project(&camera.view_proj(), [eye[0], eye[1], eye[2] - camera.near]);
+        let far = project(&camera.view_proj(), [eye[0], eye[1], eye[2] - camera.far]);
+        assert!(near[2].abs() < 1e-4 && (far[2] - 1.0).abs() < 1e-4, "{:?}: {} {}", projection, near[2], far[2]);
+    }
+}
+
+#[test]
+fn framing_fits_the_narrower_side() {
+    let (min, max) = ([-1.0, -0.5, -2.0], [3.0, 1.5, 0.0]);
+    for viewport in [(800, 600), (450, 1000)] {
+        for projection in [Projection::Perspective { fov_y: 0.8 }, Projection::Orthographic { height: 1.0 }] {
+            let mut camera = OrbitCamera { projection, yaw: 0.3, pitch: -0.2, ..OrbitCamera::new(viewport) };
+            camera.frame(min, max);
+            for corner in 0..8 {
+                let point = ::std::array::from_fn(|i| if corner & (1 << i) == 0 { min[i] } else { max[i] });
+                let [x, y, z] = project(&camera.view_proj(), point);
+                assert!(x.abs() <= 1.0 && y.abs() <= 1.0 && (0.0..=1.0).contains(&z), "{:?} {:?}: {:?}", viewport, projection, [x, y, z]);
+            }
+        }
+    }
+}
+
+#[test]
+fn orbit_wraps_yaw_and_stops_short_of_the_poles() {
+    let mut camera = OrbitCamera { distance: 2.0, ..OrbitCamera::new(VIEWPORT) };
+    camera.orbit(::std::f32::consts::FRAC_PI_2, 0.0);
+    assert_near(camera.eye(), [2.0, 0.0, 0.0]);
+    camera.orbit(-::std::f32::consts::PI, 0.0);
+    assert_near([camera.yaw], [1.5 * ::std::f32::consts::PI]);
+    assert_near(camera.eye(), [-2.0, 0.0, 0.0]);
+
+    camera.orbit(0.0, 10.0);
+    assert!(camera.pitch < ::std::f32::consts::FRAC_PI_2);
+    assert!(camera.eye()[1] > 1.99);
+    // still a valid view looking down
+    assert!(camera.view_proj().iter().flatten().all(|value| value.is_finite()));
+}
+
+#[test]
+fn pan_keeps_the_scene_under_the_pointer() {
+    for projection in [Projection::Perspective { fov_y: 0.8 }, Projection::Orthographic { height: 5.0 }] {
+        let mut camera = OrbitCamera { projection, yaw: 0.4, pitch: 0.3, ..OrbitCamera::new(VIEWPORT) };
+        let grabbed = camera.target;
+        camera.pan(30.0, -20.0);
+        assert_near(to_screen(&camera, grabbed), [430.0, 280.0]);
+    }
+}
+
+#[test]
+fn zoom_moves_closer_or_shrinks_the_view() {
+    let mut camera = OrbitCamera { distance: 10.0, ..OrbitCamera::new(VIEWPORT) };
+    camera.zoom(2.0);
+    assert_eq!(camera.distance, 5.0);
+    camera.zoom(1000.0);
+    assert_eq!(camera.distance, 2.0 * camera.near);
+    camera.zoom(0.0);
+    assert_eq!(camera.distance, 2.0 * camera.near);
+
+    let mut camera = OrbitCamera { projection: Projection::Orthographic { height: 4.0 }, ..camera };
+    camera.zoom(2.0);
+    assert_eq!(camera.projection, Projection::Orthographic { height: 2.0 });
+}
+
+#[test]
+fn zoom_at_keeps_the_point_under_the_pointer() {
+    for projection in [Projection::Perspective { fov_y: 0.8 }, Projection::Orthographic { height: 5.0 }] {
+        let mut camera = OrbitCamera { projection, yaw: -0.7, pitch: 0.2, ..OrbitCamera::new(VIEWPORT) };
+        // in the plane of the target, 200 pixels right and 100 up of the center
+        let (right, up) = camera.right_up();
+        let step = camera.world_per_pixel();
+        let point: Vec<f32> = (0..3).map(|i| camera.target[i] + right[i] * 200.0 * step + up[i] * 100.0 * step).collect();
+        let point = [point[0], point[1], point[2]];
+        assert_near(to_screen(&camera, point), [600.0, 200.0]);
+        camera.zoom_at(1.5, PhysicalPosition::new(600.0, 200.0));
+        assert_near(to_screen(&camera, point), [600.0, 200.0]);
+        assert_near([camera.world_per_pixel()], [step / 1.5]);
+    }
+}
+
+#[test]
+fn one_finger_drag_orbits() {
+    let mut camera = OrbitCamera::new(VIEWPORT);
+    let mut controller = OrbitController::new();
+    let mut fingers = PointerTracker::new();
+    assert!(!controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Started, [400.0, 300.0])));
+    // a drag across the viewport height is half a turn
+    assert!(controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Moved, [400.0, 450.0])));
+    assert_near([camera.yaw, camera.pitch], [0.0, ::std::f32::consts::FRAC_PI_4]);
+    assert!(controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Moved, [250.0, 450.0])));
+    assert_near([camera.yaw], [::std::f32::consts::FRAC_PI_4]);
+
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Ended, [250.0, 450.0]));
+    let before = camera;
+    assert!(!controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Moved, [0.0, 0.0])));
+    assert_eq!(camera, before);
+}
+
+#[test]
+fn two_fingers_pinch_and_pan() {
+    let mut camera = OrbitCamera { distance: 10.0, ..OrbitCamera::new(VIEWPORT) };
+    let mut controller = OrbitController::new();
+    let mut fingers = PointerTracker::new();
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Started, [350.0, 300.0]));
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 2, TouchPhase::Started, [450.0, 300.0]));
+    // spreading to twice the distance around the same midpoint halves the distance, without orbiting
+    assert!(controller.pointer_event(&mut camera, &touch(&mut fingers, 2, TouchPhase::Moved, [500.0, 300.0])));
+    assert!(controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Moved, [300.0, 300.0])));
+    assert_near([camera.distance, camera.yaw, camera.pitch], [5.0, 0.0, 0.0]);
+
+    // both fingers down by 50 pixels drag the scene along
+    let grabbed = camera.target;
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Moved, [300.0, 350.0]));
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 2, TouchPhase::Moved, [500.0, 350.0]));
+    assert_near(to_screen(&camera, grabbed), [400.0, 350.0]);
+    assert_near([camera.distance], [5.0]);
+
+    // lifting one finger goes back to orbiting with the other
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 1, TouchPhase::Ended, [300.0, 350.0]));
+    controller.pointer_event(&mut camera, &touch(&mut fingers, 2, TouchPhase::Moved, [500.0, 500.0]));
+    assert_near([camera.pitch], [::std::f32::consts::FRAC_PI_4]);
+}
+
+#[test]
+fn mouse_buttons_and_wheel() {
+    let mut camera = OrbitCamera::new(VIEWPORT);
+    let mut controller = OrbitController::new();
+    let mut tracker = PointerTracker::new();
+    let mut mouse = |camera: &mut OrbitCamera, event: WindowEvent| {
+        let pointer = tracker.window_event(&event).expect("a pointer event");
+        controller.pointer_event(camera, &pointer)
+    };
+    let device_id = DeviceId::dummy();
+    mouse(&mut camera, WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(400.0, 300.0) });
+    mouse(&mut camera, WindowEvent::MouseInput { device_id, state: ElementState::Pressed, button: MouseButton::Right });
+    let grabbed = camera.target;
+    assert!(mouse(&mut camera, WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(420.0, 310.0) }));
+    assert_near(to_screen(&camera, grabbed), [420.0, 310.0]);
+    assert_eq!((camera.yaw, camera.pitch), (0.0, 0.0));
+    mouse(&mut camera, WindowEvent::MouseInput { device_id, state: ElementState::Released, button: MouseButton::Right });
+    // hovering does nothing
+    assert!(!mouse(&mut camera, WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(0.0, 0.0) }));
+
+    let distance = camera.distance;
+    let wheel = MouseScrollDelta::LineDelta(0.0, 1.0);
+    assert!(mouse(&mut camera, WindowEvent::MouseWheel { device_id, delta: wheel, phase: TouchPhase::Moved }));
+    assert!(camera.distance < distance);
+}
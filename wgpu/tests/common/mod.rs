@@ -0,0 +1,16 @@
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::gpu::GpuContext;
+
+/// A headless context for tests that need a GPU.  Without any adapter, not even
+/// a software one, the test should be skipped: this returns `None` unless
+/// `REQUIRE_GPU=1` is set, then it panics so CI can't silently skip them.
+pub fn headless(config: RendererConfig) -> Option<GpuContext> {
+    let gpu = GpuContext::headless(config);
+    if gpu.is_none() {
+        if ::std::env::var_os("REQUIRE_GPU").is_some_and(|value| value == "1") {
+            panic!("no adapter available and REQUIRE_GPU=1 is set");
+        }
+        eprintln!("skipped, no adapter available");
+    }
+    gpu
+}
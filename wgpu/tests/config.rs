@@ -0,0 +1,126 @@
+use ::std::collections::HashMap;
+
+use ::android_rust_example_apk_wgpu::config::{AlphaMode, Backend, LimitsPreset, PresentMode, RendererConfig, SrgbPolicy};
+use ::wgpu::{CompositeAlphaMode, SurfaceCapabilities, TextureFormat};
+
+fn capabilities() -> SurfaceCapabilities {
+    SurfaceCapabilities {
+        formats: vec![TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb],
+        present_modes: vec![::wgpu::PresentMode::Fifo],
+        alpha_modes: vec![CompositeAlphaMode::Opaque],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn toml_overrides_defaults() {
+    let config = RendererConfig::from_toml(r#"
+        backends = ["vulkan", "gl"]
+        present_mode = "mailbox"
+        frame_latency = 1
+        record_input = true
+    "#).expect("valid config");
+    assert_eq!(config.backends, vec![Backend::Vulkan, Backend::Gl]);
+    assert_eq!(config.backends(), ::wgpu::Backends::VULKAN | ::wgpu::Backends::GL);
+    assert_eq!(config.present_mode, PresentMode::Mailbox);
+    assert_eq!(config.frame_latency, 1);
+    assert_eq!(config.alpha_mode, RendererConfig::default().alpha_mode);
+    assert!(config.record_input && !RendererConfig::default().record_input);
+}
+
+#[test]
+fn toml_rejects_unknown_settings() {
+    assert!(RendererConfig::from_toml("present_mod = \"fifo\"").is_err());
+    assert!(RendererConfig::from_toml("present_mode = \"sometimes\"").is_err());
+}
+
+#[test]
+fn env_overrides_config() {
+    let env: HashMap<&str, &str> = [
+        ("RENDERER_ALPHA_MODE", "opaque"),
+        ("RENDERER_SRGB", "avoid"),
+        ("RENDERER_FEATURES", "DEPTH_CLIP_CONTROL, TIMESTAMP_QUERY"),
+    ].into_iter().collect();
+    let mut config = RendererConfig::default();
+    config.apply_env(|name| env.get(name).map(|value| value.to_string())).expect("valid env");
+    assert_eq!(config.alpha_mode, AlphaMode::Opaque);
+    assert_eq!(config.srgb, SrgbPolicy::Avoid);
+    assert_eq!(config.required_features, vec!["DEPTH_CLIP_CONTROL", "TIMESTAMP_QUERY"]);
+
+    let error = config.apply_env(|name| (name == "RENDERER_FRAME_LATENCY").then(|| "two".to_string()));
+    assert!(error.is_err());
+}
+
+#[test]
+fn surface_settings_follow_config_when_supported() {
+    let config = RendererConfig {
+        present_mode: PresentMode::Fifo,
+        alpha_mode: AlphaMode::Opaque,
+        ..Default::default()
+    };
+    let settings = config.surface_settings(&capabilities()).expect("compatible surface");
+    assert_eq!(settings.format, TextureFormat::Bgra8UnormSrgb);
+    assert_eq!(settings.present_mode, ::wgpu::PresentMode::Fifo);
+    assert_eq!(settings.alpha_mode, CompositeAlphaMode::Opaque);
+}
+
+#[test]
+fn surface_settings_fall_back_when_unsupported() {
+    let config = RendererConfig {
+        present_mode: PresentMode::Mailbox,
+        alpha_mode: AlphaMode::PreMultiplied,
+        srgb: SrgbPolicy::Avoid,
+        frame_latency: 0,
+        ..Default::default()
+    };
+    let settings = config.surface_settings(&capabilities()).expect("compatible surface");
+    assert_eq!(settings.format, TextureFormat::Bgra8Unorm);
+    assert_eq!(settings.present_mode, ::wgpu::PresentMode::AutoVsync);
+    assert_eq!(settings.alpha_mode, CompositeAlphaMode::Auto);
+    assert_eq!(settings.frame_latency, 1);
+
+    assert!(config.surface_settings(&SurfaceCapabilities::default()).is_none());
+}
+
+#[test]
+fn unsupported_features_and_limits_are_dropped() {
+    let config = RendererConfig {
+        required_features: vec!["DEPTH_CLIP_CONTROL".to_string(), "NOT_A_FEATURE".to_string()],
+        limits: LimitsPreset::Default,
+        ..Default::default()
+    };
+    assert_eq!(config.features(::wgpu::Features::empty()), ::wgpu::Features::empty());
+    assert_eq!(config.features(::wgpu::Features::DEPTH_CLIP_CONTROL), ::wgpu::Features::DEPTH_CLIP_CONTROL);
+
+    let weak = ::wgpu::Limits::downlevel_webgl2_defaults();
+    assert_eq!(config.limits(&weak), weak.clone().using_resolution(weak));
+}
+
+#[test]
+fn compute_limits_are_raised_only_where_they_can_be_used() {
+    let webgl2 = ::wgpu::Limits::downlevel_webgl2_defaults();
+    let supported = ::wgpu::Limits { max_compute_workgroup_size_x: 128, ..::wgpu::Limits::default() };
+    let config = RendererConfig::default();
+    assert_eq!(config.compute_limits(webgl2.clone(), &supported, ::wgpu::DownlevelFlags::empty()), webgl2);
+
+    let raised = config.compute_limits(webgl2.clone(), &supported, ::wgpu::DownlevelFlags::COMPUTE_SHADERS);
+    assert_eq!(raised.max_storage_buffers_per_shader_stage, ::wgpu::Limits::downlevel_defaults().max_storage_buffers_per_shader_stage);
+    assert_eq!((raised.max_compute_workgroup_size_x, raised.max_compute_invocations_per_workgroup), (128, 256));
+    assert_eq!(raised.max_texture_dimension_2d, webgl2.max_texture_dimension_2d);
+
+    let config = RendererConfig { compute_shaders: false, ..Default::default() };
+    assert_eq!(config.compute_limits(webgl2.clone(), &supported, ::wgpu::DownlevelFlags::COMPUTE_SHADERS), webgl2);
+}
+
+#[test]
+fn sample_count_falls_back_to_supported() {
+    use ::wgpu::TextureFormatFeatureFlags as Flags;
+    let config = RendererConfig { msaa_samples: 8, ..Default::default() };
+    let color = Flags::MULTISAMPLE_X2 | Flags::MULTISAMPLE_X4 | Flags::MULTISAMPLE_X8;
+    assert_eq!(config.sample_count(color, color), 8);
+    // the depth format decides too
+    assert_eq!(config.sample_count(color, Flags::MULTISAMPLE_X4), 4);
+    assert_eq!(config.sample_count(Flags::empty(), color), 1);
+    assert_eq!(RendererConfig { msaa_samples: 3, ..Default::default() }.sample_count(color, color), 2);
+    assert_eq!(RendererConfig { msaa_samples: 0, ..Default::default() }.sample_count(color, color), 1);
+}
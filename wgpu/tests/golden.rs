@@ -0,0 +1,293 @@
+//! Renders known scenes offscreen and compares them with the PNGs in
+//! `tests/golden`.  Run with `UPDATE_GOLDEN=1` to write new references after
+//! an intended change.  On a mismatch the rendered frame and a diff image
+//! (differing pixels in red) are written next to the test binaries in
+//! `target/tmp/golden`.
+
+use ::std::fs::File;
+use ::std::path::{Path, PathBuf};
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_common::timing::FrameStats;
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::camera::OrbitCamera;
+use ::android_rust_example_apk_wgpu::compute;
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::gpu::GpuContext;
+use ::android_rust_example_apk_wgpu::model::load_model;
+use ::android_rust_example_apk_wgpu::offscreen::{write_png, OffscreenTarget};
+use ::android_rust_example_apk_wgpu::particles::Simulation;
+use ::android_rust_example_apk_wgpu::renderer::{checkerboard, multiply, scale, translation, Mesh, Renderer};
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+use ::android_rust_example_apk_wgpu::sprites::Sprite;
+use ::android_rust_example_apk_wgpu::text::{load_font, TextSection, DEFAULT_FONT};
+
+mod common;
+
+const SIZE: (u32, u32) = (64, 64);
+
+/// Color distance (YIQ, as in pixelmatch) above which two pixels count as
+/// different, relative to the largest possible distance.
+const PIXEL_THRESHOLD: f32 = 0.1;
+/// Share of pixels allowed to differ, drivers rasterize triangle edges slightly differently.
+const MAX_DIFFERENT: f32 = 0.01;
+
+struct Scene {
+    gpu: GpuContext,
+    renderer: Renderer,
+    resources: ResourceRegistry<GpuContext>,
+}
+
+impl Scene {
+    fn new(config: RendererConfig) -> Option<Self> {
+        let gpu = common::headless(config)?;
+        Some(Self { gpu, renderer: Renderer::new(), resources: ResourceRegistry::new() })
+    }
+
+    fn render(&mut self) -> Vec<u8> {
+        OffscreenTarget::new(&self.gpu, SIZE).render(&self.gpu, &mut self.renderer, &self.resources)
+    }
+}
+
+/// Skips the test (returns early) when there is no adapter, see [`common::headless`].
+macro_rules! scene {
+    () => {
+        scene!(RendererConfig::default())
+    };
+    ($config:expr) => {
+        match Scene::new($config) {
+            Some(scene) => scene,
+            None => return,
+        }
+    };
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn read_png(path: &Path) -> ((u32, u32), Vec<u8>) {
+    let decoder = ::png::Decoder::new(File::open(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)));
+    let mut reader = decoder.read_info().expect("valid png");
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).expect("png frame");
+    assert_eq!(info.color_type, ::png::ColorType::Rgba, "{} is not RGBA", path.display());
+    pixels.truncate(info.buffer_size());
+    ((info.width, info.height), pixels)
+}
+
+fn yiq(pixel: &[u8]) -> [f32; 3] {
+    // blend onto white so transparent pixels compare by what they look like
+    let alpha = pixel[3] as f32 / 255.0;
+    let [r, g, b] = [0, 1, 2].map(|i| 255.0 + (pixel[i] as f32 - 255.0) * alpha);
+    [
+        r * 0.2988953 + g * 0.5866225 + b * 0.1144822,
+        r * 0.595978 - g * 0.2741761 - b * 0.3218019,
+        r * 0.2114702 - g * 0.5226171 + b * 0.3111469,
+    ]
+}
+
+/// Perceptual distance between two pixels, from 0 to 1.
+fn distance(a: &[u8], b: &[u8]) -> f32 {
+    const MAX: f32 = 35215.0;
+    let ([ya, ia, qa], [yb, ib, qb]) = (yiq(a), yiq(b));
+    let (y, i, q) = (ya - yb, ia - ib, qa - qb);
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX
+}
+
+fn assert_golden(name: &str, pixels: &[u8]) {
+    let reference = golden_dir().join(format!("{}.png", name));
+    if ::std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&reference, SIZE, pixels).expect("write reference image");
+        eprintln!("updated {}", reference.display());
+        return;
+    }
+    let (size, expected) = read_png(&reference);
+    assert_eq!(size, SIZE, "{} has a different size, rerun with UPDATE_GOLDEN=1", reference.display());
+
+    let threshold = PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+    let mut diff = Vec::with_capacity(pixels.len());
+    let mut different = 0;
+    for (actual, expected) in pixels.chunks(4).zip(expected.chunks(4)) {
+        if distance(actual, expected) > threshold {
+            different += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // faded copy of the frame for orientation
+            let [y, _, _] = yiq(actual);
+            let gray = (255.0 - (255.0 - y) * 0.2) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+    let share = different as f32 / (SIZE.0 * SIZE.1) as f32;
+    if share > MAX_DIFFERENT {
+        let out = output_dir();
+        ::std::fs::create_dir_all(&out).expect("create output directory");
+        write_png(out.join(format!("{}.actual.png", name)), SIZE, pixels).expect("write actual image");
+        write_png(out.join(format!("{}.diff.png", name)), SIZE, &diff).expect("write diff image");
+        panic!(
+            "{}: {} pixels ({:.2}%) differ from {}, see {}",
+            name, different, share * 100.0, reference.display(), out.display(),
+        );
+    }
+}
+
+#[test]
+fn clear() {
+    let mut scene = scene!();
+    scene.renderer.clear_color = ::wgpu::Color { r: 0.1, g: 0.2, b: 0.6, a: 1.0 };
+    let pixels = scene.render();
+    assert_golden("clear", &pixels);
+}
+
+#[test]
+fn demo_scene() {
+    let mut scene = scene!();
+    scene.renderer.demo_scene(&scene.gpu, &mut scene.resources);
+    let pixels = scene.render();
+    assert_golden("demo_scene", &pixels);
+}
+
+#[test]
+fn transformed_and_tinted() {
+    let mut scene = scene!();
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    let quad = scene.renderer.add_mesh(&scene.gpu, &mut scene.resources, "quad", &Mesh::quad([1.0, 1.0, 1.0, 1.0]), None);
+    let mesh = scene.renderer.mesh_mut(quad);
+    mesh.transform = multiply(&translation(0.25, 0.25, 0.0), &scale(0.5, 1.0, 1.0));
+    mesh.tint = [1.0, 0.5, 0.0, 1.0];
+    let pixels = scene.render();
+    assert_golden("transformed_and_tinted", &pixels);
+}
+
+#[test]
+fn depth_keeps_nearer_mesh() {
+    let mut scene = scene!();
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    // the near quad is drawn first and still covers the far one
+    let near = scene.renderer.add_mesh(&scene.gpu, &mut scene.resources, "near", &Mesh::quad([1.0, 0.0, 0.0, 1.0]), None);
+    scene.renderer.mesh_mut(near).transform = translation(-0.2, 0.0, 0.25);
+    let far = scene.renderer.add_mesh(&scene.gpu, &mut scene.resources, "far", &Mesh::quad([0.0, 0.0, 1.0, 1.0]), None);
+    scene.renderer.mesh_mut(far).transform = translation(0.2, 0.0, 0.75);
+    let pixels = scene.render();
+    assert_golden("depth", &pixels);
+}
+
+#[test]
+fn msaa() {
+    let mut scene = scene!(RendererConfig { msaa_samples: 4, ..Default::default() });
+    scene.renderer.demo_scene(&scene.gpu, &mut scene.resources);
+    let pixels = scene.render();
+    assert_golden("msaa", &pixels);
+}
+
+#[test]
+fn overlay() {
+    let mut scene = scene!();
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    // few enough frames for whole pixel columns, some of them late
+    let mut stats = FrameStats::with_capacity(16);
+    let mut at = Instant::now();
+    for frame in 0..20u32 {
+        let interval = match frame % 8 { 7 => 40, 3 => 25, _ => 16 };
+        at += Duration::from_millis(interval);
+        stats.record_present(at);
+        stats.record_cpu(Duration::from_millis(4));
+        stats.record_gpu(Duration::from_millis(8));
+    }
+    scene.renderer.overlay.area = [-1.0, -1.0, 1.0, 1.0];
+    scene.renderer.overlay.visible = true;
+    scene.renderer.overlay.update(&stats);
+    let pixels = scene.render();
+    assert_golden("overlay", &pixels);
+}
+
+#[test]
+fn sprites() {
+    let mut scene = scene!(RendererConfig { msaa_samples: 4, ..Default::default() });
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    // a mesh underneath, sprites are drawn over it whatever its depth
+    let quad = scene.renderer.add_mesh(&scene.gpu, &mut scene.resources, "quad", &Mesh::quad([0.2, 0.2, 0.8, 1.0]), None);
+    scene.renderer.mesh_mut(quad).transform = translation(0.0, 0.0, 0.9);
+    let checker = Renderer::create_texture(&scene.gpu, &mut scene.resources, "checker", 4, 4, checkerboard(4, 4, [255, 255, 255, 255], [0, 0, 0, 255]));
+    let sprites = &mut scene.renderer.sprites;
+    // added on top first, `z` still puts it behind the red one
+    sprites.add(Sprite { texture: Some(checker), z: -1.0, anchor: [0.0, 0.0], ..Sprite::new([4.0, 4.0], [32.0, 32.0]) });
+    sprites.add(Sprite { color: [1.0, 0.0, 0.0, 0.5], z: -2.0, ..Sprite::new([32.0, 32.0], [24.0, 24.0]) });
+    sprites.add(Sprite { color: [0.0, 1.0, 0.0, 1.0], rotation: ::std::f32::consts::FRAC_PI_4, ..Sprite::new([48.0, 48.0], [16.0, 8.0]) });
+    sprites.add(Sprite { visible: false, ..Sprite::new([32.0, 32.0], [64.0, 64.0]) });
+    let pixels = scene.render();
+    assert_golden("sprites", &pixels);
+}
+
+#[test]
+fn text() {
+    let mut scene = scene!();
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    scene.renderer.text.font = Some(load_font(&Assets::source_tree(), DEFAULT_FONT).expect("font in the assets folder"));
+    // sections are placed in logical pixels, drawn at twice that size
+    scene.renderer.text.set_scale_factor(2.0);
+    scene.renderer.text.queue(TextSection::new("AV", [1.0, 0.0], 14.0));
+    scene.renderer.text.queue(TextSection {
+        color: [1.0, 0.8, 0.2, 1.0],
+        max_width: Some(20.0),
+        ..TextSection::new("wrap me", [1.0, 14.0], 7.0)
+    });
+    let pixels = scene.render();
+    assert_golden("text", &pixels);
+}
+
+#[test]
+fn model() {
+    let mut scene = scene!(RendererConfig { msaa_samples: 4, ..Default::default() });
+    scene.renderer.clear_color = ::wgpu::Color::BLACK;
+    let model = load_model(&Assets::source_tree(), "models/demo.gltf").expect("demo model in the assets folder");
+    scene.renderer.models.add(&scene.gpu, &mut scene.resources, &model);
+    // from above and to the right, so the lit and shaded sides both show
+    let mut camera = OrbitCamera { yaw: 0.5, pitch: 0.4, ..OrbitCamera::new(SIZE) };
+    let (min, max) = model.bounds().expect("meshes");
+    camera.frame(min, max);
+    scene.renderer.view_proj = camera.view_proj();
+    scene.renderer.eye = camera.eye();
+    let pixels = scene.render();
+    assert_golden("model", &pixels);
+}
+
+#[test]
+fn particles() {
+    // the compute shader, where the adapter runs them, and the CPU fallback end up in the same place
+    for compute_shaders in [true, false] {
+        let mut scene = scene!(RendererConfig { compute_shaders, ..Default::default() });
+        scene.renderer.clear_color = ::wgpu::Color::BLACK;
+        let particles = &mut scene.renderer.particles;
+        particles.spawn(48);
+        particles.size = 8.0;
+        particles.attractor = Some([44.0, 20.0]);
+        let mut pixels = scene.render();
+        for _ in 0..12 {
+            scene.renderer.particles.advance(Duration::from_millis(25));
+            pixels = scene.render();
+        }
+        let expected = match compute_shaders && compute::supported(&scene.gpu) {
+            true => Simulation::Gpu,
+            false => Simulation::Cpu,
+        };
+        assert_eq!(scene.renderer.particles.simulation(), Some(expected));
+        assert_golden("particles", &pixels);
+    }
+}
+
+#[test]
+fn distance_is_perceptual() {
+    let white = [255, 255, 255, 255];
+    assert_eq!(distance(&white, &white), 0.0);
+    assert!(distance(&[0, 0, 0, 255], &white) > 0.9);
+    // a small brightness change stays under the threshold
+    assert!(distance(&[250, 250, 250, 255], &white) < PIXEL_THRESHOLD * PIXEL_THRESHOLD);
+    // fully transparent pixels look like the white background
+    assert!(distance(&[0, 0, 0, 0], &white) < 0.0001);
+}
@@ -0,0 +1,127 @@
+use ::std::time::Duration;
+
+use ::android_rust_example_apk_common::headless::Harness;
+use ::android_rust_example_apk_common::navigation::ANDROID_KEYCODE_BACK;
+use ::android_rust_example_apk_common::record::{self, RecordedEvent, TimedEvent};
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::{ApplicationState, Screen};
+use ::winit::dpi::PhysicalPosition;
+use ::winit::event::{ElementState, MouseButton, WindowEvent};
+use ::winit::keyboard::{NativeKeyCode, PhysicalKey};
+
+mod common;
+
+fn harness() -> Harness<ApplicationState<'static>> {
+    let mut harness = Harness::new(ApplicationState::new(), 640, 480);
+    harness.resume();
+    harness
+}
+
+#[test]
+fn resume_without_gpu_tracks_window() {
+    let harness = harness();
+    let gfx = harness.app.gfx.as_ref().expect("resumed");
+    assert!(gfx.surface.is_none());
+    assert!(harness.app.gpu.is_none());
+    assert_eq!(gfx.size, (640, 480));
+}
+
+#[test]
+fn cursor_follows_mouse_and_touch() {
+    let mut harness = harness();
+    harness.mouse_move(12.0, 34.0);
+    assert_eq!(harness.app.gfx.as_ref().unwrap().cursor_position, PhysicalPosition::new(12.0, 34.0));
+    harness.tap(56.0, 78.0);
+    assert_eq!(harness.app.gfx.as_ref().unwrap().cursor_position, PhysicalPosition::new(56.0, 78.0));
+    harness.mouse_button(MouseButton::Left, ElementState::Pressed);
+    assert_eq!(harness.app.pointers.pressed().count(), 1);
+}
+
+#[test]
+fn resize_then_redraw_without_gpu() {
+    let mut harness = harness();
+    harness.resize(320, 200).redraw();
+    let gfx = harness.app.gfx.as_ref().unwrap();
+    assert_eq!(gfx.size, (320, 200));
+    assert!(!gfx.did_resize);
+    assert_eq!(harness.window.redraw_requests(), 1);
+}
+
+#[test]
+fn back_at_root_exits() {
+    let mut harness = harness();
+    harness.app.push_screen(Screen::Details);
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert!(!harness.app.exit_requested);
+    harness.advance(Duration::from_millis(10));
+    let now = harness.now();
+    harness.app.navigate_back(now);
+    assert!(harness.app.exit_requested);
+}
+
+#[test]
+fn replay_stops_at_close() {
+    let mut harness = harness();
+    let recording = vec![
+        TimedEvent { at: Duration::from_millis(0), event: RecordedEvent::CursorMoved(PhysicalPosition::new(1.0, 2.0)) },
+        TimedEvent { at: Duration::from_millis(10), event: RecordedEvent::CloseRequested },
+        TimedEvent { at: Duration::from_millis(20), event: RecordedEvent::CursorMoved(PhysicalPosition::new(3.0, 4.0)) },
+    ];
+    record::replay(&mut harness.app, &recording);
+    assert!(harness.app.exit_requested);
+    assert!(harness.app.gfx.is_none());
+    assert_eq!(harness.app.pointers.position(::android_rust_example_apk_common::pointer::MOUSE_POINTER_ID), Some(PhysicalPosition::new(1.0, 2.0)));
+}
+
+#[test]
+fn replayed_back_key_pops_then_exits() {
+    let mut harness = harness();
+    harness.app.push_screen(Screen::Details);
+    let back = |ms, state| TimedEvent {
+        at: Duration::from_millis(ms),
+        event: RecordedEvent::Keyboard { physical_key: PhysicalKey::Unidentified(NativeKeyCode::Android(ANDROID_KEYCODE_BACK)), state, repeat: false },
+    };
+    record::replay(&mut harness.app, &[back(0, ElementState::Pressed), back(50, ElementState::Released)]);
+    assert_eq!(harness.app.navigation.current(), &Screen::Home);
+    assert!(!harness.app.exit_requested);
+    record::replay(&mut harness.app, &[back(0, ElementState::Pressed), back(50, ElementState::Released)]);
+    assert!(harness.app.exit_requested);
+}
+
+#[test]
+fn losing_focus_ends_a_camera_drag() {
+    let mut harness = harness();
+    harness.mouse_move(100.0, 100.0).mouse_button(MouseButton::Left, ElementState::Pressed).mouse_move(150.0, 100.0);
+    let dragged = harness.app.camera;
+    assert_ne!(dragged, harness.app.camera_home);
+    // the release goes to whichever window has the focus now
+    harness.event(WindowEvent::Focused(false)).mouse_move(200.0, 100.0);
+    assert_eq!(harness.app.camera, dragged);
+}
+
+#[test]
+fn lost_device_is_replaced_once_the_retry_is_due() {
+    let mut harness = harness();
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    gpu.device.destroy();
+    gpu.device.poll(::wgpu::Maintain::Wait);
+    assert!(gpu.is_lost());
+    harness.app.gpu = Some(gpu);
+
+    // a recovery that found no device waits before asking again
+    let now = harness.now();
+    harness.app.device_retry_at = Some(now + Duration::from_millis(100));
+    harness.app.recover_device(now);
+    assert_eq!(harness.app.gpu.as_ref().map(|gpu| gpu.generation), Some(0));
+
+    harness.advance(Duration::from_millis(100));
+    let now = harness.now();
+    harness.app.recover_device(now);
+    let gpu = harness.app.gpu.as_ref().unwrap();
+    assert_eq!(gpu.generation, 1);
+    assert!(!gpu.is_lost());
+    assert_eq!(harness.app.device_retry_at, None);
+}
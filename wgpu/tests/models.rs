@@ -0,0 +1,234 @@
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::camera::{OrbitCamera, Projection};
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::model::{load_model, resolve_uri, AlphaMode, Model, ModelCamera, ModelSampler};
+use ::android_rust_example_apk_wgpu::offscreen::OffscreenTarget;
+use ::android_rust_example_apk_wgpu::pbr::{is_mirrored, normal_matrix};
+use ::android_rust_example_apk_wgpu::renderer::{multiply, rotation_z, scale, translation, Matrix, Renderer};
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+
+mod common;
+
+fn assert_near<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+    assert!(actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-4), "{:?} != {:?}", actual, expected);
+}
+
+fn transform(matrix: &Matrix, [x, y, z]: [f32; 3], w: f32) -> [f32; 4] {
+    ::std::array::from_fn(|row| matrix[0][row] * x + matrix[1][row] * y + matrix[2][row] * z + matrix[3][row] * w)
+}
+
+fn triangle_positions() -> Vec<u8> {
+    [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].iter().flatten().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+/// One triangle without normals or indices, under a translated parent node.
+/// `Some("")` leaves out the buffer URI, for the binary chunk of a GLB.
+fn triangle_json(buffer_uri: Option<&str>) -> String {
+    let uri = buffer_uri.map(str::to_string).unwrap_or_else(|| {
+        format!("data:application/octet-stream;base64,{}", ::base64::Engine::encode(&::base64::engine::general_purpose::STANDARD, triangle_positions()))
+    });
+    let buffer = match buffer_uri {
+        Some("") => r#"{"byteLength": 36}"#.to_string(),
+        _ => format!(r#"{{"byteLength": 36, "uri": "{}"}}"#, uri),
+    };
+    format!(r#"{{
+        "asset": {{"version": "2.0"}},
+        "scenes": [{{"nodes": [0]}}],
+        "nodes": [
+            {{"name": "parent", "translation": [1, 0, 0], "children": [1, 2]}},
+            {{"name": "child", "mesh": 0, "scale": [2, 2, 2]}},
+            {{"name": "eye", "camera": 0, "translation": [0, 0, 5]}}
+        ],
+        "cameras": [{{"type": "orthographic", "orthographic": {{"xmag": 1, "ymag": 1, "znear": 0.5, "zfar": 10}}}}],
+        "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}],
+        "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}}],
+        "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+        "buffers": [{}]
+    }}"#, buffer)
+}
+
+fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut bin = bin.to_vec();
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut out = vec![];
+    out.extend_from_slice(b"glTF");
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&(length as u32).to_le_bytes());
+    out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"JSON");
+    out.extend_from_slice(&json);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"BIN\0");
+    out.extend_from_slice(&bin);
+    out
+}
+
+fn check_triangle(model: &Model) {
+    let primitive = &model.meshes[0].primitives[0];
+    assert_eq!((primitive.indices.clone(), primitive.material), (vec![0, 1, 2], None));
+    // no normals in the file, so flat ones facing the counterclockwise side
+    assert!(primitive.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0] && vertex.color == [1.0; 4]));
+
+    let world: Vec<_> = model.world_transforms().into_iter().map(|(node, world)| (node, [world[0][0], world[3][0]])).collect();
+    assert_eq!(world, vec![(0, [1.0, 1.0]), (1, [2.0, 1.0]), (2, [1.0, 1.0])]);
+    assert_eq!(model.bounds(), Some(([1.0, 0.0, 0.0], [3.0, 2.0, 0.0])));
+    assert_eq!(model.cameras, vec![ModelCamera { projection: Projection::Orthographic { height: 2.0 }, near: 0.5, far: 10.0 }]);
+}
+
+#[test]
+fn uris_resolve_relative_to_the_model() {
+    assert_eq!(resolve_uri("models/demo.gltf", "demo.bin"), "models/demo.bin");
+    assert_eq!(resolve_uri("models/demo.gltf", "../textures/bricks.png"), "textures/bricks.png");
+    assert_eq!(resolve_uri("a/b/c.gltf", "./x%20y.png"), "a/b/x y.png");
+    assert_eq!(resolve_uri("scene.glb", "textures/a.png"), "textures/a.png");
+}
+
+#[test]
+fn embedded_buffers_and_hierarchy() {
+    let model = Model::from_slice(triangle_json(None).as_bytes(), "inline.gltf", &Assets::source_tree()).expect("valid glTF");
+    check_triangle(&model);
+}
+
+#[test]
+fn binary_gltf_uses_its_chunk() {
+    let model = Model::from_slice(&glb(&triangle_json(Some("")), &triangle_positions()), "inline.glb", &Assets::source_tree()).expect("valid GLB");
+    check_triangle(&model);
+}
+
+#[test]
+fn malformed_primitives_are_skipped() {
+    // three positions, then the indices [0, 1, 7] and two normals for them
+    let mut bin = triangle_positions();
+    bin.extend([0u16, 1, 7, 0].iter().flat_map(|i| i.to_le_bytes()));
+    bin.extend([[0.0f32, 0.0, 1.0]; 2].iter().flatten().flat_map(|c| c.to_le_bytes()));
+    let json = format!(r#"{{
+        "asset": {{"version": "2.0"}},
+        "scenes": [{{"nodes": [0, 1]}}],
+        "nodes": [{{"mesh": 0}}, {{"mesh": 1}}],
+        "meshes": [
+            {{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}},
+            {{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 2}}}}]}}
+        ],
+        "accessors": [
+            {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+            {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}},
+            {{"bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC3"}}
+        ],
+        "bufferViews": [
+            {{"buffer": 0, "byteLength": 36}},
+            {{"buffer": 0, "byteOffset": 36, "byteLength": 6}},
+            {{"buffer": 0, "byteOffset": 44, "byteLength": 24}}
+        ],
+        "buffers": [{{"byteLength": {}}}]
+    }}"#, bin.len());
+    let model = Model::from_slice(&glb(&json, &bin), "malformed.glb", &Assets::source_tree()).expect("structurally valid GLB");
+    assert!(model.meshes.iter().all(|mesh| mesh.primitives.is_empty()));
+    assert_eq!(model.bounds(), None);
+}
+
+#[test]
+fn textures_keep_their_sampler_and_coordinate_set() {
+    let mut bin = triangle_positions();
+    bin.extend([[0.0f32, 0.0]; 3].iter().flatten().flat_map(|c| c.to_le_bytes()));
+    bin.extend([[0.5f32, 0.25]; 3].iter().flatten().flat_map(|c| c.to_le_bytes()));
+    let json = format!(r#"{{
+        "asset": {{"version": "2.0"}},
+        "scenes": [{{"nodes": [0]}}],
+        "nodes": [{{"mesh": 0}}],
+        "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2}}, "material": 0}}]}}],
+        "materials": [{{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0, "texCoord": 1}}}}}}],
+        "textures": [{{"source": 0, "sampler": 0}}],
+        "samplers": [{{"magFilter": 9728, "minFilter": 9729, "wrapS": 33071, "wrapT": 33648}}],
+        "images": [{{"uri": "data:image/png;base64,AAAA"}}],
+        "accessors": [
+            {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+            {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+            {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"}}
+        ],
+        "bufferViews": [
+            {{"buffer": 0, "byteLength": 36}},
+            {{"buffer": 0, "byteOffset": 36, "byteLength": 24}},
+            {{"buffer": 0, "byteOffset": 60, "byteLength": 24}}
+        ],
+        "buffers": [{{"byteLength": {}}}]
+    }}"#, bin.len());
+    let model = Model::from_slice(&glb(&json, &bin), "sampled.glb", &Assets::source_tree()).expect("valid GLB");
+    let texture = model.materials[0].base_color_texture.expect("base color texture");
+    assert_eq!((texture.image, texture.tex_coord), (0, 1));
+    assert_eq!(texture.sampler, ModelSampler {
+        address_mode_u: ::wgpu::AddressMode::ClampToEdge,
+        address_mode_v: ::wgpu::AddressMode::MirrorRepeat,
+        mag_filter: ::wgpu::FilterMode::Nearest,
+        min_filter: ::wgpu::FilterMode::Linear,
+        mipmap_filter: None,
+    });
+    assert_eq!(texture.sampler.descriptor().lod_max_clamp, 0.0);
+    assert!(model.meshes[0].primitives[0].vertices.iter().all(|vertex| vertex.uv == [0.5, 0.25]));
+}
+
+#[test]
+fn missing_files_are_reported() {
+    let json = triangle_json(Some("missing.bin"));
+    assert!(Model::from_slice(json.as_bytes(), "models/inline.gltf", &Assets::source_tree()).is_err());
+}
+
+#[test]
+fn demo_model_loads_from_the_assets() {
+    let model = load_model(&Assets::source_tree(), "models/demo.gltf").expect("demo model in the assets folder");
+    assert_eq!((model.meshes.len(), model.materials.len(), model.cameras.len()), (3, 3, 1));
+    assert_eq!(model.images[0].name, "textures/bricks.png");
+    assert_eq!(model.materials[0].base_color_texture.map(|texture| (texture.image, texture.sampler)), Some((0, ModelSampler::default())));
+    assert_eq!(model.materials[1].metallic, 1.0);
+    assert_eq!((model.materials[2].alpha_mode, model.materials[2].emissive), (AlphaMode::Opaque, [0.1, 0.25, 0.8]));
+    let names: Vec<_> = model.world_transforms().into_iter().map(|(node, _)| model.nodes[node].name.clone().unwrap_or_default()).collect();
+    assert_eq!(names, vec!["demo", "box", "ball", "moon", "camera"]);
+    let (min, max) = model.bounds().expect("meshes");
+    assert!(min[0] < -1.0 && max[0] > 1.0 && max[1] > 1.2, "{:?} {:?}", min, max);
+}
+
+#[test]
+fn model_cameras_look_down_their_negative_z() {
+    let camera = ModelCamera { projection: Projection::Perspective { fov_y: ::std::f32::consts::FRAC_PI_2 }, near: 1.0, far: 10.0 };
+    let view_proj = camera.view_proj(&translation(0.0, 0.0, 5.0), 2.0);
+    let [x, y, z, w] = transform(&view_proj, [0.0, 0.0, 0.0], 1.0);
+    assert_near([x / w, y / w], [0.0, 0.0]);
+    assert!(z / w > 0.0 && z / w < 1.0);
+    // 90 degrees vertically, and twice as wide at an aspect of 2
+    let [x, y, _, w] = transform(&view_proj, [10.0, 5.0, 0.0], 1.0);
+    assert_near([x / w, y / w], [1.0, 1.0]);
+}
+
+#[test]
+fn normals_follow_non_uniform_scale_and_mirroring() {
+    let [x, y, z, _] = transform(&normal_matrix(&scale(2.0, 1.0, 1.0)), [1.0, 1.0, 0.0], 0.0);
+    assert_near([x / y, z], [0.5, 0.0]);
+    let [x, y, z, _] = transform(&normal_matrix(&scale(-1.0, 1.0, 1.0)), [1.0, 0.0, 0.0], 0.0);
+    assert_near([x, y, z], [-1.0, 0.0, 0.0]);
+}
+
+#[test]
+fn mirrored_nodes_keep_their_front_faces() {
+    assert!(is_mirrored(&scale(-1.0, 1.0, 1.0)));
+    assert!(!is_mirrored(&scale(-1.0, -1.0, 1.0)));
+    assert!(!is_mirrored(&multiply(&translation(1.0, 2.0, 3.0), &rotation_z(2.0))));
+
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let model = Model::from_slice(triangle_json(None).as_bytes(), "inline.gltf", &Assets::source_tree()).expect("valid glTF");
+    let mut resources = ResourceRegistry::new();
+    let mut renderer = Renderer::new();
+    renderer.clear_color = ::wgpu::Color::BLACK;
+    let id = renderer.models.add(&gpu, &mut resources, &model);
+    // single sided and mirrored, still facing the camera on +z
+    renderer.models.model_mut(id).transform = scale(-1.0, 1.0, 1.0);
+    let mut camera = OrbitCamera::new((32, 32));
+    camera.frame([-3.0, 0.0, 0.0], [-1.0, 2.0, 0.0]);
+    renderer.view_proj = camera.view_proj();
+    renderer.eye = camera.eye();
+    let pixels = OffscreenTarget::new(&gpu, (32, 32)).render(&gpu, &mut renderer, &resources);
+    assert!(pixels.chunks(4).any(|pixel| pixel[..3] != [0, 0, 0]), "the mirrored triangle was culled");
+}
@@ -0,0 +1,27 @@
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::offscreen::{padded_bytes_per_row, OffscreenTarget};
+use ::android_rust_example_apk_wgpu::renderer::Renderer;
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+
+mod common;
+
+#[test]
+fn rows_are_padded_to_copy_alignment() {
+    assert_eq!(padded_bytes_per_row(64), 256);
+    assert_eq!(padded_bytes_per_row(65), 512);
+    assert_eq!(padded_bytes_per_row(1), 256);
+}
+
+#[test]
+fn clear_color_reads_back() {
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let mut renderer = Renderer::new();
+    renderer.clear_color = ::wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+    // width not a multiple of the row alignment, so padding has to be stripped
+    let target = OffscreenTarget::new(&gpu, (70, 3));
+    let pixels = target.render(&gpu, &mut renderer, &ResourceRegistry::new());
+    assert_eq!(pixels.len(), 70 * 3 * 4);
+    assert!(pixels.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+}
@@ -0,0 +1,86 @@
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_wgpu::pacing::{FixedTimestep, FramePacer, RunMode};
+use ::android_rust_example_apk_wgpu::Animation;
+
+const STEP: Duration = Duration::from_millis(10);
+
+#[test]
+fn timestep_counts_whole_steps_and_keeps_the_rest() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(STEP);
+    assert_eq!(timestep.advance(start).count, 0, "the first frame only starts the clock");
+
+    let steps = timestep.advance(start + Duration::from_millis(25));
+    assert_eq!(steps.count, 2);
+    assert!((steps.alpha - 0.5).abs() < 1e-4);
+
+    // the leftover 5ms count towards the next step
+    let steps = timestep.advance(start + Duration::from_millis(30));
+    assert_eq!(steps.count, 1);
+    assert!(steps.alpha.abs() < 1e-4);
+}
+
+#[test]
+fn timestep_drops_time_after_a_long_pause() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(STEP);
+    timestep.advance(start);
+    let steps = timestep.advance(start + Duration::from_secs(5));
+    assert_eq!(steps.count, timestep.max_steps);
+    assert_eq!(steps.alpha, 0.0);
+    assert_eq!(timestep.advance(start + Duration::from_secs(5) + STEP).count, 1);
+}
+
+#[test]
+fn timestep_reset_skips_the_gap() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::from_rate(100);
+    assert_eq!(timestep.step, STEP);
+    timestep.advance(start);
+    timestep.reset();
+    assert_eq!(timestep.advance(start + Duration::from_secs(1)).count, 0);
+    assert_eq!(timestep.advance(start + Duration::from_secs(1) + STEP).count, 1);
+}
+
+#[test]
+fn fixed_rate_keeps_its_cadence() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(RunMode::FixedRate, 50);
+    let interval = Duration::from_millis(20);
+    assert_eq!(pacer.next_frame_at(), None);
+
+    pacer.frame_drawn(start);
+    assert_eq!(pacer.next_frame_at(), Some(start + interval));
+    // woken up a little late, the frame after stays on the grid
+    pacer.frame_drawn(start + interval + Duration::from_millis(3));
+    assert_eq!(pacer.next_frame_at(), Some(start + interval * 2));
+    // far behind, start over instead of drawing frames back to back
+    let late = start + Duration::from_secs(1);
+    pacer.frame_drawn(late);
+    assert_eq!(pacer.next_frame_at(), Some(late + interval));
+    assert!(!pacer.redraw_after_present());
+}
+
+#[test]
+fn other_modes_do_not_schedule_frames() {
+    let now = Instant::now();
+    let mut continuous = FramePacer::new(RunMode::Continuous, 60);
+    continuous.frame_drawn(now);
+    assert_eq!(continuous.next_frame_at(), None);
+    assert!(continuous.redraw_after_present());
+
+    let mut on_demand = FramePacer::new(RunMode::OnDemand, 60);
+    on_demand.frame_drawn(now);
+    assert_eq!(on_demand.next_frame_at(), None);
+    assert!(!on_demand.redraw_after_present());
+}
+
+#[test]
+fn animation_interpolates_across_wraparound() {
+    let mut animation = Animation { previous_angle: 0.0, angle: ::std::f32::consts::TAU - 0.1 };
+    animation.update(Duration::from_millis(200));
+    assert!(animation.angle < 0.2);
+    let halfway = animation.interpolated(0.5);
+    assert!((halfway - (animation.angle - 0.1)).abs() < 1e-4, "{}", halfway);
+}
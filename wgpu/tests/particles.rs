@@ -0,0 +1,64 @@
+use ::android_rust_example_apk_wgpu::compute::workgroups;
+use ::android_rust_example_apk_wgpu::particles::{max_particles, step, Particle, SimParams};
+
+fn params() -> SimParams {
+    SimParams { attractor: None, bounds: [100.0, 50.0], dt: 0.1, attraction: 1000.0, drag: 0.0, max_speed: 1000.0 }
+}
+
+#[test]
+fn dispatch_covers_every_particle() {
+    assert_eq!(workgroups(0, 64, 65535), [0, 1, 1]);
+    assert_eq!(workgroups(64, 64, 65535), [1, 1, 1]);
+    assert_eq!(workgroups(65, 64, 65535), [2, 1, 1]);
+    // past the per-dimension limit the workgroups spill over into rows
+    let [x, y, z] = workgroups(64 * 70_000, 64, 65535);
+    assert_eq!((y, z), (2, 1));
+    assert!(x <= 65535 && x * y * 64 >= 64 * 70_000);
+    assert_eq!(workgroups(10 * 64, 64, 4), [4, 3, 1]);
+}
+
+#[test]
+fn particles_are_pulled_towards_the_attractor() {
+    let resting = Particle { position: [20.0, 25.0], velocity: [0.0, 0.0] };
+    assert_eq!(resting.step(&params()), resting);
+
+    let params = SimParams { attractor: Some([80.0, 25.0]), ..params() };
+    let moved = resting.step(&params);
+    assert!(moved.velocity[0] > 0.0 && moved.velocity[1] == 0.0, "{:?}", moved);
+    assert!(moved.position[0] > resting.position[0]);
+    // the pull weakens with distance
+    let closer = Particle { position: [70.0, 25.0], ..resting }.step(&params);
+    assert!(closer.velocity[0] > moved.velocity[0]);
+}
+
+#[test]
+fn drag_and_max_speed_slow_particles_down() {
+    let fast = Particle { position: [50.0, 25.0], velocity: [3000.0, 4000.0] };
+    let capped = fast.step(&SimParams { dt: 0.0, ..params() });
+    assert!((capped.velocity[0] - 600.0).abs() < 1e-3 && (capped.velocity[1] - 800.0).abs() < 1e-3, "{:?}", capped);
+
+    let slow = Particle { position: [50.0, 25.0], velocity: [10.0, 0.0] };
+    let dragged = slow.step(&SimParams { drag: 1.0, ..params() });
+    assert!((dragged.velocity[0] - 10.0 / 1.1).abs() < 1e-4);
+}
+
+#[test]
+fn particles_bounce_off_the_edges() {
+    let mut particles = [
+        Particle { position: [95.0, 25.0], velocity: [100.0, 0.0] },
+        Particle { position: [50.0, 2.0], velocity: [0.0, -50.0] },
+    ];
+    step(&mut particles, &params());
+    assert_eq!(particles[0], Particle { position: [95.0, 25.0], velocity: [-100.0, 0.0] });
+    assert_eq!(particles[1], Particle { position: [50.0, 3.0], velocity: [0.0, 50.0] });
+}
+
+#[test]
+fn particle_count_fits_the_buffer_limits() {
+    let limits = ::wgpu::Limits::downlevel_defaults();
+    // 16 bytes each, in at most 128 MiB of storage buffer binding or 256 MiB of buffer
+    assert_eq!(max_particles(&limits, true), 8 << 20);
+    assert_eq!(max_particles(&limits, false), 16 << 20);
+    let limits = ::wgpu::Limits { max_storage_buffer_binding_size: 1600, ..limits };
+    assert_eq!(max_particles(&limits, true), 100);
+}
@@ -0,0 +1,42 @@
+use ::std::cell::Cell;
+
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+
+/// Stands in for a GPU context, resources remember which device made them.
+struct FakeDevice {
+    generation: u64,
+    created: Cell<usize>,
+}
+
+impl FakeDevice {
+    fn new(generation: u64) -> Self {
+        Self { generation, created: Cell::new(0) }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct FakeBuffer {
+    generation: u64,
+    contents: Vec<u8>,
+}
+
+#[test]
+fn rebuild_recreates_from_recipes() {
+    let device = FakeDevice::new(0);
+    let mut registry = ResourceRegistry::new();
+    let contents = vec![1, 2, 3];
+    let buffer = registry.register(&device, "vertices", move |device: &FakeDevice| {
+        device.created.set(device.created.get() + 1);
+        FakeBuffer { generation: device.generation, contents: contents.clone() }
+    });
+    let label = registry.register(&device, "label", |device: &FakeDevice| format!("pipeline {}", device.generation));
+    assert_eq!(registry.get(buffer).generation, 0);
+    assert_eq!(registry.len(), 2);
+
+    let replacement = FakeDevice::new(1);
+    registry.rebuild(&replacement);
+    assert_eq!(registry.get(buffer), &FakeBuffer { generation: 1, contents: vec![1, 2, 3] });
+    assert_eq!(registry.get(label), "pipeline 1");
+    assert_eq!(device.created.get(), 1);
+    assert_eq!(replacement.created.get(), 1);
+}
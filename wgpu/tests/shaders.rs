@@ -0,0 +1,24 @@
+use ::std::path::Path;
+
+use ::wgpu::naga;
+
+/// Parse and validate every WGSL file under `src/shaders` without a GPU.
+#[test]
+fn shaders_validate() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders");
+    let mut checked = 0;
+    for entry in ::std::fs::read_dir(&dir).expect("shaders directory") {
+        let path = entry.expect("shader entry").path();
+        if path.extension().is_none_or(|extension| extension != "wgsl") {
+            continue;
+        }
+        let source = ::std::fs::read_to_string(&path).expect("readable shader");
+        let module = naga::front::wgsl::parse_str(&source)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e.emit_to_string(&source)));
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e.emit_to_string(&source)));
+        checked += 1;
+    }
+    assert!(checked > 0, "no shaders found in {}", dir.display());
+}
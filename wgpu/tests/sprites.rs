@@ -0,0 +1,99 @@
+use ::android_rust_example_apk_wgpu::camera::OrthoCamera;
+use ::android_rust_example_apk_wgpu::renderer::Matrix;
+use ::android_rust_example_apk_wgpu::resources::Handle;
+use ::android_rust_example_apk_wgpu::sprites::{batch, Sprite, TextureAtlas};
+use ::android_rust_example_apk_wgpu::texture::SamplerPreset;
+use ::winit::dpi::PhysicalPosition;
+
+fn clip(matrix: &Matrix, [x, y]: [f32; 2]) -> [f32; 2] {
+    [
+        matrix[0][0] * x + matrix[1][0] * y + matrix[3][0],
+        matrix[0][1] * x + matrix[1][1] * y + matrix[3][1],
+    ]
+}
+
+fn assert_near(actual: [f32; 2], expected: [f32; 2]) {
+    assert!((actual[0] - expected[0]).abs() < 1e-4 && (actual[1] - expected[1]).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+}
+
+#[test]
+fn pixel_camera_maps_corners_to_clip_space() {
+    let camera = OrthoCamera::new((800, 600));
+    let view_proj = camera.view_proj();
+    assert_near(clip(&view_proj, [0.0, 0.0]), [-1.0, 1.0]);
+    assert_near(clip(&view_proj, [800.0, 600.0]), [1.0, -1.0]);
+    assert_near(clip(&view_proj, [400.0, 300.0]), [0.0, 0.0]);
+}
+
+#[test]
+fn cursor_maps_to_world_and_back() {
+    let camera = OrthoCamera { position: [100.0, -50.0], zoom: 2.0, viewport: (800, 600) };
+    let world = camera.screen_to_world(PhysicalPosition::new(200.0, 300.0));
+    assert_near(world, [200.0, 100.0]);
+    let screen = camera.world_to_screen(world);
+    assert_eq!((screen.x, screen.y), (200.0, 300.0));
+    // and the world position is drawn under the cursor
+    assert_near(clip(&camera.view_proj(), world), [-0.5, 0.0]);
+}
+
+#[test]
+fn anchor_and_rotation_place_the_corners() {
+    let sprite = Sprite { anchor: [0.0, 0.0], ..Sprite::new([10.0, 20.0], [4.0, 2.0]) };
+    let instance = sprite.instance();
+    assert_eq!((instance.origin, instance.axis_x, instance.axis_y), ([10.0, 20.0], [4.0, 0.0], [0.0, 2.0]));
+
+    // a quarter turn around the center, clockwise on screen
+    let sprite = Sprite { rotation: ::std::f32::consts::FRAC_PI_2, ..Sprite::new([0.0, 0.0], [4.0, 2.0]) };
+    let instance = sprite.instance();
+    assert_near(instance.axis_x, [0.0, 4.0]);
+    assert_near(instance.axis_y, [-2.0, 0.0]);
+    assert_near(instance.origin, [1.0, -2.0]);
+}
+
+#[test]
+fn untextured_sprites_sort_by_z_in_one_batch() {
+    let red = Sprite { color: [1.0, 0.0, 0.0, 1.0], z: 1.0, ..Sprite::new([0.0, 0.0], [1.0, 1.0]) };
+    let green = Sprite { color: [0.0, 1.0, 0.0, 1.0], ..red };
+    let blue = Sprite { color: [0.0, 0.0, 1.0, 1.0], z: -1.0, ..red };
+    let hidden = Sprite { visible: false, ..red };
+    let (instances, batches) = batch(&[red, hidden, green, blue]);
+    let colors: Vec<_> = instances.iter().map(|instance| instance.color).collect();
+    assert_eq!(colors, vec![blue.color, red.color, green.color]);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].instances, 0..3);
+}
+
+#[test]
+fn batches_break_on_texture_and_sampler_changes() {
+    let first = Handle::from_index(0);
+    let second = Handle::from_index(1);
+    let sprite = |texture, z| Sprite { texture: Some(texture), z, ..Sprite::new([0.0, 0.0], [1.0, 1.0]) };
+    let sprites = [
+        sprite(first, 0.0),
+        sprite(second, 1.0),
+        sprite(first, 0.0),
+        Sprite { sampler: SamplerPreset::Linear, ..sprite(first, 0.0) },
+        sprite(second, 2.0),
+    ];
+    let (instances, batches) = batch(&sprites);
+    assert_eq!(instances.len(), 5);
+    let summary: Vec<_> = batches.iter().map(|batch| (batch.texture, batch.sampler, batch.instances.clone())).collect();
+    assert_eq!(summary, vec![
+        (Some(first), SamplerPreset::Pixel, 0..2),
+        (Some(first), SamplerPreset::Linear, 2..3),
+        (Some(second), SamplerPreset::Pixel, 3..5),
+    ]);
+}
+
+#[test]
+fn atlas_regions_become_texture_coordinates() {
+    let texture = Handle::from_index(0);
+    let mut atlas = TextureAtlas::new(texture, (64, 32), SamplerPreset::Pixel);
+    assert_eq!(atlas.insert("button", [16, 8, 32, 16]).uv, [0.25, 0.25, 0.75, 0.75]);
+    assert_eq!(atlas.insert_grid("tiles", (16, 16)), 8);
+    assert_eq!(atlas.region("tiles/5").map(|region| region.uv), Some([0.25, 0.5, 0.5, 1.0]));
+    assert_eq!(atlas.region("tiles/8"), None);
+
+    let sprite = atlas.sprite("button", [5.0, 5.0]).expect("button region");
+    assert_eq!((sprite.size, sprite.texture), ([32.0, 16.0], Some(texture)));
+}
@@ -0,0 +1,117 @@
+use ::std::collections::VecDeque;
+use ::std::time::{Duration, Instant};
+
+use ::android_rust_example_apk_wgpu::frame::{Acquired, FrameSource, GpuEvent, SurfaceRecovery};
+use ::wgpu::SurfaceError;
+
+/// Hands out scripted results and counts recovery calls.
+#[derive(Default)]
+struct FakeSurface {
+    results: VecDeque<Result<u32, SurfaceError>>,
+    reconfigures: usize,
+    recreates: usize,
+    recreate_fails: bool,
+}
+
+impl FakeSurface {
+    fn with(results: Vec<Result<u32, SurfaceError>>) -> Self {
+        Self { results: results.into(), ..Default::default() }
+    }
+}
+
+impl FrameSource for FakeSurface {
+    type Frame = u32;
+
+    fn acquire(&mut self) -> Result<u32, SurfaceError> {
+        self.results.pop_front().expect("test acquired more frames than scripted")
+    }
+
+    fn reconfigure(&mut self) {
+        self.reconfigures += 1;
+    }
+
+    fn recreate(&mut self) -> bool {
+        self.recreates += 1;
+        !self.recreate_fails
+    }
+}
+
+#[test]
+fn outdated_reconfigures_and_retries() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::Outdated), Ok(7)]);
+    let mut events = vec![];
+    let acquired = SurfaceRecovery::default().acquire(&mut surface, Instant::now(), &mut events);
+    assert_eq!(acquired, Acquired::Frame(7));
+    assert_eq!(surface.reconfigures, 1);
+    assert!(events.is_empty());
+}
+
+#[test]
+fn outdated_twice_waits_for_next_frame() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::Outdated), Err(SurfaceError::Outdated)]);
+    let acquired = SurfaceRecovery::default().acquire(&mut surface, Instant::now(), &mut vec![]);
+    assert_eq!(acquired, Acquired::RetryNextFrame);
+    assert_eq!(surface.reconfigures, 1);
+}
+
+#[test]
+fn lost_recreates_surface_and_notifies() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::Lost), Ok(1)]);
+    let mut events = vec![];
+    let acquired = SurfaceRecovery::default().acquire(&mut surface, Instant::now(), &mut events);
+    assert_eq!(acquired, Acquired::Frame(1));
+    assert_eq!(surface.recreates, 1);
+    assert_eq!(events, vec![GpuEvent::SurfaceLost, GpuEvent::SurfaceRecreated]);
+}
+
+#[test]
+fn lost_without_new_surface_stops() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::Lost)]);
+    surface.recreate_fails = true;
+    let mut events = vec![];
+    let acquired = SurfaceRecovery::default().acquire(&mut surface, Instant::now(), &mut events);
+    assert_eq!(acquired, Acquired::Stop);
+    assert_eq!(events, vec![GpuEvent::SurfaceLost]);
+}
+
+#[test]
+fn timeouts_back_off_exponentially_and_reset() {
+    let mut surface = FakeSurface::with(vec![
+        Err(SurfaceError::Timeout),
+        Err(SurfaceError::Timeout),
+        Err(SurfaceError::Timeout),
+        Ok(3),
+        Err(SurfaceError::Timeout),
+    ]);
+    let mut recovery = SurfaceRecovery::default();
+    let now = Instant::now();
+    let base = recovery.timeout_backoff;
+    for expected in [base, base * 2, base * 4] {
+        assert_eq!(recovery.acquire(&mut surface, now, &mut vec![]), Acquired::RetryAt(now + expected));
+    }
+    assert_eq!(recovery.acquire(&mut surface, now, &mut vec![]), Acquired::Frame(3));
+    assert_eq!(recovery.consecutive_timeouts(), 0);
+    assert_eq!(recovery.acquire(&mut surface, now, &mut vec![]), Acquired::RetryAt(now + base));
+}
+
+#[test]
+fn timeout_backoff_is_capped() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::Timeout); 12]);
+    let mut recovery = SurfaceRecovery::default();
+    let now = Instant::now();
+    let mut last = Acquired::RetryNextFrame;
+    for _ in 0..12 {
+        last = recovery.acquire(&mut surface, now, &mut vec![]);
+    }
+    assert_eq!(last, Acquired::RetryAt(now + recovery.max_backoff));
+    assert!(recovery.max_backoff <= Duration::from_secs(1));
+}
+
+#[test]
+fn out_of_memory_reports_device_lost() {
+    let mut surface = FakeSurface::with(vec![Err(SurfaceError::OutOfMemory)]);
+    let mut events = vec![];
+    let acquired = SurfaceRecovery::default().acquire(&mut surface, Instant::now(), &mut events);
+    assert_eq!(acquired, Acquired::Stop);
+    assert_eq!(events, vec![GpuEvent::DeviceLost]);
+}
@@ -0,0 +1,85 @@
+use ::ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::text::{layout, load_font, ShelfPacker, TextRenderer, TextSection, DEFAULT_FONT};
+
+fn font() -> FontArc {
+    load_font(&Assets::source_tree(), DEFAULT_FONT).expect("font in the assets folder")
+}
+
+fn text(layout: &::android_rust_example_apk_wgpu::text::TextLayout) -> String {
+    layout.glyphs.iter().map(|glyph| glyph.character).collect()
+}
+
+#[test]
+fn pairs_are_kerned() {
+    let font = font();
+    let scaled = font.as_scaled(PxScale::from(32.0));
+    let (a, v) = (scaled.glyph_id('A'), scaled.glyph_id('V'));
+    assert!(scaled.kern(a, v) < 0.0, "the font kerns AV");
+
+    let laid_out = layout(&font, "AV", 32.0, None);
+    let v_position = laid_out.glyphs[1].position[0];
+    assert!((v_position - (scaled.h_advance(a) + scaled.kern(a, v))).abs() < 1e-3);
+    assert!(laid_out.size[0] < scaled.h_advance(a) + scaled.h_advance(v));
+}
+
+#[test]
+fn lines_break_at_newlines() {
+    let font = font();
+    let laid_out = layout(&font, "one\ntwo", 20.0, None);
+    assert_eq!((laid_out.lines, text(&laid_out)), (2, "onetwo".to_string()));
+    let (first, second) = (laid_out.glyphs[0].position, laid_out.glyphs[3].position);
+    assert_eq!(first[0], second[0]);
+    assert!(second[1] > first[1]);
+    assert!((laid_out.size[1] - 2.0 * (second[1] - first[1])).abs() < 1e-3);
+}
+
+#[test]
+fn long_lines_wrap_between_words() {
+    let font = font();
+    let single = layout(&font, "wrap these words", 20.0, None);
+    assert_eq!(single.lines, 1);
+
+    let width = layout(&font, "wrap these", 20.0, None).size[0] + 1.0;
+    let wrapped = layout(&font, "wrap these words", 20.0, Some(width));
+    assert_eq!(wrapped.lines, 2);
+    assert!(wrapped.size[0] <= width);
+    // "words" starts the second line
+    let words = wrapped.glyphs[9];
+    assert_eq!((words.character, words.position[0]), ('w', 0.0));
+    assert!(words.position[1] > wrapped.glyphs[0].position[1]);
+}
+
+#[test]
+fn words_wider_than_a_line_break_between_characters() {
+    let font = font();
+    let laid_out = layout(&font, "abcdefghij", 20.0, Some(30.0));
+    assert!(laid_out.lines > 2);
+    assert!(laid_out.size[0] <= 30.0);
+    assert_eq!(text(&laid_out), "abcdefghij");
+}
+
+#[test]
+fn packer_fills_rows_then_reports_full() {
+    let mut packer = ShelfPacker::new((10, 10));
+    assert_eq!(packer.allocate(4, 3), Some([0, 0]));
+    assert_eq!(packer.allocate(4, 5), Some([4, 0]));
+    // the row is full, the next one starts below its tallest entry
+    assert_eq!(packer.allocate(4, 2), Some([0, 5]));
+    assert_eq!(packer.allocate(11, 1), None);
+    assert_eq!(packer.allocate(4, 6), None);
+    packer.clear();
+    assert_eq!(packer.allocate(10, 10), Some([0, 0]));
+}
+
+#[test]
+fn measure_is_in_logical_pixels() {
+    let mut text = TextRenderer::new();
+    let section = TextSection::new("Hello", [0.0, 0.0], 16.0);
+    assert_eq!(text.measure(&section), None);
+    text.font = Some(font());
+    let logical = text.measure(&section).expect("font set");
+    text.set_scale_factor(2.0);
+    let scaled = text.measure(&section).expect("font set");
+    assert!((logical[0] - scaled[0]).abs() < 0.5 && (logical[1] - scaled[1]).abs() < 0.5, "{:?} {:?}", logical, scaled);
+}
@@ -0,0 +1,150 @@
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::offscreen::read_texture;
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+use ::android_rust_example_apk_wgpu::texture::{candidates, decode, decode_ktx2, ktx2_format, load_texture, upload, TextureData, TextureError, TextureOptions};
+use ::wgpu::{AstcBlock, AstcChannel, Features, TextureFormat};
+
+mod common;
+
+#[test]
+fn compressed_variants_come_first_when_supported() {
+    assert_eq!(candidates("textures/bricks", Features::empty()), vec![
+        "textures/bricks.ktx2", "textures/bricks.png", "textures/bricks.jpg", "textures/bricks.jpeg",
+    ]);
+    let phone = Features::TEXTURE_COMPRESSION_ASTC | Features::TEXTURE_COMPRESSION_ETC2;
+    assert_eq!(&candidates("bricks", phone)[..3], ["bricks.astc.ktx2", "bricks.etc2.ktx2", "bricks.ktx2"]);
+    assert_eq!(candidates("bricks", Features::TEXTURE_COMPRESSION_BC)[0], "bricks.bc.ktx2");
+    assert_eq!(candidates("photo.JPG", phone), vec!["photo.JPG"]);
+}
+
+#[test]
+fn ktx2_formats_map_to_wgpu() {
+    assert_eq!(ktx2_format(::ktx2::Format::R8G8B8A8_SRGB), Some(TextureFormat::Rgba8UnormSrgb));
+    assert_eq!(ktx2_format(::ktx2::Format::BC7_SRGB_BLOCK), Some(TextureFormat::Bc7RgbaUnormSrgb));
+    assert_eq!(ktx2_format(::ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK), Some(TextureFormat::Etc2Rgba8Unorm));
+    assert_eq!(
+        ktx2_format(::ktx2::Format::ASTC_6x6_SRGB_BLOCK),
+        Some(TextureFormat::Astc { block: AstcBlock::B6x6, channel: AstcChannel::UnormSrgb }),
+    );
+    assert_eq!(
+        ktx2_format(::ktx2::Format::ASTC_12x12_UNORM_BLOCK),
+        Some(TextureFormat::Astc { block: AstcBlock::B12x12, channel: AstcChannel::Unorm }),
+    );
+    assert_eq!(ktx2_format(::ktx2::Format::R16G16B16A16_SFLOAT), None);
+}
+
+/// An uncompressed RGBA8 KTX2 file holding `levels`, the largest first.
+fn ktx2(size: (u32, u32), levels: &[Vec<u8>]) -> Vec<u8> {
+    let level_index_end = ::ktx2::Header::LENGTH + levels.len() * 24;
+    // a data format descriptor is required, an empty one will do for the reader
+    let dfd_offset = level_index_end as u32;
+    let mut data_offset = (level_index_end + 4) as u64;
+    let mut bytes = ::ktx2::Header {
+        format: Some(::ktx2::Format::R8G8B8A8_SRGB),
+        type_size: 1,
+        pixel_width: size.0,
+        pixel_height: size.1,
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 1,
+        level_count: levels.len() as u32,
+        supercompression_scheme: None,
+        index: ::ktx2::Index {
+            dfd_byte_offset: dfd_offset,
+            dfd_byte_length: 4,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: 0,
+            sgd_byte_length: 0,
+        },
+    }.as_bytes().to_vec();
+    for level in levels {
+        let index = ::ktx2::LevelIndex {
+            byte_offset: data_offset,
+            byte_length: level.len() as u64,
+            uncompressed_byte_length: level.len() as u64,
+        };
+        bytes.extend_from_slice(&index.as_bytes());
+        data_offset += level.len() as u64;
+    }
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    for level in levels {
+        bytes.extend_from_slice(level);
+    }
+    bytes
+}
+
+#[test]
+fn ktx2_keeps_baked_mip_levels() {
+    let levels = vec![vec![10; 2 * 2 * 4], vec![20; 4]];
+    let data = decode_ktx2(&ktx2((2, 2), &levels)).expect("valid ktx2");
+    assert_eq!(data, TextureData { size: (2, 2), format: TextureFormat::Rgba8UnormSrgb, levels });
+    assert!(matches!(decode_ktx2(b"not a ktx2 file"), Err(TextureError::Ktx2(_))));
+}
+
+#[test]
+fn png_decodes_to_rgba8() {
+    let bytes = Assets::source_tree().read("textures/bricks.png").expect("bricks texture in the assets folder");
+    let data = decode("textures/bricks.png", &bytes, true).expect("valid png");
+    assert_eq!((data.size, data.format), ((64, 64), TextureFormat::Rgba8UnormSrgb));
+    assert_eq!(data.levels.len(), 1);
+    assert_eq!(data.levels[0].len(), 64 * 64 * 4);
+    assert_eq!(decode("bricks.png", &bytes, false).unwrap().format, TextureFormat::Rgba8Unorm);
+}
+
+#[test]
+fn mipmaps_are_generated_on_the_gpu() {
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let mut resources = ResourceRegistry::new();
+    let red = [255, 0, 0, 255];
+    let blue = [0, 0, 255, 255];
+    let data = TextureData {
+        size: (2, 2),
+        format: TextureFormat::Rgba8UnormSrgb,
+        levels: vec![[red, blue, blue, red].concat()],
+    };
+    let loaded = upload(&gpu, &mut resources, "checker", data, TextureOptions::default());
+    assert_eq!(loaded.mip_level_count, 2);
+
+    // copy the 1x1 level somewhere `read_texture` can reach
+    let texture = resources.get(loaded.texture);
+    let level = gpu.device.create_texture(&::wgpu::TextureDescriptor {
+        label: Some("level 1"),
+        size: ::wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: ::wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: ::wgpu::TextureUsages::COPY_DST | ::wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let mut encoder = gpu.device.create_command_encoder(&::wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_texture(
+        ::wgpu::TexelCopyTextureInfo { texture, mip_level: 1, origin: ::wgpu::Origin3d::ZERO, aspect: ::wgpu::TextureAspect::All },
+        level.as_image_copy(),
+        level.size(),
+    );
+    gpu.queue.submit(Some(encoder.finish()));
+
+    // averaged in linear space, so 50% comes out as 188 in sRGB
+    let pixel = read_texture(&gpu, &level);
+    for (channel, expected) in pixel.iter().zip([188, 0, 188, 255]) {
+        assert!(channel.abs_diff(expected) <= 2, "{:?}", pixel);
+    }
+}
+
+#[test]
+fn missing_texture_lists_what_was_tried() {
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let error = load_texture(&gpu, &mut ResourceRegistry::new(), &Assets::source_tree(), "textures/missing", TextureOptions::default())
+        .expect_err("no such texture");
+    match error {
+        TextureError::NotFound(tried) => assert!(tried.contains(&"textures/missing.png".to_string())),
+        e => panic!("unexpected error {}", e),
+    }
+}
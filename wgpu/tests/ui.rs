@@ -0,0 +1,115 @@
+use ::android_rust_example_apk_wgpu::assets::Assets;
+use ::android_rust_example_apk_wgpu::config::RendererConfig;
+use ::android_rust_example_apk_wgpu::offscreen::OffscreenTarget;
+use ::android_rust_example_apk_wgpu::renderer::Renderer;
+use ::android_rust_example_apk_wgpu::resources::ResourceRegistry;
+use ::android_rust_example_apk_wgpu::text::{load_font, TextSection, DEFAULT_FONT};
+use ::android_rust_example_apk_wgpu::ui::{Insets, UiFrame, UiPainter};
+
+mod common;
+
+const SIZE: (u32, u32) = (32, 32);
+
+/// One frame of `ctx` on a `SIZE` screen at a scale factor of 1.
+fn frame(ctx: &::egui::Context, build: impl FnMut(&::egui::Context)) -> UiFrame {
+    let input = ::egui::RawInput {
+        screen_rect: Some(::egui::Rect::from_min_size(::egui::Pos2::ZERO, ::egui::vec2(SIZE.0 as f32, SIZE.1 as f32))),
+        ..Default::default()
+    };
+    let output = ctx.run(input, build);
+    UiFrame {
+        textures_delta: output.textures_delta,
+        primitives: ctx.tessellate(output.shapes, output.pixels_per_point),
+        pixels_per_point: output.pixels_per_point,
+        repaint: false,
+    }
+}
+
+#[test]
+fn insets_are_what_lies_outside_the_content() {
+    let insets = Insets::outside((1080, 2400), [0, 96, 1080, 2280]);
+    assert_eq!(insets, Insets { left: 0.0, top: 96.0, right: 0.0, bottom: 120.0 });
+    // before the first layout the content rectangle is empty
+    assert_eq!(Insets::outside((1080, 2400), [0, 0, 0, 0]), Insets::default());
+
+    let rect = ::egui::Rect::from_min_max(::egui::pos2(0.0, 0.0), ::egui::pos2(540.0, 1200.0));
+    let shrunk = insets.shrink(rect, 2.0);
+    assert_eq!((shrunk.min, shrunk.max), (::egui::pos2(0.0, 48.0), ::egui::pos2(540.0, 1140.0)));
+}
+
+#[test]
+fn unpainted_texture_changes_are_kept() {
+    let ctx = ::egui::Context::default();
+    let mut painter = UiPainter::new();
+    // the first frame uploads the font atlas, the second has nothing new
+    let first = frame(&ctx, |ctx| {
+        ::egui::CentralPanel::default().show(ctx, |ui| ui.label("hello"));
+    });
+    assert!(!first.textures_delta.set.is_empty());
+    let uploads = first.textures_delta.set.len();
+    painter.set_frame(first);
+
+    let second = frame(&ctx, |ctx| {
+        ::egui::CentralPanel::default().show(ctx, |ui| ui.label("hello"));
+    });
+    assert!(second.textures_delta.set.is_empty());
+    painter.set_frame(second);
+
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let mut renderer = Renderer::new();
+    renderer.ui = painter;
+    let pixels = OffscreenTarget::new(&gpu, SIZE).render(&gpu, &mut renderer, &ResourceRegistry::new());
+    // the label needs the font atlas from the first frame
+    assert!(uploads > 0 && pixels.chunks(4).any(|pixel| pixel != &pixels[..4]), "nothing was painted");
+    assert!(!renderer.ui.take_textures_lost());
+}
+
+#[test]
+fn panels_are_painted_over_the_scene() {
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let ctx = ::egui::Context::default();
+    let mut renderer = Renderer::new();
+    renderer.clear_color = ::wgpu::Color::BLACK;
+    renderer.ui.set_frame(frame(&ctx, |ctx| {
+        ::egui::TopBottomPanel::top("bar")
+            .exact_height(8.0)
+            .frame(::egui::Frame::NONE.fill(::egui::Color32::from_rgb(255, 0, 0)))
+            .show(ctx, |_| ());
+    }));
+    let pixels = OffscreenTarget::new(&gpu, SIZE).render(&gpu, &mut renderer, &ResourceRegistry::new());
+    let pixel = |x: u32, y: u32| {
+        let i = ((y * SIZE.0 + x) * 4) as usize;
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    };
+    assert_eq!(pixel(16, 2), [255, 0, 0, 255]);
+    assert_eq!(pixel(16, 24), [0, 0, 0, 255]);
+}
+
+#[test]
+fn repeated_frames_keep_text_and_ui() {
+    let Some(gpu) = common::headless(RendererConfig::default()) else {
+        return;
+    };
+    let ctx = ::egui::Context::default();
+    let mut renderer = Renderer::new();
+    renderer.clear_color = ::wgpu::Color::BLACK;
+    renderer.text.font = Some(load_font(&Assets::source_tree(), DEFAULT_FONT).expect("font in the assets folder"));
+    renderer.text.queue(TextSection::new("Hi", [2.0, 16.0], 12.0));
+    renderer.ui.set_frame(frame(&ctx, |ctx| {
+        ::egui::TopBottomPanel::top("bar")
+            .exact_height(8.0)
+            .frame(::egui::Frame::NONE.fill(::egui::Color32::from_rgb(255, 0, 0)))
+            .show(ctx, |_| ());
+    }));
+    let target = OffscreenTarget::new(&gpu, SIZE);
+    let resources = ResourceRegistry::new();
+    let first = target.render(&gpu, &mut renderer, &resources);
+    renderer.repeat_frame();
+    assert_eq!(target.render(&gpu, &mut renderer, &resources), first);
+    // without it both are used up
+    assert_ne!(target.render(&gpu, &mut renderer, &resources), first);
+}